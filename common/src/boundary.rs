@@ -0,0 +1,130 @@
+//! The types that can be checked when they cross the security boundary.
+//!
+//! The other world can put any bit pattern in the registers of a call, so a value of a type that
+//! has invalid bit patterns has to be checked before it becomes that type.
+
+/// A type whose values can be passed as another type and checked when they're received.
+///
+/// The shims the macros generate for functions that don't use the C ABI receive an argument of a
+/// type they don't know as its `Raw` type, and reject the call if `from_raw` returns `None`.
+/// The bindings do the same with the return value.
+///
+/// Implement it for fieldless enums with [boundary_enum], and implement [AnyBitPattern] for
+/// `#[repr(C)]` structs of which every bit pattern is valid.
+///
+/// # Safety
+///
+/// `from_raw` must only return `Some` for a value that is a valid `Self`, and `Raw` must be passed
+/// in the same registers as `Self`.
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` can't be checked when it crosses the security boundary",
+    note = "use `boundary_enum!` for a fieldless enum or implement `AnyBitPattern` for a `#[repr(C)]` struct of integers"
+)]
+pub unsafe trait Boundary: Copy {
+    /// The type the value is passed as
+    type Raw: Copy;
+
+    /// Turns the value into the value that is passed
+    fn into_raw(self) -> Self::Raw;
+
+    /// Turns the value that was received back into the value, or returns `None` if it isn't valid
+    fn from_raw(raw: Self::Raw) -> Option<Self>;
+}
+
+/// A type of which every bit pattern is a valid value, so the other world can't give us an invalid
+/// value of it. Shared statics of types other than integers and atomic integers must implement it.
+///
+/// # Safety
+///
+/// The type must not have invalid bit patterns, which rules out `bool`, `char`, enums, references
+/// and the `NonZero` integers, and must not contain pointers, because the other world could make
+/// them point anywhere.
+#[diagnostic::on_unimplemented(
+    message = "the other world could give `{Self}` a bit pattern that isn't valid for it",
+    note = "implement the unsafe `AnyBitPattern` trait if every bit pattern is a valid `{Self}`"
+)]
+pub unsafe trait AnyBitPattern {}
+
+macro_rules! impl_any_bit_pattern {
+    ($($ty:ty),*) => {
+        $(unsafe impl AnyBitPattern for $ty {})*
+    };
+}
+
+impl_any_bit_pattern!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize, f32, f64);
+impl_any_bit_pattern!(
+    core::sync::atomic::AtomicU8,
+    core::sync::atomic::AtomicU16,
+    core::sync::atomic::AtomicU32,
+    core::sync::atomic::AtomicUsize,
+    core::sync::atomic::AtomicI8,
+    core::sync::atomic::AtomicI16,
+    core::sync::atomic::AtomicI32,
+    core::sync::atomic::AtomicIsize
+);
+
+unsafe impl<T: AnyBitPattern, const N: usize> AnyBitPattern for [T; N] {}
+
+unsafe impl<T: AnyBitPattern + Copy> Boundary for T {
+    type Raw = T;
+
+    fn into_raw(self) -> T {
+        self
+    }
+
+    fn from_raw(raw: T) -> Option<T> {
+        Some(raw)
+    }
+}
+
+/// Defines a fieldless enum that implements [Boundary], so it can be passed to and returned from
+/// the functions of the other world.
+///
+/// The enum is passed as the `u32` of its discriminant and a value that isn't the discriminant of
+/// one of the variants is rejected. It has to derive `Clone` and `Copy`.
+///
+/// ```
+/// trustzone_m_common::boundary_enum! {
+///     #[derive(Clone, Copy, Debug, PartialEq)]
+///     #[repr(u8)]
+///     pub enum Status {
+///         Ok,
+///         Busy,
+///         Failed = 10,
+///     }
+/// }
+///
+/// use trustzone_m_common::Boundary;
+/// assert_eq!(Status::Failed.into_raw(), 10);
+/// assert_eq!(Status::from_raw(1), Some(Status::Busy));
+/// assert_eq!(Status::from_raw(2), None);
+/// ```
+#[macro_export]
+macro_rules! boundary_enum {
+    (
+        $(#[$meta:meta])*
+        $vis:vis enum $name:ident {
+            $($(#[$variant_meta:meta])* $variant:ident $(= $discriminant:expr)?),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        $vis enum $name {
+            $($(#[$variant_meta])* $variant $(= $discriminant)?),*
+        }
+
+        unsafe impl $crate::Boundary for $name {
+            type Raw = u32;
+
+            fn into_raw(self) -> u32 {
+                self as u32
+            }
+
+            fn from_raw(raw: u32) -> Option<Self> {
+                match raw {
+                    $(raw if raw == $name::$variant as u32 => Some($name::$variant),)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
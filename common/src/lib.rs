@@ -0,0 +1,117 @@
+//! The items the code generated by the macros uses in both runtimes.
+//!
+//! Their output has to match what the bindings generator calculates byte for byte, so there is only
+//! one copy of them. The runtimes re-export them, because the apps only depend on a runtime.
+
+#![no_std]
+
+mod boundary;
+
+pub use boundary::{AnyBitPattern, Boundary};
+
+/// The id of the veneer of an exported item that doesn't have an explicit id.
+///
+/// This is the hash of the path of the item relative to the crate root, so items with the same name
+/// in different modules get different ids. Items in the crate root are hashed by their name only.
+/// The code the macros generate uses this and it must match what the bindings generator calculates.
+pub const fn veneer_id(module_path: &str, name: &str) -> u32 {
+    const CRC: crc::Crc<u32> = crc::Crc::<u32>::new(&crc::CRC_32_CKSUM);
+
+    let mut digest = CRC.digest();
+
+    // Strip the crate name from the module path
+    let module_path = module_path.as_bytes();
+    let mut i = 0;
+    while i + 1 < module_path.len() {
+        if module_path[i] == b':' && module_path[i + 1] == b':' {
+            digest.update(module_path.split_at(i + 2).1);
+            digest.update(b"::");
+            break;
+        }
+        i += 1;
+    }
+
+    digest.update(name.as_bytes());
+    digest.finalize()
+}
+
+/// The part of the checksum in the header of a veneer table that the veneer of an exported item
+/// adds, which is the low half of the CRC of its id and signature hash.
+///
+/// Every veneer puts a section of this size in the `.ns_veneers_checksum` or `.nsc_veneers_checksum`
+/// section, so the linker scripts can write the sum of the parts into the header. The secure runtime
+/// recomputes the sum from the table to notice a table that doesn't match the built veneers.
+pub const fn veneer_checksum(id: u32, signature_hash: u32) -> u32 {
+    const CRC: crc::Crc<u32> = crc::Crc::<u32>::new(&crc::CRC_32_CKSUM);
+
+    let mut digest = CRC.digest();
+    digest.update(&id.to_le_bytes());
+    digest.update(&signature_hash.to_le_bytes());
+    digest.finalize() & 0xFFFF
+}
+
+/// The length of the metadata record of an exported item with the given strings.
+/// See [veneer_metadata].
+pub const fn veneer_metadata_len(strings: &[&str]) -> usize {
+    // The length, the id and the signature hash
+    let mut len = 12;
+
+    let mut i = 0;
+    while i < strings.len() {
+        len += 4 + strings[i].len();
+        i += 1;
+    }
+
+    // Records are a whole number of words
+    (len + 3) & !3
+}
+
+/// The metadata record of an exported item, which the macros put in the `.trustzone_meta` section
+/// so the bindings generator can read the exported items from the built image.
+///
+/// A record is its length, the id and the signature hash, followed by the strings with their length
+/// in front of them. The numbers are little endian `u32`s.
+pub const fn veneer_metadata<const N: usize>(
+    id: u32,
+    signature_hash: u32,
+    strings: &[&str],
+) -> [u8; N] {
+    const fn write(record: &mut [u8], offset: usize, bytes: &[u8]) -> usize {
+        let mut i = 0;
+        while i < bytes.len() {
+            record[offset + i] = bytes[i];
+            i += 1;
+        }
+        offset + bytes.len()
+    }
+
+    assert!(N == veneer_metadata_len(strings));
+
+    let mut record = [0; N];
+    let mut offset = write(&mut record, 0, &(N as u32).to_le_bytes());
+    offset = write(&mut record, offset, &id.to_le_bytes());
+    offset = write(&mut record, offset, &signature_hash.to_le_bytes());
+
+    let mut i = 0;
+    while i < strings.len() {
+        offset = write(
+            &mut record,
+            offset,
+            &(strings[i].len() as u32).to_le_bytes(),
+        );
+        offset = write(&mut record, offset, strings[i].as_bytes());
+        i += 1;
+    }
+
+    record
+}
+
+/// The address of the entry function of an exported item in the simulation on the host, which the
+/// bindings of the other app call directly instead of through a veneer
+#[cfg(not(target_arch = "arm"))]
+#[repr(transparent)]
+pub struct SimulatedVeneer(pub *const ());
+
+// The entry function can be called from any thread
+#[cfg(not(target_arch = "arm"))]
+unsafe impl Sync for SimulatedVeneer {}
@@ -12,6 +12,7 @@ use embassy_nrf::{
     uarte::{Config, Uarte}, bind_interrupts,
 };
 use embassy_sync::blocking_mutex::{raw::CriticalSectionRawMutex, Mutex};
+use trustzone_m_secure_rt::Port0;
 
 include!(concat!(env!("OUT_DIR"), "/trustzone_bindings.rs"));
 
@@ -58,40 +59,8 @@ async fn main(_spawner: Spawner) -> ! {
             (dp.SPIM0_S, dp.SPIS0_S, dp.TWIM0_S, dp.TWIS0_S, dp.UARTE0_S).into(),
             (&dp.P0_S).into(),
         ],
-        [
-            (0, 0),
-            (0, 1),
-            (0, 2),
-            (0, 3),
-            (0, 4),
-            (0, 5),
-            (0, 6),
-            (0, 7),
-            (0, 8),
-            (0, 9),
-            (0, 10),
-            (0, 11),
-            (0, 12),
-            (0, 13),
-            (0, 14),
-            (0, 15),
-            (0, 16),
-            (0, 17),
-            (0, 18),
-            (0, 19),
-            (0, 20),
-            (0, 21),
-            (0, 22),
-            (0, 23),
-            (0, 24),
-            (0, 25),
-            (0, 26),
-            (0, 27),
-            // (0, 28),
-            // (0, 29),
-            (0, 30),
-            (0, 31),
-        ],
+        // The pins of the UART stay secure
+        [Port0::all_except([28, 29])],
         [
             (0, 0),
             (0, 1),
@@ -116,11 +85,11 @@ async fn main(_spawner: Spawner) -> ! {
 
     writeln!(Printer, 
         "Read call private: {}",
-        trustzone_bindings::read_private_thing()
+        trustzone_bindings::other_private_thing::read_private_thing()
     ).unwrap();
     writeln!(Printer, 
         "Read call other public: {}",
-        trustzone_bindings::read_public_thing()
+        trustzone_bindings::other_public_thing::read_public_thing()
     ).unwrap();
     writeln!(Printer, "Read call: {}", trustzone_bindings::read_thing()).unwrap();
 
@@ -5,8 +5,31 @@ pub fn secure_callable(attr: TokenStream, item: TokenStream) -> TokenStream {
     trustzone_m_tools::secure_callable_macro::secure_callable(attr.into(), item.into()).into()
 }
 
+/// Exports the function to the nonsecure app through a veneer.
+///
+/// Every pointer and reference argument is checked to point to memory the nonsecure caller has
+/// access to before the body runs, and null raw pointers are passed on unchecked. References also
+/// have to be aligned, and the type behind them has to implement `AnyBitPattern`. Arguments of
+/// types with invalid bit patterns, like `bool`, `char` and enums, are checked to be valid.
+///
+/// # Panics
+///
+/// A call that fails a check returns the value given with `on_reject = ...`, or `None` if the
+/// function returns an `Option`. Any other function panics, so a nonsecure caller that passes an
+/// invalid pointer can stop the secure app. Give it an `on_reject` value to prevent that:
+///
+/// ```ignore
+/// #[nonsecure_callable(on_reject = u32::MAX)]
+/// pub extern "C" fn read_counter(counter: &u32) -> u32 {
+///     *counter
+/// }
+/// ```
 #[proc_macro_attribute]
 pub fn nonsecure_callable(attr: TokenStream, item: TokenStream) -> TokenStream {
     trustzone_m_tools::nonsecure_callable_macro::nonsecure_callable(attr.into(), item.into()).into()
 }
 
+#[proc_macro_attribute]
+pub fn nonsecure_shared(attr: TokenStream, item: TokenStream) -> TokenStream {
+    trustzone_m_tools::nonsecure_shared_macro::nonsecure_shared(attr.into(), item.into()).into()
+}
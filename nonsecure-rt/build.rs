@@ -9,6 +9,10 @@ fn main() {
             &include_bytes!("../trustzone_memory.x.in")[..],
             "trustzone_memory.x",
         ),
+        (
+            &include_bytes!("../nonsecure_ns_shared.x.in")[..],
+            "ns_shared.x",
+        ),
         (
             &include_bytes!("../no_region_asserts.x.in")[..],
             "region_asserts.x",
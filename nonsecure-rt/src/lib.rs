@@ -32,3 +32,12 @@ core::arch::global_asm! {
     // Jump back to the caller.
     "bx lr",
 }
+
+pub use trustzone_m_common::{boundary_enum, AnyBitPattern, Boundary};
+
+#[doc(hidden)]
+pub use trustzone_m_common::{veneer_checksum, veneer_id, veneer_metadata, veneer_metadata_len};
+
+#[cfg(not(target_arch = "arm"))]
+#[doc(hidden)]
+pub use trustzone_m_common::SimulatedVeneer;
@@ -3,10 +3,16 @@ use std::{env, fs::File, io::Write, path::PathBuf};
 fn main() {
     let out = &PathBuf::from(env::var_os("OUT_DIR").unwrap());
 
-    let mut linker_scripts = vec![(
-        &include_bytes!("../trustzone_memory.x.in")[..],
-        "trustzone_memory.x",
-    )];
+    let mut linker_scripts = vec![
+        (
+            &include_bytes!("../trustzone_memory.x.in")[..],
+            "trustzone_memory.x",
+        ),
+        (
+            &include_bytes!("../secure_ns_shared.x.in")[..],
+            "ns_shared.x",
+        ),
+    ];
 
     if cfg!(feature = "_nrf") {
         linker_scripts.push((
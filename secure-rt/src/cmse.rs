@@ -0,0 +1,63 @@
+//! Validation of memory that is handed to the secure world by the nonsecure world.
+//!
+//! These are the equivalent of `cmse_check_address_range` and are used by the code the
+//! `nonsecure_callable` macro generates for pointer and reference arguments.
+//!
+//! The simulation on the host has no security attribution, so all memory except for the null
+//! pointer is accessible there.
+
+#[cfg(target_arch = "arm")]
+use cortex_m::cmse::{AccessType, TestTarget};
+
+/// Returns true if the nonsecure caller is allowed to read all of `ptr..ptr + len`.
+///
+/// A range of length 0 is always accepted because nothing can be accessed through it.
+pub fn is_nonsecure_readable(ptr: *const u8, len: usize) -> bool {
+    if len == 0 {
+        return true;
+    }
+
+    #[cfg(target_arch = "arm")]
+    return test_range(ptr, len).is_some_and(|target| target.ns_readable());
+    #[cfg(not(target_arch = "arm"))]
+    return !ptr.is_null();
+}
+
+/// Returns true if the nonsecure caller is allowed to read and write all of `ptr..ptr + len`.
+///
+/// A range of length 0 is always accepted because nothing can be accessed through it.
+pub fn is_nonsecure_read_writable(ptr: *const u8, len: usize) -> bool {
+    if len == 0 {
+        return true;
+    }
+
+    #[cfg(target_arch = "arm")]
+    return test_range(ptr, len).is_some_and(|target| target.ns_read_and_writable());
+    #[cfg(not(target_arch = "arm"))]
+    return !ptr.is_null();
+}
+
+#[cfg(target_arch = "arm")]
+fn test_range(ptr: *const u8, len: usize) -> Option<TestTarget> {
+    // Test with the privilege level of the nonsecure caller so that an unprivileged
+    // nonsecure thread can't use us to reach privileged nonsecure memory
+    let access_type = if nonsecure_is_privileged() {
+        AccessType::NonSecure
+    } else {
+        AccessType::NonSecureUnprivileged
+    };
+
+    // This returns None if the range crosses a SAU, IDAU or MPU region boundary
+    TestTarget::check_range(ptr as *mut u32, len, access_type)
+}
+
+#[cfg(target_arch = "arm")]
+fn nonsecure_is_privileged() -> bool {
+    let control_ns: u32;
+    unsafe {
+        core::arch::asm!("mrs {}, CONTROL_NS", out(reg) control_ns, options(nomem, nostack, preserves_flags));
+    }
+
+    // Bit 0 is nPRIV
+    control_ns & 1 == 0
+}
@@ -1,6 +1,18 @@
 use cortex_m::peripheral::sau::{SauError, SauRegion, SauRegionAttribute};
 
+/// Sets up the SAU for the nonsecure app and starts it like [initialize_checked], but panics if the
+/// nonsecure image can't be started
 pub fn initialize() {
+    if let Err(error) = initialize_checked() {
+        panic!("The nonsecure image can't be started: {error}");
+    }
+}
+
+/// Sets up the SAU for the nonsecure app and initializes the data of the nonsecure image.
+///
+/// The nonsecure image is checked first. If it can't be started, nothing of it is called and the
+/// error tells why, so the app can report it or fall back to something else.
+pub fn initialize_checked() -> Result<(), crate::veneer_table::NonsecureImageError> {
     extern "C" {
         static _nsc_flash_start: u32;
         static _nsc_flash_end: u32;
@@ -67,5 +79,13 @@ pub fn initialize() {
 
     sau.enable();
 
-    unsafe { crate::initialize_ns_data(); }
+    // Don't jump into the nonsecure flash if it doesn't hold an image we can start
+    crate::veneer_table::verify_nonsecure_image()?;
+
+    unsafe {
+        crate::initialize_ns_shared();
+        crate::initialize_ns_data();
+    }
+
+    Ok(())
 }
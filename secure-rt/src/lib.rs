@@ -9,11 +9,16 @@ mod nrf;
 #[cfg(feature = "generic")]
 mod generic;
 
+pub mod cmse;
+pub mod veneer_table;
+
 #[cfg(feature = "_nrf")]
-pub use nrf::initialize;
+pub use nrf::{initialize, initialize_checked, NonSecurePins, Port0};
+#[cfg(feature = "nrf5340")]
+pub use nrf::Port1;
 
 #[cfg(feature = "generic")]
-pub use generic::initialize;
+pub use generic::{initialize, initialize_checked};
 
 #[cfg(not(any(feature = "_nrf", feature = "generic", not(target_arch = "arm"))))]
 compile_error!("Select a trustzone runtime with the feature flags. Pick the feature of your chip or `generic`.");
@@ -22,3 +27,28 @@ compile_error!("Select a trustzone runtime with the feature flags. Pick the feat
 extern "C" {
     pub(crate) fn initialize_ns_data();
 }
+
+/// Copies the initial values of the statics that are shared with the nonsecure app into NS_RAM
+#[allow(dead_code)]
+pub(crate) unsafe fn initialize_ns_shared() {
+    extern "C" {
+        static _ns_shared_start: u32;
+        static _ns_shared_end: u32;
+        static _ns_shared_load_start: u32;
+    }
+
+    let start = &_ns_shared_start as *const u32 as *mut u32;
+    let end = &_ns_shared_end as *const u32;
+    let load_start = &_ns_shared_load_start as *const u32;
+
+    core::ptr::copy_nonoverlapping(load_start, start, end.offset_from(start) as usize);
+}
+
+pub use trustzone_m_common::{boundary_enum, AnyBitPattern, Boundary};
+
+#[doc(hidden)]
+pub use trustzone_m_common::{veneer_checksum, veneer_id, veneer_metadata, veneer_metadata_len};
+
+#[cfg(not(target_arch = "arm"))]
+#[doc(hidden)]
+pub use trustzone_m_common::SimulatedVeneer;
@@ -13,11 +13,31 @@ pub const RAM_REGION_SIZE: u32 = 8 * 1024;
 #[cfg(feature = "nrf9160")]
 pub const RAM_REGION_SIZE: u32 = 8 * 1024;
 
+/// Sets up the SPU for the nonsecure app and starts it like [initialize_checked], but panics if the
+/// nonsecure image can't be started
 pub fn initialize<const PERIPHERALS_LEN: usize, const PINS_LEN: usize, const DPPI_LEN: usize>(
     nonsecure_peripherals: [NonSecurePeripheral; PERIPHERALS_LEN],
-    nonsecure_pins: [(usize, u32); PINS_LEN],
+    nonsecure_pins: [NonSecurePins; PINS_LEN],
     nonsecure_dppi: [(usize, u32); DPPI_LEN],
 ) {
+    if let Err(error) = initialize_checked(nonsecure_peripherals, nonsecure_pins, nonsecure_dppi) {
+        panic!("The nonsecure image can't be started: {error}");
+    }
+}
+
+/// Sets up the SPU for the nonsecure app and initializes the data of the nonsecure image.
+///
+/// The nonsecure image is checked first. If it can't be started, nothing of it is called and the
+/// error tells why, so the app can report it or fall back to something else.
+pub fn initialize_checked<
+    const PERIPHERALS_LEN: usize,
+    const PINS_LEN: usize,
+    const DPPI_LEN: usize,
+>(
+    nonsecure_peripherals: [NonSecurePeripheral; PERIPHERALS_LEN],
+    nonsecure_pins: [NonSecurePins; PINS_LEN],
+    nonsecure_dppi: [(usize, u32); DPPI_LEN],
+) -> Result<(), crate::veneer_table::NonsecureImageError> {
     extern "C" {
         static _s_flash_start: u32;
         static _s_flash_end: u32;
@@ -147,10 +167,10 @@ pub fn initialize<const PERIPHERALS_LEN: usize, const PINS_LEN: usize, const DPP
     }
 
     // Set all given pins to nonsecure
-    for (pin_port, pin) in nonsecure_pins {
-        spu.gpioport[pin_port]
+    for pins in nonsecure_pins {
+        spu.gpioport[pins.port]
             .perm
-            .modify(|r, w| unsafe { w.bits(r.bits() & !(1 << pin)) })
+            .modify(|r, w| unsafe { w.bits(r.bits() & !pins.mask) })
     }
 
     // Set all given dppi channels to nonsecure
@@ -176,9 +196,15 @@ pub fn initialize<const PERIPHERALS_LEN: usize, const PINS_LEN: usize, const DPP
     cortex_m::asm::isb();
     cortex_m::asm::dsb();
 
+    // Don't jump into the nonsecure flash if it doesn't hold an image we can start
+    crate::veneer_table::verify_nonsecure_image()?;
+
     unsafe {
+        crate::initialize_ns_shared();
         crate::initialize_ns_data();
     }
+
+    Ok(())
 }
 
 fn set_nsc_region(spu: &SPU, region: core::ops::Range<u32>) {
@@ -204,6 +230,156 @@ pub struct NonSecurePeripheral {
     id: usize,
 }
 
+/// GPIO pins of one port that are given to the nonsecure app.
+///
+/// Pins are usually described with the helpers of the port, like [Port0::all_except], which check
+/// the pin numbers against the chip at compile time when they're used in a const.
+/// Pins of a HAL can be given with [NonSecurePins::from_psel_bits] and a whole port with its PAC
+/// peripheral. The pins of a port that aren't given stay secure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NonSecurePins {
+    port: usize,
+    mask: u32,
+}
+
+impl NonSecurePins {
+    /// The pins of the port in the mask, where bit `n` is pin `n`
+    const fn new(port: usize, pin_count: u32, mask: u32) -> Self {
+        assert!(
+            pin_count == 32 || mask >> pin_count == 0,
+            "The pin doesn't exist on this port"
+        );
+        Self { port, mask }
+    }
+
+    /// The pin with the `PSEL` bits of the peripherals, which is the port number times 32 plus the
+    /// pin number. The pins of the HALs return them with `psel_bits()`.
+    ///
+    /// Panics if the chip doesn't have the pin.
+    pub const fn from_psel_bits(psel_bits: u32) -> Self {
+        let (port, pin) = ((psel_bits / 32) as usize, psel_bits % 32);
+        assert!(port < PORT_PIN_COUNTS.len(), "The port doesn't exist on this chip");
+        Self::new(port, PORT_PIN_COUNTS[port], 1 << pin)
+    }
+
+    /// The pins of these and the other pins, which must be of the same port
+    pub const fn and(self, other: NonSecurePins) -> Self {
+        assert!(self.port == other.port, "The pins are of different ports");
+        Self {
+            port: self.port,
+            mask: self.mask | other.mask,
+        }
+    }
+
+    /// The index of the port
+    pub const fn port(&self) -> usize {
+        self.port
+    }
+
+    /// The mask of the pins, where bit `n` is pin `n`
+    pub const fn mask(&self) -> u32 {
+        self.mask
+    }
+}
+
+/// The mask of the given pins of a port with the pin count
+const fn pin_mask(pin_count: u32, pins: &[u32]) -> u32 {
+    let mut mask = 0;
+    let mut i = 0;
+    while i < pins.len() {
+        assert!(pins[i] < pin_count, "The pin doesn't exist on this port");
+        mask |= 1 << pins[i];
+        i += 1;
+    }
+    mask
+}
+
+/// The mask of all pins of a port with the pin count
+const fn all_pins_mask(pin_count: u32) -> u32 {
+    if pin_count == 32 {
+        u32::MAX
+    } else {
+        (1 << pin_count) - 1
+    }
+}
+
+macro_rules! impl_port {
+    ($(#[$attr:meta])* $port:ident, $index:expr) => {
+        $(#[$attr])*
+        #[derive(Debug, Clone, Copy)]
+        pub struct $port;
+
+        impl $port {
+            /// The number of pins of the port
+            pub const PIN_COUNT: u32 = PORT_PIN_COUNTS[$index];
+
+            /// All pins of the port
+            pub const fn all() -> NonSecurePins {
+                NonSecurePins::new($index, Self::PIN_COUNT, all_pins_mask(Self::PIN_COUNT))
+            }
+
+            /// The given pins of the port
+            pub const fn pins<const N: usize>(pins: [u32; N]) -> NonSecurePins {
+                NonSecurePins::new($index, Self::PIN_COUNT, pin_mask(Self::PIN_COUNT, &pins))
+            }
+
+            /// All pins of the port except the given ones, which stay secure
+            pub const fn all_except<const N: usize>(pins: [u32; N]) -> NonSecurePins {
+                NonSecurePins::new(
+                    $index,
+                    Self::PIN_COUNT,
+                    all_pins_mask(Self::PIN_COUNT) & !pin_mask(Self::PIN_COUNT, &pins),
+                )
+            }
+
+            /// The pins of the port in the range
+            pub const fn range(pins: core::ops::Range<u32>) -> NonSecurePins {
+                assert!(
+                    pins.start <= pins.end && pins.end <= Self::PIN_COUNT,
+                    "The pin doesn't exist on this port"
+                );
+                let mask = all_pins_mask(pins.end - pins.start) << pins.start;
+                NonSecurePins::new($index, Self::PIN_COUNT, mask)
+            }
+        }
+    };
+}
+
+/// The number of pins of every GPIO port of the chip
+#[cfg(feature = "nrf5340")]
+const PORT_PIN_COUNTS: [u32; 2] = [32, 16];
+#[cfg(feature = "nrf9160")]
+const PORT_PIN_COUNTS: [u32; 1] = [32];
+
+impl_port!(
+    /// The GPIO port P0
+    Port0,
+    0
+);
+#[cfg(feature = "nrf5340")]
+impl_port!(
+    /// The GPIO port P1
+    Port1,
+    1
+);
+
+macro_rules! impl_ns_pins {
+    ($peripheral:ty, $port:ty) => {
+        impl From<$peripheral> for NonSecurePins {
+            fn from(_: $peripheral) -> Self {
+                <$port>::all()
+            }
+        }
+    };
+}
+
+#[cfg(feature = "nrf5340")]
+impl_ns_pins!(&nrf5340_app_pac::P0_S, Port0);
+#[cfg(feature = "nrf5340")]
+impl_ns_pins!(&nrf5340_app_pac::P1_S, Port1);
+#[cfg(feature = "nrf9160")]
+impl_ns_pins!(&nrf9160_pac::P0_S, Port0);
+
 macro_rules! impl_ns_peripheral {
     ($peripheral:ty, $id:expr) => {
         impl From<$peripheral> for NonSecurePeripheral {
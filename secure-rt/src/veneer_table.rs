@@ -0,0 +1,489 @@
+//! The header of the veneer tables and the checks of the nonsecure image.
+//!
+//! Both tables start with the searcher or initializer veneer, followed by a header of four words:
+//! the magic, the version of the table format, the number of veneers and the checksum of their ids
+//! and signature hashes. The veneers follow the header, sorted by id. The header is written by the
+//! linker scripts of the runtimes. The checksum is the wrapping sum of the
+//! [veneer_checksum](crate::veneer_checksum) of every veneer.
+//!
+//! The `.ns_veneers` table of the nonsecure image is followed by the list of the items of the secure
+//! app the nonsecure image imports. It is the magic and the number of imports, followed by the id
+//! and the signature hash of every import. The bindings of the nonsecure image put them there, but
+//! only for the items whose panicking bindings the image uses.
+//!
+//! The secure runtime checks the nonsecure image before it calls the initializer veneer, so it
+//! doesn't jump into blank flash, into an image that isn't built with these crates or into an image
+//! that uses items the secure app doesn't export.
+
+use core::fmt;
+
+/// The magic of the `.ns_veneers` table of the nonsecure image, "TZNS" in ASCII
+pub const NS_VENEERS_MAGIC: u32 = u32::from_le_bytes(*b"TZNS");
+/// The magic of the `.nsc_veneers` table of the secure image, "TZSC" in ASCII
+pub const NSC_VENEERS_MAGIC: u32 = u32::from_le_bytes(*b"TZSC");
+/// The magic of the list of imports of the nonsecure image, "TZIM" in ASCII
+pub const IMPORTS_MAGIC: u32 = u32::from_le_bytes(*b"TZIM");
+/// The version of the format of the veneer tables
+pub const VENEER_TABLE_VERSION: u32 = 2;
+
+/// The number of words of a veneer in the `.ns_veneers` table: the branch, the id and the signature hash
+const NS_VENEER_WORDS: usize = 3;
+/// The number of words of a veneer in the `.nsc_veneers` table: the SG instruction, the branch, the id
+/// and the signature hash
+#[cfg_attr(not(target_arch = "arm"), allow(dead_code))]
+const NSC_VENEER_WORDS: usize = 4;
+/// The number of words of the header
+const HEADER_WORDS: usize = 4;
+
+/// The reason the `.ns_veneers` table of the nonsecure image can't be used
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VeneerTableError {
+    /// The table doesn't have the magic, so the nonsecure flash is blank or holds an image that
+    /// isn't built with these crates
+    WrongMagic(u32),
+    /// The table has a format this runtime doesn't know
+    UnsupportedVersion(u32),
+    /// The table has more veneers than fit in the nonsecure flash
+    TooManyVeneers(u32),
+    /// The veneer with the index isn't a branch instruction. The initializer veneer has no index.
+    NotABranch(Option<usize>),
+    /// The id of the veneer with the index isn't higher than the id of the veneer before it
+    NotSorted(usize),
+    /// The checksum of the ids and signature hashes of the veneers doesn't match the header
+    ChecksumMismatch {
+        /// The checksum in the header
+        expected: u32,
+        /// The checksum of the veneers of the table
+        actual: u32,
+    },
+}
+
+impl fmt::Display for VeneerTableError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VeneerTableError::WrongMagic(magic) => write!(
+                f,
+                "the veneer table has the magic {magic:#010x} instead of {NS_VENEERS_MAGIC:#010x}"
+            ),
+            VeneerTableError::UnsupportedVersion(version) => write!(
+                f,
+                "the veneer table has version {version}, but version {VENEER_TABLE_VERSION} is supported"
+            ),
+            VeneerTableError::TooManyVeneers(count) => write!(
+                f,
+                "the veneer table has {count} veneers, which don't fit in the nonsecure flash"
+            ),
+            VeneerTableError::NotABranch(None) => {
+                write!(f, "the initializer veneer isn't a branch instruction")
+            }
+            VeneerTableError::NotABranch(Some(index)) => {
+                write!(f, "veneer {index} isn't a branch instruction")
+            }
+            VeneerTableError::NotSorted(index) => {
+                write!(f, "veneer {index} isn't sorted by id")
+            }
+            VeneerTableError::ChecksumMismatch { expected, actual } => write!(
+                f,
+                "the veneers have the checksum {actual:#010x} instead of {expected:#010x}"
+            ),
+        }
+    }
+}
+
+/// Checks the `.ns_veneers` table at the start of the nonsecure flash.
+///
+/// The magic, the version, the number of veneers and the checksum of the header are checked, as
+/// well as that every veneer is a branch instruction and that the veneers are sorted by id.
+/// [initialize](crate::initialize) panics and [initialize_checked](crate::initialize_checked)
+/// returns the error if this fails, before anything of the nonsecure image is called.
+#[cfg(target_arch = "arm")]
+pub fn verify_ns_veneers() -> Result<(), VeneerTableError> {
+    verify_table(ns_flash()).map(|_| ())
+}
+
+/// Checks that the nonsecure image can be started.
+///
+/// Besides the checks of [verify_ns_veneers], the secure app has to export every item the
+/// nonsecure image imports, with the same signature. Otherwise the error lists the missing imports.
+/// [initialize](crate::initialize) panics and [initialize_checked](crate::initialize_checked)
+/// returns the error if this fails, before anything of the nonsecure image is called.
+#[cfg(target_arch = "arm")]
+pub fn verify_nonsecure_image() -> Result<(), NonsecureImageError> {
+    verify_image(ns_flash(), nsc_veneers())
+}
+
+/// The words of the nonsecure flash
+#[cfg(target_arch = "arm")]
+fn ns_flash() -> &'static [u32] {
+    extern "C" {
+        static _NS_VENEERS: u32;
+        static _ns_flash_end: u32;
+    }
+
+    let start = unsafe { &_NS_VENEERS as *const u32 };
+    let end = unsafe { &_ns_flash_end as *const u32 };
+    unsafe { core::slice::from_raw_parts(start, (end as usize - start as usize) / 4) }
+}
+
+/// The veneers of the `.nsc_veneers` table of this image
+#[cfg(target_arch = "arm")]
+fn nsc_veneers() -> &'static [[u32; NSC_VENEER_WORDS]] {
+    extern "C" {
+        static _NSC_VENEERS: u32;
+    }
+
+    // The linker script of this image has written the header, so it can be trusted
+    let count_ptr = unsafe { (&_NSC_VENEERS as *const u32).add(NSC_VENEER_WORDS + 2) };
+    unsafe { core::slice::from_raw_parts(count_ptr.add(2) as *const _, *count_ptr as usize) }
+}
+
+/// Checks the table at the start of the words of the nonsecure flash.
+/// Returns the number of words of the table.
+#[cfg_attr(not(target_arch = "arm"), allow(dead_code))]
+fn verify_table(ns_flash: &[u32]) -> Result<usize, VeneerTableError> {
+    let header = ns_flash
+        .get(NS_VENEER_WORDS..NS_VENEER_WORDS + HEADER_WORDS)
+        .ok_or(VeneerTableError::WrongMagic(0))?;
+    let [magic, version, count, checksum] = [header[0], header[1], header[2], header[3]];
+
+    if magic != NS_VENEERS_MAGIC {
+        return Err(VeneerTableError::WrongMagic(magic));
+    }
+    if version != VENEER_TABLE_VERSION {
+        return Err(VeneerTableError::UnsupportedVersion(version));
+    }
+
+    let veneers = (count as usize)
+        .checked_mul(NS_VENEER_WORDS)
+        .and_then(|len| ns_flash[NS_VENEER_WORDS + HEADER_WORDS..].get(..len))
+        .ok_or(VeneerTableError::TooManyVeneers(count))?;
+
+    if !is_branch(ns_flash[0]) {
+        return Err(VeneerTableError::NotABranch(None));
+    }
+
+    let mut previous_id = None;
+    let mut actual_checksum = 0u32;
+    for (index, veneer) in veneers.chunks_exact(NS_VENEER_WORDS).enumerate() {
+        if !is_branch(veneer[0]) {
+            return Err(VeneerTableError::NotABranch(Some(index)));
+        }
+        if previous_id.is_some_and(|previous_id| veneer[1] <= previous_id) {
+            return Err(VeneerTableError::NotSorted(index));
+        }
+        previous_id = Some(veneer[1]);
+        actual_checksum =
+            actual_checksum.wrapping_add(crate::veneer_checksum(veneer[1], veneer[2]));
+    }
+
+    if actual_checksum != checksum {
+        return Err(VeneerTableError::ChecksumMismatch {
+            expected: checksum,
+            actual: actual_checksum,
+        });
+    }
+
+    Ok(NS_VENEER_WORDS + HEADER_WORDS + veneers.len())
+}
+
+/// Checks the veneer table and the imports in the words of the nonsecure flash against the
+/// veneers of the `.nsc_veneers` table
+#[cfg_attr(not(target_arch = "arm"), allow(dead_code))]
+fn verify_image(
+    ns_flash: &'static [u32],
+    nsc_veneers: &'static [[u32; NSC_VENEER_WORDS]],
+) -> Result<(), NonsecureImageError> {
+    let table_len = verify_table(ns_flash).map_err(NonsecureImageError::VeneerTable)?;
+
+    let import_list = &ns_flash[table_len..];
+    let count = match import_list {
+        [IMPORTS_MAGIC, count, ..] => *count,
+        _ => return Err(NonsecureImageError::NoImportList),
+    };
+    let imports = (count as usize)
+        .checked_mul(2)
+        .and_then(|len| import_list[2..].get(..len))
+        .ok_or(NonsecureImageError::TooManyImports(count))?;
+
+    let missing_imports = MissingImports {
+        imports,
+        nsc_veneers,
+    };
+    if missing_imports.iter().next().is_some() {
+        return Err(NonsecureImageError::MissingImports(missing_imports));
+    }
+
+    Ok(())
+}
+
+/// The reason the nonsecure image can't be started
+#[derive(Debug, Clone, Copy)]
+pub enum NonsecureImageError {
+    /// The `.ns_veneers` table can't be used
+    VeneerTable(VeneerTableError),
+    /// The veneer table isn't followed by the list of imports, so the image isn't built with this
+    /// version of these crates
+    NoImportList,
+    /// The list of imports has more imports than fit in the nonsecure flash
+    TooManyImports(u32),
+    /// The secure app doesn't export some of the imports
+    MissingImports(MissingImports),
+}
+
+impl fmt::Display for NonsecureImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NonsecureImageError::VeneerTable(error) => write!(f, "{error}"),
+            NonsecureImageError::NoImportList => {
+                write!(f, "the veneer table isn't followed by the list of imports")
+            }
+            NonsecureImageError::TooManyImports(count) => write!(
+                f,
+                "the list of imports has {count} imports, which don't fit in the nonsecure flash"
+            ),
+            NonsecureImageError::MissingImports(missing_imports) => write!(f, "{missing_imports}"),
+        }
+    }
+}
+
+/// The imports of the nonsecure image that the secure app doesn't export
+#[derive(Debug, Clone, Copy)]
+pub struct MissingImports {
+    /// The ids and signature hashes of all imports
+    imports: &'static [u32],
+    nsc_veneers: &'static [[u32; NSC_VENEER_WORDS]],
+}
+
+impl MissingImports {
+    /// The missing imports
+    pub fn iter(&self) -> impl Iterator<Item = MissingImport> + '_ {
+        self.imports.chunks_exact(2).filter_map(|import| {
+            let [id, signature_hash] = [import[0], import[1]];
+            let exported_signature_hash = self
+                .nsc_veneers
+                .binary_search_by_key(&id, |[_, _, id, _]| *id)
+                .ok()
+                .map(|index| self.nsc_veneers[index][3]);
+
+            (exported_signature_hash != Some(signature_hash)).then_some(MissingImport {
+                id,
+                signature_hash,
+                exported_signature_hash,
+            })
+        })
+    }
+}
+
+impl fmt::Display for MissingImports {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "the secure app doesn't export these imports of the nonsecure image:"
+        )?;
+        for missing_import in self.iter() {
+            write!(f, "\n- {missing_import}")?;
+        }
+        Ok(())
+    }
+}
+
+/// An import of the nonsecure image that the secure app doesn't export
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MissingImport {
+    /// The veneer id of the import. The docs of the bindings show the id of every binding.
+    pub id: u32,
+    /// The signature hash the nonsecure image expects
+    pub signature_hash: u32,
+    /// The signature hash of the item with the same id the secure app exports, if there is one
+    pub exported_signature_hash: Option<u32>,
+}
+
+impl fmt::Display for MissingImport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.exported_signature_hash {
+            None => write!(f, "id {:#010x} isn't exported", self.id),
+            Some(exported_signature_hash) => write!(
+                f,
+                "id {:#010x} is exported with signature {exported_signature_hash:#010x} instead of {:#010x}",
+                self.id, self.signature_hash
+            ),
+        }
+    }
+}
+
+/// Returns true if the word is a `B.w` instruction
+fn is_branch(word: u32) -> bool {
+    // The instruction is two halfwords: 0b11110xxxxxxxxxxx and 0b10x1xxxxxxxxxxxx
+    let [first, second] = [word & 0xFFFF, word >> 16];
+    first & 0xF800 == 0xF000 && second & 0xD000 == 0x9000
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::{boxed::Box, vec, vec::Vec};
+
+    /// A `B.w` instruction
+    const BRANCH: u32 = 0x9000F000;
+
+    /// The words of a nonsecure flash with a table of the veneers and a list of the imports
+    fn ns_flash(veneers: &[(u32, u32)], imports: &[(u32, u32)]) -> Vec<u32> {
+        let checksum = veneers.iter().fold(0u32, |checksum, (id, signature_hash)| {
+            checksum.wrapping_add(crate::veneer_checksum(*id, *signature_hash))
+        });
+
+        let mut words = vec![BRANCH, 0, 0];
+        words.extend([
+            NS_VENEERS_MAGIC,
+            VENEER_TABLE_VERSION,
+            veneers.len() as u32,
+            checksum,
+        ]);
+        for (id, signature_hash) in veneers {
+            words.extend([BRANCH, *id, *signature_hash]);
+        }
+        words.extend([IMPORTS_MAGIC, imports.len() as u32]);
+        for (id, signature_hash) in imports {
+            words.extend([*id, *signature_hash]);
+        }
+        // The rest of the flash is blank
+        words.extend([0xFFFF_FFFF; 4]);
+        words
+    }
+
+    fn verify(ns_flash: Vec<u32>, nsc_veneers: &[(u32, u32)]) -> Result<(), NonsecureImageError> {
+        let nsc_veneers = nsc_veneers
+            .iter()
+            .map(|(id, signature_hash)| [0xE97F_E97F, BRANCH, *id, *signature_hash])
+            .collect::<Vec<_>>();
+        verify_image(
+            Box::leak(ns_flash.into_boxed_slice()),
+            Box::leak(nsc_veneers.into_boxed_slice()),
+        )
+    }
+
+    #[test]
+    fn test_verify_table() {
+        let veneers = [(1, 10), (2, 20), (5, 50)];
+        let flash = ns_flash(&veneers, &[]);
+        assert_eq!(verify_table(&flash), Ok(3 + HEADER_WORDS + 9));
+        assert_eq!(verify_table(&ns_flash(&[], &[])), Ok(3 + HEADER_WORDS));
+
+        // Blank flash
+        assert_eq!(
+            verify_table(&[0xFFFF_FFFF; 64]),
+            Err(VeneerTableError::WrongMagic(0xFFFF_FFFF))
+        );
+        assert_eq!(verify_table(&[]), Err(VeneerTableError::WrongMagic(0)));
+
+        let mut wrong_magic = flash.clone();
+        wrong_magic[3] = NSC_VENEERS_MAGIC;
+        assert_eq!(
+            verify_table(&wrong_magic),
+            Err(VeneerTableError::WrongMagic(NSC_VENEERS_MAGIC))
+        );
+
+        let mut wrong_version = flash.clone();
+        wrong_version[4] = 1;
+        assert_eq!(
+            verify_table(&wrong_version),
+            Err(VeneerTableError::UnsupportedVersion(1))
+        );
+
+        let mut too_many_veneers = flash.clone();
+        too_many_veneers[5] = 100;
+        assert_eq!(
+            verify_table(&too_many_veneers),
+            Err(VeneerTableError::TooManyVeneers(100))
+        );
+        too_many_veneers[5] = u32::MAX;
+        assert_eq!(
+            verify_table(&too_many_veneers),
+            Err(VeneerTableError::TooManyVeneers(u32::MAX))
+        );
+
+        let mut not_a_branch = flash.clone();
+        not_a_branch[0] = 0;
+        assert_eq!(
+            verify_table(&not_a_branch),
+            Err(VeneerTableError::NotABranch(None))
+        );
+        not_a_branch[0] = BRANCH;
+        not_a_branch[7 + 3] = 0xFFFF_FFFF;
+        assert_eq!(
+            verify_table(&not_a_branch),
+            Err(VeneerTableError::NotABranch(Some(1)))
+        );
+
+        let unsorted = ns_flash(&[(1, 10), (5, 50), (2, 20)], &[]);
+        assert_eq!(verify_table(&unsorted), Err(VeneerTableError::NotSorted(2)));
+        let duplicate = ns_flash(&[(1, 10), (1, 10)], &[]);
+        assert_eq!(
+            verify_table(&duplicate),
+            Err(VeneerTableError::NotSorted(1))
+        );
+
+        // A signature hash that doesn't match the one the checksum was built from
+        let mut checksum_mismatch = flash.clone();
+        checksum_mismatch[7 + 5] = 21;
+        let expected = flash[6];
+        let actual = expected
+            .wrapping_sub(crate::veneer_checksum(2, 20))
+            .wrapping_add(crate::veneer_checksum(2, 21));
+        assert_eq!(
+            verify_table(&checksum_mismatch),
+            Err(VeneerTableError::ChecksumMismatch { expected, actual })
+        );
+    }
+
+    #[test]
+    fn test_verify_image() {
+        let exports = [(1, 10), (2, 20), (3, 30)];
+
+        assert!(verify(ns_flash(&[(7, 70)], &[(1, 10), (3, 30)]), &exports).is_ok());
+        assert!(verify(ns_flash(&[], &[]), &[]).is_ok());
+
+        assert!(matches!(
+            verify(vec![0xFFFF_FFFF; 64], &exports),
+            Err(NonsecureImageError::VeneerTable(
+                VeneerTableError::WrongMagic(0xFFFF_FFFF)
+            ))
+        ));
+
+        let mut no_import_list = ns_flash(&[(7, 70)], &[]);
+        no_import_list.truncate(3 + HEADER_WORDS + 3);
+        assert!(matches!(
+            verify(no_import_list, &exports),
+            Err(NonsecureImageError::NoImportList)
+        ));
+
+        let mut too_many_imports = ns_flash(&[(7, 70)], &[(1, 10)]);
+        too_many_imports[3 + HEADER_WORDS + 3 + 1] = 100;
+        assert!(matches!(
+            verify(too_many_imports, &exports),
+            Err(NonsecureImageError::TooManyImports(100))
+        ));
+
+        let error = verify(ns_flash(&[], &[(1, 10), (2, 21), (4, 40)]), &exports).unwrap_err();
+        let NonsecureImageError::MissingImports(missing_imports) = error else {
+            panic!("unexpected error {error}");
+        };
+        assert_eq!(
+            missing_imports.iter().collect::<Vec<_>>(),
+            [
+                MissingImport {
+                    id: 2,
+                    signature_hash: 21,
+                    exported_signature_hash: Some(20),
+                },
+                MissingImport {
+                    id: 4,
+                    signature_hash: 40,
+                    exported_signature_hash: None,
+                },
+            ]
+        );
+    }
+}
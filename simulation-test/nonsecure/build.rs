@@ -0,0 +1,6 @@
+fn main() {
+    trustzone_m_tools::BindingsBuilder::new("../secure/src/lib.rs", false)
+        .simulation_peer("simulation_secure")
+        .generate()
+        .unwrap();
+}
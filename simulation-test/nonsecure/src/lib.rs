@@ -0,0 +1,22 @@
+//! The nonsecure app of the simulation test.
+
+#![no_std]
+
+use core::sync::atomic::{AtomicU32, Ordering};
+use trustzone_m_macros::secure_callable;
+
+include!(concat!(env!("OUT_DIR"), "/trustzone_bindings.rs"));
+
+static LAST_EVENT: AtomicU32 = AtomicU32::new(0);
+
+/// Records the event and answers with 100
+#[secure_callable]
+pub extern "C" fn on_event(event: u32) -> u32 {
+    LAST_EVENT.store(event, Ordering::Relaxed);
+    100
+}
+
+/// The last event the secure app sent
+pub fn last_event() -> u32 {
+    LAST_EVENT.load(Ordering::Relaxed)
+}
@@ -0,0 +1,45 @@
+use core::sync::atomic::Ordering;
+use simulation_nonsecure::trustzone_bindings;
+use simulation_shared::{Mode, RadioStatus};
+
+// The bindings call the secure app directly
+use simulation_secure as _;
+
+#[test]
+fn test_calls() {
+    assert_eq!(trustzone_bindings::add(1, 2), 103);
+    assert_eq!(simulation_nonsecure::last_event(), 7);
+    assert_eq!(trustzone_bindings::toggle(Mode::Off, 'k'), Mode::On);
+    assert_eq!(trustzone_bindings::read(core::ptr::null(), &4), 4);
+    assert_eq!(trustzone_bindings::read(&5, &4), 5);
+}
+
+#[test]
+fn test_modules() {
+    let key = trustzone_bindings::storage::store(9, &[1, 0, 0, 0]);
+    assert_eq!(key.map(|key| key.get()), Some(9));
+    assert_eq!(simulation_secure::storage::value(), 1);
+}
+
+#[test]
+fn test_shared_statics() {
+    trustzone_bindings::events().fetch_add(3, Ordering::Relaxed);
+    assert_eq!(simulation_secure::events(), 3);
+
+    let radio = unsafe { trustzone_bindings::radio().read() };
+    assert_eq!(
+        radio,
+        RadioStatus {
+            rssi: -60,
+            packets: 0
+        }
+    );
+}
+
+#[test]
+fn test_availability() {
+    trustzone_bindings::resolve_all();
+    assert!(trustzone_bindings::is_available::add());
+    assert!(trustzone_bindings::storage::is_available::store());
+    assert_eq!(trustzone_bindings::try_add(2, 2), Ok(104));
+}
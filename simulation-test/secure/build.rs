@@ -0,0 +1,6 @@
+fn main() {
+    trustzone_m_tools::BindingsBuilder::new("../nonsecure/src/lib.rs", true)
+        .simulation_peer("simulation_nonsecure")
+        .generate()
+        .unwrap();
+}
@@ -0,0 +1,92 @@
+//! The secure app of the simulation test.
+
+#![no_std]
+#![cfg_attr(
+    target_arch = "arm",
+    feature(cmse_nonsecure_entry, abi_c_cmse_nonsecure_call)
+)]
+
+use core::sync::atomic::{AtomicU32, Ordering};
+use simulation_shared::{Mode, RadioStatus};
+use trustzone_m_macros::{nonsecure_callable, nonsecure_shared};
+
+// The bindings call the nonsecure app directly in the tests
+#[cfg(test)]
+extern crate simulation_nonsecure;
+
+include!(concat!(env!("OUT_DIR"), "/trustzone_bindings.rs"));
+
+#[nonsecure_shared]
+pub static EVENTS: AtomicU32 = AtomicU32::new(0);
+
+#[nonsecure_shared]
+pub static mut RADIO: RadioStatus = RadioStatus {
+    rssi: -60,
+    packets: 0,
+};
+
+/// Adds the numbers and the answer of the nonsecure app to an event
+#[nonsecure_callable]
+pub extern "C" fn add(a: u32, b: u32) -> u32 {
+    a + b + trustzone_bindings::on_event(7)
+}
+
+// The macro checks the pointer before the body runs
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[nonsecure_callable(on_reject = u32::MAX)]
+pub extern "C" fn read(value: *const u32, fallback: &u32) -> u32 {
+    unsafe { value.as_ref() }.map_or(*fallback, |value| *value)
+}
+
+#[nonsecure_callable(on_reject = Mode::Off)]
+pub fn toggle(mode: Mode, key: char) -> Mode {
+    match (mode, key) {
+        (Mode::Off, 'k') => Mode::On,
+        _ => Mode::Off,
+    }
+}
+
+pub mod storage {
+    use core::{
+        num::NonZeroU32,
+        sync::atomic::{AtomicU32, Ordering},
+    };
+    use trustzone_m_macros::nonsecure_callable;
+
+    static VALUE: AtomicU32 = AtomicU32::new(0);
+
+    #[nonsecure_callable]
+    pub fn store(key: u32, value: &[u8; 4]) -> Option<NonZeroU32> {
+        VALUE.store(u32::from_le_bytes(*value), Ordering::Relaxed);
+        NonZeroU32::new(key)
+    }
+
+    pub fn value() -> u32 {
+        VALUE.load(Ordering::Relaxed)
+    }
+}
+
+/// The number of events the nonsecure app counted
+pub fn events() -> u32 {
+    EVENTS.load(Ordering::Relaxed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_calls_nonsecure() {
+        assert_eq!(add(1, 1), 102);
+        assert_eq!(simulation_nonsecure::last_event(), 7);
+    }
+
+    #[test]
+    fn test_rejects_invalid_arguments() {
+        // The entry gets the lowered arguments, like a nonsecure app that calls the veneer
+        assert_eq!(__trustzone_toggle(0, 'k' as u32), 5);
+        assert_eq!(__trustzone_toggle(5, 'k' as u32), 0);
+        assert_eq!(__trustzone_toggle(2, 'k' as u32), 0);
+        assert_eq!(__trustzone_toggle(0, 0xD800), 0);
+    }
+}
@@ -0,0 +1,24 @@
+//! The types in the interface between the two apps of the simulation test.
+//!
+//! The bindings of both apps name these types, so they're in a crate both apps depend on.
+
+#![no_std]
+
+trustzone_m_common::boundary_enum! {
+    #[derive(Clone, Copy, PartialEq, Eq, Debug)]
+    #[repr(u8)]
+    pub enum Mode {
+        Off,
+        On = 5,
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct RadioStatus {
+    pub rssi: i32,
+    pub packets: u32,
+}
+
+// Safety: Both fields are integers
+unsafe impl trustzone_m_common::AnyBitPattern for RadioStatus {}
@@ -0,0 +1,938 @@
+//! The rules for moving values across the security boundary.
+//!
+//! Both the macros and the bindings generator use this so that the two sides of a call always
+//! agree on how a Rust type is represented in the registers.
+
+use proc_macro2::{Span, TokenStream};
+use quote::{quote, quote_spanned, ToTokens};
+use syn::spanned::Spanned;
+
+/// Returns true if the function is declared as `extern "C"`.
+pub(crate) fn is_extern_c(signature: &syn::Signature) -> bool {
+    signature.abi.as_ref().is_some_and(|abi| {
+        abi.name
+            .as_ref()
+            .is_some_and(|abi_name| abi_name.value().to_uppercase() == "C")
+    })
+}
+
+/// How a value of a Rust type is turned into something that can be passed in a register.
+#[derive(Clone)]
+pub(crate) enum Lowering {
+    /// The type can be passed as-is. This is the case for the primitives and pointers.
+    Identity,
+    /// `bool`, passed as a `u32` that must be 0 or 1.
+    Bool,
+    /// `char`, passed as a `u32` that must be a valid code point.
+    Char,
+    /// `Option<NonZero*>`, passed as the underlying integer where `None` is 0.
+    OptionNonZero {
+        non_zero: syn::Ident,
+        integer: syn::Ident,
+    },
+    /// Any other type, like a fieldless enum or a `#[repr(C)]` struct. It's passed as the `Raw`
+    /// type of its `Boundary` implementation in the runtime, which also checks it.
+    Checked(Box<syn::Type>),
+}
+
+impl Lowering {
+    pub(crate) fn of(ty: &syn::Type) -> Self {
+        match unparenthesize(ty) {
+            syn::Type::Path(type_path) if type_path.qself.is_none() => {
+                let Some(last_segment) = type_path.path.segments.last() else {
+                    return Self::Identity;
+                };
+
+                match primitive_name(&type_path.path) {
+                    Some("bool") => return Self::Bool,
+                    Some("char") => return Self::Char,
+                    Some(_) => return Self::Identity,
+                    None => {}
+                }
+
+                if last_segment.ident == "Option" {
+                    if let Some(inner) = single_generic_argument(last_segment) {
+                        if let Some(non_zero) = last_path_ident(inner) {
+                            if let Some(integer) = non_zero_integer(&non_zero) {
+                                return Self::OptionNonZero {
+                                    integer: syn::Ident::new(integer, Span::call_site()),
+                                    non_zero,
+                                };
+                            }
+                        }
+                    }
+                    // The other options that can cross are nullable pointers
+                    return Self::Identity;
+                }
+
+                match last_segment.ident.to_string().as_str() {
+                    "NonNull" | "PhantomData" => Self::Identity,
+                    _ if non_zero_integer(&last_segment.ident).is_some() => Self::Identity,
+                    _ => Self::Checked(Box::new(ty.clone())),
+                }
+            }
+            _ => Self::Identity,
+        }
+    }
+
+    /// Returns true if the lowered type has values that aren't valid for the Rust type, so
+    /// [Self::lift] gives an `Option`
+    pub(crate) fn is_checked(&self) -> bool {
+        matches!(self, Self::Bool | Self::Char | Self::Checked(_))
+    }
+
+    /// The type that is used in the `extern "C"` signature for the given type
+    pub(crate) fn lowered_type(&self, ty: &syn::Type, runtime: &TokenStream) -> syn::Type {
+        match self {
+            Self::Identity => ty.clone(),
+            Self::Bool | Self::Char => syn::parse_quote!(u32),
+            Self::OptionNonZero { integer, .. } => syn::parse_quote!(#integer),
+            Self::Checked(ty) => syn::parse_quote!(<#ty as #runtime::Boundary>::Raw),
+        }
+    }
+
+    /// Turns the Rust value into the value of the lowered type
+    pub(crate) fn lower(&self, value: syn::Expr, runtime: &TokenStream) -> syn::Expr {
+        match self {
+            Self::Identity => value,
+            Self::Bool | Self::Char => {
+                let value = operand(value);
+                syn::parse_quote!(#value as u32)
+            }
+            Self::OptionNonZero { non_zero, .. } => {
+                let value = operand(value);
+                syn::parse_quote!(#value.map_or(0, core::num::#non_zero::get))
+            }
+            Self::Checked(_) => syn::parse_quote!(#runtime::Boundary::into_raw(#value)),
+        }
+    }
+
+    /// Turns the value of the lowered type back into the Rust value. If the lowering
+    /// [is checked](Self::is_checked), this gives an `Option` that is `None` for an invalid value.
+    pub(crate) fn lift(&self, value: syn::Expr, runtime: &TokenStream) -> syn::Expr {
+        match self {
+            Self::Identity => value,
+            Self::Bool => syn::parse_quote! {
+                match #value {
+                    0 => Some(false),
+                    1 => Some(true),
+                    _ => None,
+                }
+            },
+            Self::Char => syn::parse_quote!(char::from_u32(#value)),
+            Self::OptionNonZero { non_zero, .. } => {
+                syn::parse_quote!(core::num::#non_zero::new(#value))
+            }
+            Self::Checked(ty) => syn::parse_quote!(<#ty as #runtime::Boundary>::from_raw(#value)),
+        }
+    }
+}
+
+/// The `extern "C"` version of a Rust signature.
+/// Every typed argument is replaced by an argument with its lowered type.
+pub(crate) struct LoweredSignature {
+    pub(crate) inputs: Vec<LoweredArgument>,
+    pub(crate) output: Option<(syn::Type, Lowering)>,
+    /// The path of the runtime of the app the code is generated for, which has the `Boundary` trait
+    runtime: TokenStream,
+}
+
+pub(crate) struct LoweredArgument {
+    pub(crate) ident: syn::Ident,
+    pub(crate) lowered_ty: syn::Type,
+    pub(crate) lowering: Lowering,
+}
+
+impl LoweredSignature {
+    pub(crate) fn new(signature: &syn::Signature, runtime: TokenStream) -> Self {
+        let inputs = signature
+            .inputs
+            .iter()
+            .filter_map(|input| match input {
+                syn::FnArg::Typed(t) => Some(t),
+                _ => None,
+            })
+            .enumerate()
+            .map(|(index, input)| {
+                let ident = match input.pat.as_ref() {
+                    syn::Pat::Ident(pat_ident) => pat_ident.ident.clone(),
+                    _ => syn::Ident::new(&format!("arg{index}"), Span::call_site()),
+                };
+                let lowering = Lowering::of(&input.ty);
+
+                LoweredArgument {
+                    ident,
+                    lowered_ty: lowering.lowered_type(&input.ty, &runtime),
+                    lowering,
+                }
+            })
+            .collect();
+
+        let output = match &signature.output {
+            syn::ReturnType::Default => None,
+            syn::ReturnType::Type(_, ty) => Some((*ty.clone(), Lowering::of(ty))),
+        };
+
+        Self {
+            inputs,
+            output,
+            runtime,
+        }
+    }
+
+    /// Returns true if any argument has to be checked when it's received
+    pub(crate) fn has_checked_inputs(&self) -> bool {
+        self.inputs.iter().any(|input| input.lowering.is_checked())
+    }
+
+    /// Returns true if the return value has to be checked when it's received
+    pub(crate) fn has_checked_output(&self) -> bool {
+        self.output
+            .as_ref()
+            .is_some_and(|(_, lowering)| lowering.is_checked())
+    }
+
+    /// The arguments of the lowered function
+    pub(crate) fn lowered_inputs(&self) -> impl Iterator<Item = syn::BareFnArg> + '_ {
+        self.inputs.iter().map(|input| {
+            let lowered_ty = &input.lowered_ty;
+            syn::parse_quote!(#lowered_ty)
+        })
+    }
+
+    /// The return type of the lowered function
+    pub(crate) fn lowered_output(&self) -> syn::ReturnType {
+        match &self.output {
+            None => syn::ReturnType::Default,
+            Some((ty, lowering)) => {
+                let lowered_ty = lowering.lowered_type(ty, &self.runtime);
+                syn::parse_quote!(-> #lowered_ty)
+            }
+        }
+    }
+
+    /// The lowered arguments in the order of the signature
+    pub(crate) fn lowered_arguments(&self) -> impl Iterator<Item = syn::Expr> + '_ {
+        self.inputs.iter().map(|input| {
+            let ident = &input.ident;
+            input.lowering.lower(syn::parse_quote!(#ident), &self.runtime)
+        })
+    }
+
+    /// Lowers the return value
+    pub(crate) fn lower_output(&self, value: syn::Expr) -> syn::Expr {
+        match &self.output {
+            None => value,
+            Some((_, lowering)) => lowering.lower(value, &self.runtime),
+        }
+    }
+
+    /// Lifts the return value, which gives an `Option` if the output
+    /// [is checked](Self::has_checked_output)
+    pub(crate) fn lift_output(&self, value: syn::Expr) -> syn::Expr {
+        match &self.output {
+            None => value,
+            Some((_, lowering)) => lowering.lift(value, &self.runtime),
+        }
+    }
+}
+
+/// Creates an `extern "C"` function that lifts its arguments, calls the Rust function and lowers the
+/// result again. The caller side of the binding does the reverse.
+///
+/// An argument that isn't valid for its type runs the `rejection` of the argument instead of the
+/// function.
+pub(crate) fn rust_abi_shim(
+    function: &syn::ItemFn,
+    shim_ident: &syn::Ident,
+    attrs: Vec<syn::Attribute>,
+    runtime: TokenStream,
+    rejection: impl Fn(&syn::Ident) -> TokenStream,
+) -> syn::ItemFn {
+    let lowered_signature = LoweredSignature::new(&function.sig, runtime);
+
+    let function_ident = &function.sig.ident;
+    let shim_inputs = lowered_signature.inputs.iter().map(|input| {
+        let ident = &input.ident;
+        let lowered_ty = &input.lowered_ty;
+        quote!(#ident: #lowered_ty)
+    });
+    let shim_output = lowered_signature.lowered_output();
+
+    let mut argument_checks: Vec<syn::Stmt> = Vec::new();
+    let mut lifted_arguments: Vec<syn::Expr> = Vec::new();
+    for input in lowered_signature.inputs.iter() {
+        let ident = &input.ident;
+        let lifted = input
+            .lowering
+            .lift(syn::parse_quote!(#ident), &lowered_signature.runtime);
+
+        if input.lowering.is_checked() {
+            // A `match` can't be followed by the `else`
+            let lifted = operand(lifted);
+            let rejection = rejection(ident);
+            argument_checks.push(syn::parse_quote! {
+                let Some(#ident) = #lifted else {
+                    #rejection
+                };
+            });
+            lifted_arguments.push(syn::parse_quote!(#ident));
+        } else {
+            lifted_arguments.push(lifted);
+        }
+    }
+
+    let call: syn::Expr = if function.sig.unsafety.is_some() {
+        syn::parse_quote!(unsafe { #function_ident(#(#lifted_arguments),*) })
+    } else {
+        syn::parse_quote!(#function_ident(#(#lifted_arguments),*))
+    };
+    let call = lowered_signature.lower_output(call);
+
+    syn::parse_quote! {
+        #(#attrs)*
+        extern "C" fn #shim_ident(#(#shim_inputs),*) #shim_output {
+            #(#argument_checks)*
+            #call
+        }
+    }
+}
+
+/// Puts the expression in parentheses if it can't be the operand of `as`, a binary operator or a
+/// method call as it is, like the `unsafe { .. }` block around a call of an unsafe function
+pub(crate) fn operand(value: syn::Expr) -> syn::Expr {
+    match value {
+        syn::Expr::Path(_)
+        | syn::Expr::Call(_)
+        | syn::Expr::MethodCall(_)
+        | syn::Expr::Field(_)
+        | syn::Expr::Index(_)
+        | syn::Expr::Lit(_)
+        | syn::Expr::Paren(_) => value,
+        value => syn::parse_quote!((#value)),
+    }
+}
+
+pub(crate) fn unparenthesize(ty: &syn::Type) -> &syn::Type {
+    match ty {
+        syn::Type::Paren(paren) => unparenthesize(&paren.elem),
+        syn::Type::Group(group) => unparenthesize(&group.elem),
+        ty => ty,
+    }
+}
+
+fn single_generic_argument(segment: &syn::PathSegment) -> Option<&syn::Type> {
+    let syn::PathArguments::AngleBracketed(arguments) = &segment.arguments else {
+        return None;
+    };
+
+    match arguments.args.first() {
+        Some(syn::GenericArgument::Type(ty)) if arguments.args.len() == 1 => Some(ty),
+        _ => None,
+    }
+}
+
+fn last_path_ident(ty: &syn::Type) -> Option<syn::Ident> {
+    match unparenthesize(ty) {
+        syn::Type::Path(type_path) => type_path
+            .path
+            .segments
+            .last()
+            .map(|segment| segment.ident.clone()),
+        _ => None,
+    }
+}
+
+/// The primitive type a path names, which is the path itself for a primitive like `u32`, or
+/// `core::primitive::u32` and `core::ffi::c_uint` for `u32`. The C types are the ones of Arm,
+/// where a `c_char` is unsigned and a `c_long` is 32 bits.
+fn primitive_name(path: &syn::Path) -> Option<&'static str> {
+    const PRIMITIVES: &[&str] = &[
+        "u8", "u16", "u32", "u64", "u128", "usize", "i8", "i16", "i32", "i64", "i128", "isize",
+        "f32", "f64", "bool", "char",
+    ];
+
+    if path
+        .segments
+        .iter()
+        .any(|segment| !segment.arguments.is_empty())
+    {
+        return None;
+    }
+    let segments = path
+        .segments
+        .iter()
+        .map(|segment| segment.ident.to_string())
+        .collect::<Vec<_>>();
+    let (name, module) = segments.split_last()?;
+    let module = module.iter().map(String::as_str).collect::<Vec<_>>();
+
+    match module.as_slice() {
+        [] | ["core" | "std", "primitive"] => PRIMITIVES
+            .iter()
+            .find(|primitive| *primitive == name)
+            .copied(),
+        ["core" | "std", "ffi"] | ["std", "os", "raw"] => Some(match name.as_str() {
+            "c_char" | "c_uchar" => "u8",
+            "c_schar" => "i8",
+            "c_short" => "i16",
+            "c_ushort" => "u16",
+            "c_int" | "c_long" => "i32",
+            "c_uint" | "c_ulong" => "u32",
+            "c_longlong" => "i64",
+            "c_ulonglong" => "u64",
+            "c_float" => "f32",
+            "c_double" => "f64",
+            _ => return None,
+        }),
+        _ => None,
+    }
+}
+
+fn non_zero_integer(non_zero: &syn::Ident) -> Option<&'static str> {
+    Some(match non_zero.to_string().as_str() {
+        "NonZeroU8" => "u8",
+        "NonZeroU16" => "u16",
+        "NonZeroU32" => "u32",
+        "NonZeroUsize" => "usize",
+        "NonZeroI8" => "i8",
+        "NonZeroI16" => "i16",
+        "NonZeroI32" => "i32",
+        "NonZeroIsize" => "isize",
+        _ => return None,
+    })
+}
+
+/// The number of core registers (r0-r3) that can be used for the arguments and return value of a
+/// function that crosses the security boundary. Anything that would go on the stack is rejected.
+const ARGUMENT_REGISTERS: usize = 4;
+
+/// The size and alignment in bytes of a type that we know the layout of
+#[derive(Debug, Clone, Copy)]
+struct Layout {
+    size: usize,
+    align: usize,
+}
+
+impl Layout {
+    const fn new(size: usize, align: usize) -> Self {
+        Self { size, align }
+    }
+
+    fn words(&self) -> usize {
+        self.size.div_ceil(4)
+    }
+}
+
+enum Footprint {
+    Known(Layout),
+    /// A type we don't know the layout of, like a user struct. Its size is checked at compile time
+    /// by the generated code instead.
+    Unknown,
+}
+
+/// Checks that the function can cross the security boundary. This means that all arguments and the
+/// return value must be able to be passed in r0-r3, because passing anything on the stack is not
+/// allowed.
+///
+/// What can be checked here is turned into an error pointing at the offending part of the
+/// signature. The sizes of types we don't know are checked with the returned const assertions,
+/// which measure the `Raw` type of their `Boundary` implementation in the runtime, because that is
+/// what is passed.
+pub(crate) fn check_signature(
+    signature: &syn::Signature,
+    runtime: &TokenStream,
+) -> Result<TokenStream, syn::Error> {
+    let mut errors = Vec::new();
+
+    if let Some(asyncness) = &signature.asyncness {
+        errors.push(syn::Error::new_spanned(
+            asyncness,
+            "A function that crosses the security boundary can't be async",
+        ));
+    }
+    if let Some(variadic) = &signature.variadic {
+        errors.push(syn::Error::new_spanned(
+            variadic,
+            "A function that crosses the security boundary can't be variadic",
+        ));
+    }
+    for param in signature.generics.params.iter() {
+        if !matches!(param, syn::GenericParam::Lifetime(_)) {
+            errors.push(syn::Error::new_spanned(
+                param,
+                "A function that crosses the security boundary can't be generic",
+            ));
+        }
+    }
+
+    let mut used_registers = 0;
+    // The size and alignment of every argument, as expressions for the types we don't know
+    let mut argument_layouts = Vec::new();
+    let mut unknown_arguments = Vec::new();
+
+    for input in signature.inputs.iter() {
+        let input = match input {
+            syn::FnArg::Typed(t) => t,
+            syn::FnArg::Receiver(receiver) => {
+                errors.push(syn::Error::new_spanned(
+                    receiver,
+                    "A function that crosses the security boundary can't take `self`",
+                ));
+                continue;
+            }
+        };
+
+        match argument_footprint(&input.ty) {
+            Ok(Footprint::Known(layout)) => {
+                let (size, align) = (layout.size, layout.align);
+                argument_layouts.push(quote!((#size, #align)));
+
+                // 8-byte aligned values start at an even register
+                if layout.align == 8 {
+                    used_registers += used_registers % 2;
+                }
+                let first_register = used_registers;
+                used_registers += layout.words();
+
+                if used_registers > ARGUMENT_REGISTERS {
+                    let name = match input.pat.as_ref() {
+                        syn::Pat::Ident(pat_ident) => format!("`{}`", pat_ident.ident),
+                        _ => "This argument".into(),
+                    };
+                    errors.push(syn::Error::new_spanned(
+                        input,
+                        format!(
+                            "{name} doesn't fit in the registers: it would be passed in r{}-r{}, \
+                            but only r0-r{} can be used to cross the security boundary. \
+                            Pass a pointer instead.",
+                            first_register,
+                            used_registers - 1,
+                            ARGUMENT_REGISTERS - 1
+                        ),
+                    ));
+                }
+            }
+            Ok(Footprint::Unknown) => {
+                let raw = passed_type(&input.ty, runtime);
+                argument_layouts
+                    .push(quote!((core::mem::size_of::<#raw>(), core::mem::align_of::<#raw>())));
+                unknown_arguments.push(input.ty.clone());
+            }
+            Err(e) => errors.push(e),
+        }
+    }
+
+    let mut assertions = TokenStream::new();
+
+    if !unknown_arguments.is_empty() {
+        let function_name = signature.ident.to_string();
+        let message = format!(
+            "The arguments of '{function_name}' don't fit in r0-r{}, which is required to cross the security boundary",
+            ARGUMENT_REGISTERS - 1
+        );
+        let argument_count = argument_layouts.len();
+        let span = unknown_arguments[0].span();
+
+        // The registers are counted like the compiler assigns them, so an 8-byte aligned argument
+        // after an odd number of registers skips one
+        assertions.extend(quote_spanned! {span=>
+            const _: () = {
+                const ARGUMENTS: [(usize, usize); #argument_count] = [#(#argument_layouts),*];
+                let mut registers = 0;
+                let mut i = 0;
+                while i < ARGUMENTS.len() {
+                    let (size, align) = ARGUMENTS[i];
+                    if align >= 8 {
+                        registers += registers % 2;
+                    }
+                    registers += size.div_ceil(4);
+                    i += 1;
+                }
+                assert!(registers <= #ARGUMENT_REGISTERS, #message);
+            };
+        });
+    }
+
+    if let syn::ReturnType::Type(_, ty) = &signature.output {
+        match return_footprint(ty) {
+            Ok(Footprint::Known(_)) => {}
+            Ok(Footprint::Unknown) => {
+                let function_name = signature.ident.to_string();
+                let message = format!(
+                    "The return type of '{function_name}' is bigger than 4 bytes, so it would be returned through memory"
+                );
+                let raw = passed_type(ty, runtime);
+                assertions.extend(quote_spanned! {ty.span()=>
+                    const _: () = assert!(core::mem::size_of::<#raw>() <= 4, #message);
+                });
+            }
+            Err(e) => errors.push(e),
+        }
+    }
+
+    match errors.into_iter().reduce(|mut combined, e| {
+        combined.combine(e);
+        combined
+    }) {
+        Some(e) => Err(e),
+        None => Ok(assertions),
+    }
+}
+
+/// The type a value of a type we don't know the layout of is passed as, which is the `Raw` type
+/// of its `Boundary` implementation if it's checked
+fn passed_type(ty: &syn::Type, runtime: &TokenStream) -> syn::Type {
+    Lowering::of(ty).lowered_type(ty, runtime)
+}
+
+/// The footprint of a value that is passed as an argument, so after it has been lowered
+fn argument_footprint(ty: &syn::Type) -> Result<Footprint, syn::Error> {
+    match Lowering::of(ty) {
+        Lowering::Identity | Lowering::Checked(_) => type_footprint(ty),
+        Lowering::Bool | Lowering::Char => Ok(Footprint::Known(Layout::new(4, 4))),
+        Lowering::OptionNonZero { integer, .. } => Ok(Footprint::Known(
+            primitive_layout(&integer.to_string()).unwrap(),
+        )),
+    }
+}
+
+/// The footprint of the return value. Only primitives are returned in r0 and r1,
+/// composite types bigger than 4 bytes are returned through memory.
+fn return_footprint(ty: &syn::Type) -> Result<Footprint, syn::Error> {
+    let footprint = argument_footprint(ty)?;
+
+    match (unparenthesize(ty), footprint) {
+        (syn::Type::Array(_), Footprint::Known(layout)) if layout.size > 4 => {
+            Err(syn::Error::new_spanned(
+                ty,
+                "This return type is bigger than 4 bytes, so it would be returned through memory. \
+                Use an out pointer argument instead.",
+            ))
+        }
+        (_, footprint) => Ok(footprint),
+    }
+}
+
+fn type_footprint(ty: &syn::Type) -> Result<Footprint, syn::Error> {
+    let pointer = Footprint::Known(Layout::new(4, 4));
+
+    match unparenthesize(ty) {
+        syn::Type::Ptr(syn::TypePtr { elem, .. })
+        | syn::Type::Reference(syn::TypeReference { elem, .. }) => {
+            if is_unsized(elem) {
+                Err(syn::Error::new_spanned(
+                    ty,
+                    "A pointer to an unsized type can't cross the security boundary. \
+                    Pass a thin pointer and a length as separate arguments instead.",
+                ))
+            } else {
+                Ok(pointer)
+            }
+        }
+        syn::Type::BareFn(_) | syn::Type::Never(_) => Ok(pointer),
+        syn::Type::Tuple(tuple) if tuple.elems.is_empty() => {
+            Ok(Footprint::Known(Layout::new(0, 1)))
+        }
+        syn::Type::Tuple(_) => Err(syn::Error::new_spanned(
+            ty,
+            "Tuples don't have a stable layout and can't cross the security boundary. \
+            Use a #[repr(C)] struct instead.",
+        )),
+        syn::Type::Array(array) => {
+            let elem = match type_footprint(&array.elem)? {
+                Footprint::Known(layout) => layout,
+                Footprint::Unknown => return Ok(Footprint::Unknown),
+            };
+            let len = match &array.len {
+                syn::Expr::Lit(syn::ExprLit {
+                    lit: syn::Lit::Int(len),
+                    ..
+                }) => len.base10_parse::<usize>()?,
+                _ => return Ok(Footprint::Unknown),
+            };
+
+            Ok(Footprint::Known(Layout::new(elem.size * len, elem.align)))
+        }
+        syn::Type::ImplTrait(_) | syn::Type::TraitObject(_) | syn::Type::Infer(_) => {
+            Err(syn::Error::new_spanned(
+                ty,
+                "Only concrete FFI-safe types can cross the security boundary",
+            ))
+        }
+        syn::Type::Path(type_path) => {
+            let Some(last_segment) = type_path.path.segments.last() else {
+                return Ok(Footprint::Unknown);
+            };
+            let name = last_segment.ident.to_string();
+
+            if let Some(primitive) = primitive_name(&type_path.path) {
+                return match primitive {
+                    "bool" => Ok(Footprint::Known(Layout::new(1, 1))),
+                    "char" => Ok(Footprint::Known(Layout::new(4, 4))),
+                    "u128" | "i128" => Err(syn::Error::new_spanned(
+                        ty,
+                        "128-bit integers don't fit in the registers and can't cross the security boundary",
+                    )),
+                    _ => Ok(Footprint::Known(primitive_layout(primitive).unwrap())),
+                };
+            }
+            if type_path.path.is_ident("str") {
+                return Err(syn::Error::new_spanned(
+                    ty,
+                    "Only concrete FFI-safe types can cross the security boundary",
+                ));
+            }
+
+            match name.as_str() {
+                "String" | "Vec" | "Box" | "Rc" | "Arc" | "Cow" | "HashMap" | "BTreeMap"
+                | "VecDeque" => Err(syn::Error::new_spanned(
+                    ty,
+                    format!(
+                        "`{name}` owns memory of one of the worlds and can't cross the security boundary"
+                    ),
+                )),
+                "NonNull" => Ok(pointer),
+                _ if non_zero_integer(&last_segment.ident).is_some() => Ok(Footprint::Known(
+                    primitive_layout(non_zero_integer(&last_segment.ident).unwrap()).unwrap(),
+                )),
+                "PhantomData" => Ok(Footprint::Known(Layout::new(0, 1))),
+                "Option" => {
+                    let inner = single_generic_argument(last_segment);
+                    let nullable = inner.is_some_and(|inner| match unparenthesize(inner) {
+                        syn::Type::Reference(_) | syn::Type::BareFn(_) => true,
+                        syn::Type::Path(inner_path) => {
+                            inner_path.path.segments.last().is_some_and(|segment| {
+                                segment.ident == "NonNull"
+                                    || non_zero_integer(&segment.ident).is_some()
+                            })
+                        }
+                        _ => false,
+                    });
+
+                    if nullable {
+                        type_footprint(inner.unwrap())
+                    } else {
+                        Err(syn::Error::new_spanned(
+                            ty,
+                            "`Option` can only cross the security boundary when it wraps a reference, \
+                            a function pointer, `NonNull` or a `NonZero` integer",
+                        ))
+                    }
+                }
+                _ => Ok(Footprint::Unknown),
+            }
+        }
+        _ => Ok(Footprint::Unknown),
+    }
+}
+
+fn primitive_layout(name: &str) -> Option<Layout> {
+    Some(match name {
+        "u8" | "i8" => Layout::new(1, 1),
+        "u16" | "i16" => Layout::new(2, 2),
+        "u32" | "i32" | "usize" | "isize" | "f32" => Layout::new(4, 4),
+        "u64" | "i64" | "f64" => Layout::new(8, 8),
+        _ => return None,
+    })
+}
+
+fn is_unsized(ty: &syn::Type) -> bool {
+    match unparenthesize(ty) {
+        syn::Type::Slice(_) | syn::Type::TraitObject(_) => true,
+        syn::Type::Path(type_path) => type_path.path.is_ident("str"),
+        _ => false,
+    }
+}
+
+/// Checks that the type of a `nonsecure_shared` static can live in memory the nonsecure world can
+/// write to. That rules out types with invalid bit patterns and pointers, because the nonsecure
+/// world could make the secure world read something it isn't supposed to.
+///
+/// Integers, atomic integers and arrays of them are always fine. Any other type has to implement
+/// the `AnyBitPattern` trait of the runtime, which is checked by the returned const assertion.
+pub(crate) fn check_shared_static_type(
+    ty: &syn::Type,
+    runtime: &TokenStream,
+) -> Result<TokenStream, syn::Error> {
+    if is_shared_integer(ty)? {
+        return Ok(TokenStream::new());
+    }
+
+    Ok(quote_spanned! {ty.span()=>
+        const _: () = {
+            const fn assert_any_bit_pattern<T: ?Sized + #runtime::AnyBitPattern>() {}
+            assert_any_bit_pattern::<#ty>();
+        };
+    })
+}
+
+/// Returns true if the type is an integer, an atomic integer or an array of them. Types that can
+/// never be shared are an error.
+fn is_shared_integer(ty: &syn::Type) -> Result<bool, syn::Error> {
+    let invalid_values_error = || {
+        Err(syn::Error::new_spanned(
+            ty,
+            "The nonsecure world could write an invalid value to this type. \
+            Use an integer or an atomic integer instead.",
+        ))
+    };
+    let pointer_error = || {
+        Err(syn::Error::new_spanned(
+            ty,
+            "The nonsecure world could change a shared pointer to point to secure memory. \
+            Share the data itself instead.",
+        ))
+    };
+
+    match unparenthesize(ty) {
+        syn::Type::Ptr(_) | syn::Type::Reference(_) | syn::Type::BareFn(_) => pointer_error(),
+        syn::Type::Array(array) => is_shared_integer(&array.elem),
+        syn::Type::Path(type_path) => {
+            let Some(last_segment) = type_path.path.segments.last() else {
+                return Ok(false);
+            };
+            let name = last_segment.ident.to_string();
+
+            match name.as_str() {
+                "NonNull" | "AtomicPtr" => pointer_error(),
+                "bool" | "char" | "AtomicBool" | "Option" => invalid_values_error(),
+                _ if non_zero_integer(&last_segment.ident).is_some() => invalid_values_error(),
+                "u8" | "u16" | "u32" | "u64" | "usize" | "i8" | "i16" | "i32" | "i64" | "isize"
+                    if type_path.path.segments.len() == 1 =>
+                {
+                    Ok(true)
+                }
+                "AtomicU8" | "AtomicU16" | "AtomicU32" | "AtomicU64" | "AtomicUsize" | "AtomicI8"
+                | "AtomicI16" | "AtomicI32" | "AtomicI64" | "AtomicIsize" => Ok(true),
+                _ => type_footprint(ty).map(|_| false),
+            }
+        }
+        _ => type_footprint(ty).map(|_| false),
+    }
+}
+
+/// The hash of the canonical form of a shared static, which is stored in its veneer in place of
+/// the signature hash of a function
+pub(crate) fn static_hash(ty: &syn::Type, mutable: bool) -> u32 {
+    match crate::hash_vector_name(&canonical_static(ty, mutable)) {
+        0 => 1,
+        hash => hash,
+    }
+}
+
+/// The textual form of a shared static that [static_hash] hashes, e.g. `static mut [AtomicU32;4]`
+pub(crate) fn canonical_static(ty: &syn::Type, mutable: bool) -> String {
+    let mutability = if mutable { "mut " } else { "" };
+
+    format!("static {mutability}{}", canonical_type(ty))
+}
+
+/// The hash of the canonical form of the signature, which is stored next to the id of a veneer.
+/// A lookup only succeeds when both match, so two images that were built from different versions
+/// of a function can't call each other with the wrong arguments.
+///
+/// 0 is never returned, because that is used to look up a veneer regardless of its signature.
+pub(crate) fn signature_hash(signature: &syn::Signature) -> u32 {
+    match crate::hash_vector_name(&canonical_signature(signature)) {
+        0 => 1,
+        hash => hash,
+    }
+}
+
+/// A textual form of the signature that doesn't depend on formatting, argument names, lifetimes or
+/// how the types are imported, e.g. `extern "C" fn(u32,*const u8)->bool`.
+pub(crate) fn canonical_signature(signature: &syn::Signature) -> String {
+    let abi = if is_extern_c(signature) {
+        "extern \"C\" "
+    } else {
+        ""
+    };
+    let inputs = signature
+        .inputs
+        .iter()
+        .filter_map(|input| match input {
+            syn::FnArg::Typed(t) => Some(canonical_type(&t.ty)),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join(",");
+    let output = match &signature.output {
+        syn::ReturnType::Default => String::new(),
+        syn::ReturnType::Type(_, ty) => format!("->{}", canonical_type(ty)),
+    };
+
+    format!("{abi}fn({inputs}){output}")
+}
+
+fn canonical_type(ty: &syn::Type) -> String {
+    match unparenthesize(ty) {
+        syn::Type::Path(type_path) => match type_path.path.segments.last() {
+            Some(segment) => {
+                let arguments = match &segment.arguments {
+                    syn::PathArguments::AngleBracketed(arguments) => {
+                        let arguments = arguments
+                            .args
+                            .iter()
+                            .filter_map(|argument| match argument {
+                                syn::GenericArgument::Type(ty) => Some(canonical_type(ty)),
+                                syn::GenericArgument::Lifetime(_) => None,
+                                argument => Some(canonical_tokens(argument)),
+                            })
+                            .collect::<Vec<_>>();
+                        if arguments.is_empty() {
+                            String::new()
+                        } else {
+                            format!("<{}>", arguments.join(","))
+                        }
+                    }
+                    _ => String::new(),
+                };
+                format!("{}{arguments}", segment.ident)
+            }
+            None => canonical_tokens(type_path),
+        },
+        syn::Type::Ptr(pointer) => format!(
+            "*{} {}",
+            if pointer.mutability.is_some() {
+                "mut"
+            } else {
+                "const"
+            },
+            canonical_type(&pointer.elem)
+        ),
+        syn::Type::Reference(reference) => format!(
+            "&{}{}",
+            if reference.mutability.is_some() {
+                "mut "
+            } else {
+                ""
+            },
+            canonical_type(&reference.elem)
+        ),
+        syn::Type::Array(array) => format!(
+            "[{};{}]",
+            canonical_type(&array.elem),
+            canonical_tokens(&array.len)
+        ),
+        syn::Type::Slice(slice) => format!("[{}]", canonical_type(&slice.elem)),
+        syn::Type::Tuple(tuple) => format!(
+            "({})",
+            tuple
+                .elems
+                .iter()
+                .map(canonical_type)
+                .collect::<Vec<_>>()
+                .join(",")
+        ),
+        ty => canonical_tokens(ty),
+    }
+}
+
+fn canonical_tokens(tokens: &impl ToTokens) -> String {
+    tokens
+        .to_token_stream()
+        .to_string()
+        .split_whitespace()
+        .collect()
+}
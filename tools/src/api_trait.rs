@@ -0,0 +1,371 @@
+//! A trait for the functions of the other side, so the app logic can be tested on the host.
+//!
+//! The bindings only compile for the Arm target, because they call the other side through veneers.
+//! Code that is generic over the API trait can use the veneers on the device and the generated mock
+//! in tests on the host. The mock answers every call with a closure that the test gives it in
+//! advance, in the order the calls are expected.
+
+use proc_macro2::Span;
+use syn::Attribute;
+
+/// The cargo feature of the crate that includes the bindings that enables the mock outside of its
+/// own tests
+pub(crate) const MOCK_FEATURE: &str = "trustzone-mock";
+
+/// A binding of an exported function that gets a method in the trait
+pub(crate) struct ApiFunction {
+    /// The path of the module the function is in, relative to the crate root
+    pub(crate) module_path: Vec<String>,
+    /// The binding that panics if the veneer can't be used
+    pub(crate) binding: syn::ItemFn,
+}
+
+/// The attribute that limits the items that use the veneers to the Arm target
+pub(crate) fn target_cfg() -> Attribute {
+    syn::parse_quote!(#[cfg(target_arch = "arm")])
+}
+
+/// Generates the API trait, its implementation that uses the veneers and its mock.
+///
+/// The trait is `SecureApi` in the bindings of the nonsecure app and `NonSecureApi` in the bindings
+/// of the secure app. The methods are named after the path of the function, so functions with the
+/// same name in different modules don't collide.
+///
+/// The implementation that uses the veneers is only compiled for the Arm target, unless the
+/// bindings call the other app directly on the host in the simulation.
+pub(crate) fn generate_api_trait(
+    functions: &[ApiFunction],
+    secure: bool,
+    simulation: bool,
+) -> Vec<syn::Item> {
+    let trait_ident = if secure {
+        syn::Ident::new("NonSecureApi", Span::call_site())
+    } else {
+        syn::Ident::new("SecureApi", Span::call_site())
+    };
+    let mock_ident = quote::format_ident!("Mock{}", trait_ident);
+    let world = if secure { "nonsecure" } else { "secure" };
+
+    let methods = functions.iter().map(ApiMethod::new).collect::<Vec<_>>();
+
+    let trait_methods = methods.iter().map(|method| {
+        let signature = &method.signature;
+        let docs = &method.docs;
+        quote::quote! {
+            #(#docs)*
+            #signature;
+        }
+    });
+    let trait_doc = format!(" The functions of the {world} app, with a method per function");
+    let api_trait: syn::Item = syn::parse_quote! {
+        #[doc = #trait_doc]
+        pub trait #trait_ident {
+            #(#trait_methods)*
+        }
+    };
+
+    let target_cfg = (!simulation).then(target_cfg);
+    let veneer_methods = methods.iter().map(|method| {
+        let signature = &method.signature;
+        let path = &method.path;
+        let arguments = &method.arguments;
+        let call: syn::Expr = if signature.unsafety.is_some() {
+            syn::parse_quote!(unsafe { #path(#(#arguments),*) })
+        } else {
+            syn::parse_quote!(#path(#(#arguments),*))
+        };
+        let allow_deprecated = method
+            .deprecated
+            .then(|| quote::quote!(#[allow(deprecated)]));
+        quote::quote! {
+            #allow_deprecated
+            #signature {
+                #call
+            }
+        }
+    });
+    let veneers_doc = format!(" Calls the functions of the {world} app through the veneers");
+    let veneers_struct: syn::Item = syn::parse_quote! {
+        #[doc = #veneers_doc]
+        #target_cfg
+        #[derive(Debug, Clone, Copy, Default)]
+        pub struct Veneers;
+    };
+    let veneers_impl: syn::Item = syn::parse_quote! {
+        #target_cfg
+        impl #trait_ident for Veneers {
+            #(#veneer_methods)*
+        }
+    };
+
+    let mock_fields = methods.iter().map(|method| {
+        let ident = &method.signature.ident;
+        let closure_type = &method.closure_type;
+        quote::quote!(#ident: Expectations<dyn #closure_type>)
+    });
+    let mock_expectations = methods.iter().map(|method| {
+        let ident = &method.signature.ident;
+        let expect_ident = quote::format_ident!("expect_{}", ident);
+        let calls_ident = quote::format_ident!("{}_calls", ident);
+        let closure_type = &method.closure_type;
+        let expect_doc = format!(
+            " Expects a call of `{}`, which is answered by the closure",
+            method.path_string
+        );
+        let calls_doc = format!(
+            " The number of times `{}` has been called",
+            method.path_string
+        );
+        quote::quote! {
+            #[doc = #expect_doc]
+            pub fn #expect_ident(&mut self, answer: impl #closure_type + 'static) -> &mut Self {
+                self.#ident.answers.get_mut().push_back(Box::new(answer));
+                self
+            }
+
+            #[doc = #calls_doc]
+            pub fn #calls_ident(&self) -> usize {
+                self.#ident.calls.get()
+            }
+        }
+    });
+    let mock_checks = methods.iter().map(|method| {
+        let ident = &method.signature.ident;
+        let path_string = &method.path_string;
+        quote::quote!(self.#ident.check(#path_string);)
+    });
+    let mock_methods = methods.iter().map(|method| {
+        let signature = &method.signature;
+        let ident = &signature.ident;
+        let path_string = &method.path_string;
+        let arguments = &method.arguments;
+        quote::quote! {
+            #signature {
+                let answer = self.#ident.next(#path_string);
+                answer(#(#arguments),*)
+            }
+        }
+    });
+    let mock_cfg: Attribute = syn::parse_quote!(#[cfg(any(test, feature = #MOCK_FEATURE))]);
+    let mock_doc = format!(" A mock of [`{trait_ident}`] for tests on the host");
+    let mock_module: syn::Item = syn::parse_quote! {
+        /// The mock of the API trait
+        #mock_cfg
+        // Tests don't necessarily use all of it
+        #[allow(dead_code)]
+        pub mod mock {
+            extern crate std;
+
+            #[allow(unused_imports)]
+            use super::*;
+            use std::{boxed::Box, collections::VecDeque};
+
+            /// The answers to the expected calls of a function
+            struct Expectations<F: ?Sized> {
+                answers: core::cell::RefCell<VecDeque<Box<F>>>,
+                calls: core::cell::Cell<usize>,
+            }
+
+            impl<F: ?Sized> Default for Expectations<F> {
+                fn default() -> Self {
+                    Self {
+                        answers: Default::default(),
+                        calls: Default::default(),
+                    }
+                }
+            }
+
+            impl<F: ?Sized> Expectations<F> {
+                fn next(&self, path: &str) -> Box<F> {
+                    self.calls.set(self.calls.get() + 1);
+                    match self.answers.borrow_mut().pop_front() {
+                        Some(answer) => answer,
+                        None => panic!("Unexpected call of `{}`", path),
+                    }
+                }
+
+                fn check(&self, path: &str) {
+                    let missing = self.answers.borrow().len();
+                    if missing != 0 {
+                        panic!("`{}` was expected to be called {} more times", path, missing);
+                    }
+                }
+            }
+
+            #[doc = #mock_doc]
+            ///
+            /// Every call is answered by the next closure that was given to the `expect_` function
+            /// of the method. Calls that aren't expected panic, and so does dropping the mock
+            /// while expected calls are missing.
+            #[derive(Default)]
+            pub struct #mock_ident {
+                #(#mock_fields,)*
+            }
+
+            impl #mock_ident {
+                /// Creates a mock that doesn't expect any calls yet
+                pub fn new() -> Self {
+                    Self::default()
+                }
+
+                #(#mock_expectations)*
+
+                /// Panics if an expected call hasn't happened
+                pub fn checkpoint(&self) {
+                    #(#mock_checks)*
+                }
+            }
+
+            impl Drop for #mock_ident {
+                fn drop(&mut self) {
+                    if !std::thread::panicking() {
+                        self.checkpoint();
+                    }
+                }
+            }
+
+            impl #trait_ident for #mock_ident {
+                #(#mock_methods)*
+            }
+        }
+    };
+
+    vec![api_trait, veneers_struct, veneers_impl, mock_module]
+}
+
+/// A method of the API trait
+struct ApiMethod {
+    /// The signature of the method, which takes `&self`
+    signature: syn::Signature,
+    /// The doc comments of the binding
+    docs: Vec<Attribute>,
+    /// Whether the binding is deprecated
+    deprecated: bool,
+    /// The path of the binding, relative to the bindings module
+    path: syn::Path,
+    /// The path as text, for the messages of the mock
+    path_string: String,
+    /// The names of the arguments
+    arguments: Vec<syn::Ident>,
+    /// The `FnOnce` trait of the closures that answer the calls in the mock
+    closure_type: syn::TypeParamBound,
+}
+
+impl ApiMethod {
+    fn new(function: &ApiFunction) -> Self {
+        let binding = &function.binding;
+        let binding_ident = &binding.sig.ident;
+
+        let method_ident = quote::format_ident!(
+            "{}",
+            function
+                .module_path
+                .iter()
+                .cloned()
+                .chain([binding_ident.to_string()])
+                .collect::<Vec<_>>()
+                .join("_")
+        );
+        let module_idents = function
+            .module_path
+            .iter()
+            .map(|name| syn::Ident::new(name, Span::call_site()));
+        let path: syn::Path = syn::parse_quote!(#(#module_idents::)*#binding_ident);
+        let path_string = function
+            .module_path
+            .iter()
+            .cloned()
+            .chain([binding_ident.to_string()])
+            .collect::<Vec<_>>()
+            .join("::");
+
+        // The arguments of the bindings always have a name, but may be mutable
+        let mut arguments = Vec::new();
+        let mut argument_types = Vec::new();
+        let mut inputs = syn::punctuated::Punctuated::<syn::FnArg, syn::Token![,]>::new();
+        inputs.push(syn::parse_quote!(&self));
+        for input in binding.sig.inputs.iter() {
+            let syn::FnArg::Typed(input) = input else {
+                continue;
+            };
+            let syn::Pat::Ident(pat) = input.pat.as_ref() else {
+                continue;
+            };
+            let ident = &pat.ident;
+            let ty = &input.ty;
+            inputs.push(syn::parse_quote!(#ident: #ty));
+            arguments.push(ident.clone());
+            argument_types.push(ty.as_ref().clone());
+        }
+
+        let mut signature = binding.sig.clone();
+        signature.ident = method_ident;
+        signature.abi = None;
+        signature.inputs = inputs;
+
+        // Lifetimes of the signature have to be bound by the closure type of the mock
+        let lifetimes = binding
+            .sig
+            .generics
+            .lifetimes()
+            .map(|lifetime| &lifetime.lifetime)
+            .collect::<Vec<_>>();
+        let for_lifetimes = (!lifetimes.is_empty()).then(|| quote::quote!(for<#(#lifetimes),*>));
+        let output = &binding.sig.output;
+        let closure_type = syn::parse_quote!(#for_lifetimes FnOnce(#(#argument_types),*) #output);
+
+        Self {
+            signature,
+            docs: binding
+                .attrs
+                .iter()
+                .filter(|attr| attr.path().is_ident("doc"))
+                .cloned()
+                .collect(),
+            deprecated: binding
+                .attrs
+                .iter()
+                .any(|attr| attr.path().is_ident("deprecated")),
+            path,
+            path_string,
+            arguments,
+            closure_type,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_api_trait() {
+        let functions = [
+            ApiFunction {
+                module_path: vec![],
+                binding: syn::parse_quote! {
+                    /// Adds two numbers
+                    pub extern "C" fn add(a: u32, mut b: u32) -> u32 {}
+                },
+            },
+            ApiFunction {
+                module_path: vec![String::from("storage")],
+                binding: syn::parse_quote! {
+                    #[deprecated]
+                    pub unsafe fn read<'a>(key: &'a [u8], buffer: *mut u8) -> Option<&'a u32> {}
+                },
+            },
+        ];
+
+        let file = syn::File {
+            shebang: None,
+            attrs: Vec::new(),
+            items: generate_api_trait(&functions, false, false),
+        };
+
+        pretty_assertions::assert_eq!(
+            prettyplease::unparse(&file),
+            include_str!("../test-sources/api_trait_result.txt").replace("\r\n", "\n")
+        );
+    }
+}
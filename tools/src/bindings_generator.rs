@@ -1,151 +1,745 @@
+use anyhow::Context;
 use proc_macro2::Span;
 use std::{
     fs,
     io::Write,
-    iter::FromIterator,
     ops::Deref,
     path::{Path, PathBuf},
 };
-use syn::{punctuated::Punctuated, Attribute, PathSegment};
+use syn::{
+    ext::IdentExt,
+    visit_mut::{self, VisitMut},
+    Attribute,
+};
+
+use crate::{
+    abi,
+    api_trait::{self, ApiFunction},
+    c_bindings,
+    cfg::CfgSet,
+    diagnostics::{BindingsError, ExportedItem, SourceLocation},
+    export_attributes::ExportAttributes,
+    import_library, lock_file,
+    manifest::{self, Manifest, ManifestItem, ManifestItemKind},
+    metadata,
+    secure_image::{self, SecureImageVeneer},
+    simulation,
+    type_paths::ModuleScope,
+};
 
+/// How the generated bindings find the veneers of the other side
+pub enum VeneerLookup {
+    /// Search the veneer table at runtime for the id and signature hash of the function
+    Search,
+    /// Link against the veneer addresses of an already linked secure image.
+    ///
+    /// This is only possible for the nonsecure side. The veneers are provided as symbols in the
+    /// `<module name>_veneers.x` linker script next to the bindings, which is added to the link
+    /// arguments.
+    /// Functions the secure image doesn't export or exports with a different signature are
+    /// reported as an error.
+    SecureImage(PathBuf),
+}
+
+/// Generates the bindings to the items the other side exports with the default settings of
+/// [BindingsBuilder].
 pub fn generate_bindings<P: AsRef<Path>>(
     module_file_path: P,
     secure: bool,
 ) -> Result<(), anyhow::Error> {
-    let out_dir = std::env::var("OUT_DIR").unwrap();
-
-    fn generate_bindings_inner<P: AsRef<Path>>(
-        module_file_path: P,
-        secure: bool,
-        generated_items: &mut Vec<(syn::Item, String, u32)>,
-    ) -> Result<(), anyhow::Error> {
-        println!(
-            "cargo:rerun-if-changed={}",
-            module_file_path.as_ref().display()
-        );
+    BindingsBuilder::new(module_file_path, secure).generate()
+}
 
-        // Read the source code file
-        let file_text = std::fs::read_to_string(module_file_path.as_ref())?;
+/// The settings of the bindings generator
+pub struct BindingsBuilder {
+    source: BindingsSource,
+    secure: bool,
+    manifest_path: Option<PathBuf>,
+    c_bindings_paths: Option<(PathBuf, PathBuf)>,
+    output_path: Option<PathBuf>,
+    module_name: String,
+    visibility: String,
+    emit_glue: bool,
+    veneer_lookup: VeneerLookup,
+    cfg: Option<CfgSet>,
+    generate_docs: bool,
+    propagated_attributes: Vec<String>,
+    generate_api_trait: bool,
+    simulation_peer: Option<String>,
+    lock_file_path: Option<PathBuf>,
+    own_source_root: Option<PathBuf>,
+    update_lock_file: bool,
+    allow_breaking_changes: bool,
+}
 
-        // Parse the file
-        let file = syn::parse_file(&file_text)?;
+impl BindingsBuilder {
+    /// Creates the generator of the bindings to the items the crate with the given root file exports.
+    ///
+    /// When the bindings are for the secure app, the source root is the root of the nonsecure app
+    /// and vice versa.
+    pub fn new<P: AsRef<Path>>(source_root: P, secure: bool) -> Self {
+        Self::with_source(
+            BindingsSource::Crate(source_root.as_ref().to_path_buf()),
+            secure,
+        )
+    }
 
-        // Generate the bindings to the file
-        let child_modules = generate_file_bindings(file, generated_items)?;
+    /// Creates the generator of the bindings to the items in the given manifest, which the other
+    /// side wrote with [BindingsBuilder::write_manifest].
+    pub fn from_manifest<P: AsRef<Path>>(manifest_path: P, secure: bool) -> Self {
+        Self::with_source(
+            BindingsSource::Manifest(manifest_path.as_ref().to_path_buf()),
+            secure,
+        )
+    }
 
-        // Continue reading other modules
-        for module in child_modules {
-            // Is this a module that refers to another file?
-            if module.content.is_some() {
-                // No, so let's continue
-                continue;
+    /// Creates the generator of the nonsecure bindings to a secure image that isn't built with this
+    /// crate, from its CMSE import library and the header with the prototypes of its functions.
+    ///
+    /// The veneers of such an image don't have an id and a signature hash, so the bindings are
+    /// always linked against the veneer addresses in the import library, like with
+    /// [VeneerLookup::SecureImage]. Functions without a prototype that can be translated to Rust
+    /// are skipped with a warning.
+    pub fn from_import_library<P: AsRef<Path>, Q: AsRef<Path>>(
+        import_library_path: P,
+        header_path: Q,
+    ) -> Self {
+        Self::with_source(
+            BindingsSource::ImportLibrary {
+                library: import_library_path.as_ref().to_path_buf(),
+                header: header_path.as_ref().to_path_buf(),
+            },
+            false,
+        )
+    }
+
+    /// Creates the generator of the bindings to the items that the given ELF file or static library
+    /// of the other side exports.
+    ///
+    /// The items are read from the metadata records the macros put in the `.trustzone_meta`
+    /// section, so this also finds items that are generated by other macros or that are in
+    /// dependencies. The image has to be built before the bindings are generated.
+    pub fn from_elf<P: AsRef<Path>>(elf_path: P, secure: bool) -> Self {
+        Self::with_source(BindingsSource::Elf(elf_path.as_ref().to_path_buf()), secure)
+    }
+
+    fn with_source(source: BindingsSource, secure: bool) -> Self {
+        Self {
+            source,
+            secure,
+            manifest_path: None,
+            c_bindings_paths: None,
+            output_path: None,
+            module_name: String::from("trustzone_bindings"),
+            visibility: String::from("pub"),
+            emit_glue: true,
+            veneer_lookup: VeneerLookup::Search,
+            cfg: None,
+            generate_docs: true,
+            propagated_attributes: DEFAULT_PROPAGATED_ATTRIBUTES
+                .iter()
+                .map(|name| name.to_string())
+                .collect(),
+            generate_api_trait: false,
+            simulation_peer: None,
+            lock_file_path: None,
+            own_source_root: None,
+            update_lock_file: false,
+            allow_breaking_changes: false,
+        }
+    }
+
+    /// The file the bindings are written to. Defaults to `$OUT_DIR/<module name>.rs`.
+    pub fn output_path<P: AsRef<Path>>(mut self, output_path: P) -> Self {
+        self.output_path = Some(output_path.as_ref().to_path_buf());
+        self
+    }
+
+    /// The name of the module the bindings are in. Defaults to `trustzone_bindings`.
+    pub fn module_name(mut self, module_name: impl Into<String>) -> Self {
+        self.module_name = module_name.into();
+        self
+    }
+
+    /// The visibility of the module the bindings are in, like `pub(crate)`. Defaults to `pub`.
+    pub fn visibility(mut self, visibility: impl Into<String>) -> Self {
+        self.visibility = visibility.into();
+        self
+    }
+
+    /// Whether the functions and veneers that find the veneers of the other side are generated.
+    /// Defaults to true.
+    ///
+    /// They may only exist once in an app. When the app includes multiple bindings files, only one
+    /// of them may have the glue and all of them have to be included in the same module.
+    pub fn emit_glue(mut self, emit_glue: bool) -> Self {
+        self.emit_glue = emit_glue;
+        self
+    }
+
+    /// How the bindings find the veneers of the other side. Defaults to [VeneerLookup::Search].
+    pub fn veneer_lookup(mut self, veneer_lookup: VeneerLookup) -> Self {
+        self.veneer_lookup = veneer_lookup;
+        self
+    }
+
+    /// The cfg options the `#[cfg(...)]` attributes of the scanned crate are evaluated against.
+    /// Defaults to [CfgSet::from_target_env], so no features of the scanned crate are enabled.
+    /// The features of the crate of the build script are not the features of the scanned crate.
+    pub fn cfg(mut self, cfg: CfgSet) -> Self {
+        self.cfg = Some(cfg);
+        self
+    }
+
+    /// Also writes a JSON manifest of the exported items to the given path.
+    ///
+    /// The manifest lists the path, veneer id, direction and signature of every item. The other
+    /// side can generate its bindings from it with [BindingsBuilder::from_manifest].
+    ///
+    /// The proc macros don't write a manifest, because they don't know the module they're in and
+    /// incremental builds don't expand them again for code that didn't change. A manifest of what was
+    /// actually compiled can be written from the records of the macros in the built image instead,
+    /// with [BindingsBuilder::from_elf].
+    pub fn write_manifest<P: AsRef<Path>>(mut self, manifest_path: P) -> Self {
+        self.manifest_path = Some(manifest_path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Also writes C bindings of the exported items to the given header and source file, for a
+    /// nonsecure app that is written in C.
+    ///
+    /// The functions the secure app exports can be called through them and the functions of the
+    /// C app that the secure app calls are put in its `.ns_veneers` table. The source file has the
+    /// initializer veneer unless [BindingsBuilder::emit_glue] is turned off.
+    pub fn write_c_bindings<P: AsRef<Path>, Q: AsRef<Path>>(
+        mut self,
+        header_path: P,
+        source_path: Q,
+    ) -> Self {
+        self.c_bindings_paths = Some((
+            header_path.as_ref().to_path_buf(),
+            source_path.as_ref().to_path_buf(),
+        ));
+        self
+    }
+
+    /// Whether the generated items get doc comments. Defaults to true.
+    pub fn generate_docs(mut self, generate_docs: bool) -> Self {
+        self.generate_docs = generate_docs;
+        self
+    }
+
+    /// The attributes of the exported items that the bindings get as well, by their path.
+    /// Defaults to `doc`, `must_use`, `deprecated` and `inline`.
+    ///
+    /// The doc comments of a binding end with the veneer id and where the item is defined, even
+    /// when `doc` isn't in the list.
+    pub fn propagate_attributes<I, S>(mut self, attributes: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.propagated_attributes = attributes.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Whether a trait with a method per exported function is generated, so app logic that is
+    /// generic over it can be tested on the host. Defaults to false.
+    ///
+    /// The trait is `SecureApi` in the bindings of the nonsecure app and `NonSecureApi` in the
+    /// bindings of the secure app. `Veneers` implements it with the bindings and the `mock` module
+    /// has a mock implementation, which is compiled in tests and when the `trustzone-mock` feature
+    /// of the app is enabled. The bindings themselves are then only compiled for the Arm target.
+    pub fn generate_api_trait(mut self, generate_api_trait: bool) -> Self {
+        self.generate_api_trait = generate_api_trait;
+        self
+    }
+
+    /// The name of the crate of the other app, which the bindings call directly when they aren't
+    /// built for the Arm target. Defaults to none, which means the bindings only build for Arm.
+    ///
+    /// This simulates both worlds on the host, so both apps can be linked into one test binary.
+    /// The macros export the items for the simulation when they aren't built for Arm either.
+    /// The test binary has to use the crate of the other app, for example with
+    /// `use secure_app as _;`, and items it doesn't export with the same signature are link errors.
+    pub fn simulation_peer(mut self, crate_name: impl Into<String>) -> Self {
+        self.simulation_peer = Some(crate_name.into());
+        self
+    }
+
+    /// Checks the exported items against the lock file at the given path, which records the
+    /// interface between the two apps. Defaults to none.
+    ///
+    /// The lock file should be committed and shared by the builds of both apps. Each build checks
+    /// the items it generates bindings for and the items its own crate exports. Any difference
+    /// fails the build with a diff, unless the lock file is updated.
+    pub fn lock_file<P: AsRef<Path>>(mut self, lock_file_path: P) -> Self {
+        self.lock_file_path = Some(lock_file_path.as_ref().to_path_buf());
+        self
+    }
+
+    /// The root file of the crate the bindings are for, whose exports are checked against the lock
+    /// file as well. Defaults to `src/main.rs` or `src/lib.rs` of the crate of the build script.
+    /// It's the crate that is being built, so its cfg options are [CfgSet::from_env].
+    pub fn own_source_root<P: AsRef<Path>>(mut self, own_source_root: P) -> Self {
+        self.own_source_root = Some(own_source_root.as_ref().to_path_buf());
+        self
+    }
+
+    /// Whether the lock file is written with the interface of this build instead of failing the
+    /// build when they differ. The lock file is created if it doesn't exist. Defaults to false.
+    ///
+    /// The lock file is also updated when the `TRUSTZONE_UPDATE_LOCK` environment variable is set to
+    /// anything but `0`, so it can be updated without changing the build script.
+    pub fn update_lock_file(mut self, update_lock_file: bool) -> Self {
+        self.update_lock_file = update_lock_file;
+        self
+    }
+
+    /// Whether breaking changes of the interface are written when the lock file is updated instead
+    /// of failing the build. Defaults to false.
+    ///
+    /// Breaking changes are also allowed when the `TRUSTZONE_ALLOW_BREAKING` environment variable
+    /// is set to anything but `0`.
+    pub fn allow_breaking_changes(mut self, allow_breaking_changes: bool) -> Self {
+        self.allow_breaking_changes = allow_breaking_changes;
+        self
+    }
+
+    /// Generates the bindings and writes them to the output path
+    pub fn generate(self) -> Result<(), anyhow::Error> {
+        let module_ident = syn::parse_str::<syn::Ident>(&self.module_name)
+            .with_context(|| format!("`{}` is not a valid module name", self.module_name))?;
+        let visibility = syn::parse_str::<syn::Visibility>(&self.visibility)
+            .with_context(|| format!("`{}` is not a valid visibility", self.visibility))?;
+        let output_path = match self.output_path {
+            Some(output_path) => output_path,
+            None => PathBuf::from(std::env::var_os("OUT_DIR").context(
+                "OUT_DIR is not set, so the bindings generator doesn't run in a build script. Set an output path instead.",
+            )?)
+            .join(&self.module_name)
+            .with_extension("rs"),
+        };
+        let cfg = self.cfg.unwrap_or_else(CfgSet::from_target_env);
+        let secure = self.secure;
+        let propagated_attributes = &self.propagated_attributes;
+
+        fn generate_bindings_inner(
+            module_file: &ModuleFile,
+            crate_directory: &Path,
+            cfg: &CfgSet,
+            propagated_attributes: &[String],
+            generated_items: &mut Vec<GeneratedItem>,
+        ) -> Result<(), anyhow::Error> {
+            println!("cargo:rerun-if-changed={}", module_file.path.display());
+
+            // Read the source code file
+            let file_text = std::fs::read_to_string(&module_file.path)
+                .with_context(|| format!("Could not read {}", module_file.path.display()))?;
+            let source_file = SourceFile {
+                path: &module_file.path,
+                text: &file_text,
+                crate_directory,
+            };
+
+            // Parse the file
+            let file = syn::parse_file(&file_text).map_err(|e| source_file.error(e))?;
+
+            // Generate the bindings to the file and the inline modules in it
+            let child_module_files = generate_module_bindings(
+                &source_file,
+                &file.items,
+                &module_file.module_path,
+                &ModuleDirectory {
+                    file_directory: module_file.path.parent().unwrap().to_path_buf(),
+                    directory: module_file.directory.clone(),
+                    inline: false,
+                },
+                cfg,
+                propagated_attributes,
+                generated_items,
+            )?;
+
+            // Continue reading other modules
+            for child_module_file in child_module_files {
+                generate_bindings_inner(
+                    &child_module_file,
+                    crate_directory,
+                    cfg,
+                    propagated_attributes,
+                    generated_items,
+                )?;
             }
 
-            let module_name = module.ident.to_string();
+            Ok(())
+        }
 
-            let module_entry = module_file_path
-                .as_ref()
-                .parent()
-                .unwrap()
-                .read_dir()?
-                .filter_map(Result::ok)
-                .find(|entry| {
-                    entry
-                        .path()
-                        .with_extension("")
-                        .file_name()
-                        .unwrap()
-                        .to_string_lossy()
-                        == module_name
+        fn generate_crate_bindings(
+            source_root: &Path,
+            cfg: &CfgSet,
+            propagated_attributes: &[String],
+            generated_items: &mut Vec<GeneratedItem>,
+        ) -> Result<(), anyhow::Error> {
+            // The crate root is a mod-rs file, so its child modules are next to it
+            let root_file = ModuleFile {
+                path: source_root.to_path_buf(),
+                module_path: Vec::new(),
+                directory: source_root.parent().unwrap().to_path_buf(),
+            };
+            generate_bindings_inner(
+                &root_file,
+                &crate_directory(source_root),
+                cfg,
+                propagated_attributes,
+                generated_items,
+            )
+        }
+
+        let mut generated_items = Vec::new();
+        let mut import_library_veneers = None;
+
+        let result = match &self.source {
+            BindingsSource::Crate(source_root) => generate_crate_bindings(
+                source_root,
+                &cfg,
+                propagated_attributes,
+                &mut generated_items,
+            ),
+            BindingsSource::Manifest(manifest_path) => {
+                println!("cargo:rerun-if-changed={}", manifest_path.display());
+
+                Manifest::read(manifest_path).and_then(|manifest| {
+                    for manifest_item in manifest.items {
+                        generated_items.push(generate_item(
+                            manifest_item,
+                            None,
+                            propagated_attributes,
+                        )?);
+                    }
+                    Ok(())
+                })
+            }
+            BindingsSource::ImportLibrary { library, header } => {
+                println!("cargo:rerun-if-changed={}", library.display());
+                println!("cargo:rerun-if-changed={}", header.display());
+
+                import_library::read_import_library(library, header).and_then(|import_library| {
+                    for manifest_item in import_library.items {
+                        generated_items.push(generate_item(
+                            manifest_item,
+                            None,
+                            propagated_attributes,
+                        )?);
+                    }
+                    import_library_veneers = Some(import_library.veneers);
+                    Ok(())
+                })
+            }
+            BindingsSource::Elf(elf_path) => {
+                println!("cargo:rerun-if-changed={}", elf_path.display());
+
+                metadata::read_metadata(elf_path).and_then(|manifest_items| {
+                    for manifest_item in manifest_items {
+                        generated_items.push(generate_item(
+                            manifest_item,
+                            None,
+                            propagated_attributes,
+                        )?);
+                    }
+                    Ok(())
+                })
+            }
+        };
+        if let Err(error) = result {
+            if let Some(error) = error.downcast_ref::<BindingsError>() {
+                error.print_cargo_warning();
+            }
+            return Err(error);
+        }
+
+        // Check if there aren't any name and hash collisions.
+        // Items in different modules may have the same name, but never the same hash.
+        // The bindings of different items in the same module may not have the same name either,
+        // like `read` and the `try_read` of `read`, or the accessor `read` of the static `READ`.
+        let mut errors = Vec::new();
+        for (index, item) in generated_items.iter().enumerate() {
+            if let Some(name) = item
+                .binding_names()
+                .find(|name| RESERVED_NAMES.contains(&name.as_str()))
+            {
+                errors.push(BindingsError::ReservedName {
+                    item: item.exported_item.clone(),
+                    name,
                 });
+            }
 
-            if let Some(module_entry) = module_entry {
-                if module_entry.path().is_file() {
-                    generate_bindings_inner(module_entry.path(), secure, generated_items)?;
+            for other_item in generated_items[..index].iter() {
+                if item.exported_item.path == other_item.exported_item.path {
+                    errors.push(BindingsError::DuplicateExport {
+                        first: other_item.exported_item.clone(),
+                        second: item.exported_item.clone(),
+                    });
+                } else if let Some(name) = item.binding_names().find(|name| {
+                    item.bindings.module_path == other_item.bindings.module_path
+                        && other_item
+                            .binding_names()
+                            .any(|other_name| &other_name == name)
+                }) {
+                    errors.push(BindingsError::NameClash {
+                        first: other_item.exported_item.clone(),
+                        second: item.exported_item.clone(),
+                        name: manifest::item_path(&item.bindings.module_path, &name),
+                    });
+                } else if item.hash == other_item.hash {
+                    errors.push(BindingsError::HashCollision {
+                        a: other_item.exported_item.clone(),
+                        b: item.exported_item.clone(),
+                        hash: item.hash,
+                    });
                 }
+            }
+        }
+        for error in errors.iter() {
+            error.print_cargo_warning();
+        }
+        if let Some(error) = errors.into_iter().next() {
+            return Err(error.into());
+        }
+
+        if let Some(lock_file_path) = &self.lock_file_path {
+            let env_is_set = |name| {
+                println!("cargo:rerun-if-env-changed={name}");
+                std::env::var_os(name).is_some_and(|value| value != "0")
+            };
+            let update = self.update_lock_file || env_is_set(lock_file::UPDATE_ENV);
+            let allow_breaking =
+                self.allow_breaking_changes || env_is_set(lock_file::ALLOW_BREAKING_ENV);
+            let (directions, own_directions): (&[&str], &[&str]) = if secure {
+                (
+                    &["secure_callable"],
+                    &["nonsecure_callable", "nonsecure_shared"],
+                )
+            } else {
+                (
+                    &["nonsecure_callable", "nonsecure_shared"],
+                    &["secure_callable"],
+                )
+            };
 
-                if module_entry.path().is_dir() {
-                    generate_bindings_inner(
-                        module_entry.path().join("mod.rs"),
-                        secure,
-                        generated_items,
-                    )
-                    .or_else(|_| {
-                        generate_bindings_inner(
-                            module_entry
-                                .path()
-                                .join(module_entry.path().file_name().unwrap())
-                                .with_extension("rs"),
-                            secure,
-                            generated_items,
-                        )
-                    })?;
+            lock_file::check_lock_file(
+                lock_file_path,
+                &generated_items
+                    .iter()
+                    .map(|item| item.manifest_item.clone())
+                    .collect::<Vec<_>>(),
+                directions,
+                update,
+                allow_breaking,
+            )?;
+
+            // The exports of this crate are checked as well, so a breaking change fails the build of
+            // the side that makes it and not only the build of the other side
+            let own_source_root = match &self.own_source_root {
+                Some(own_source_root) => own_source_root.clone(),
+                None => own_crate_root()?,
+            };
+            let mut own_items = Vec::new();
+            if let Err(error) = generate_crate_bindings(
+                &own_source_root,
+                &CfgSet::from_env(),
+                propagated_attributes,
+                &mut own_items,
+            ) {
+                if let Some(error) = error.downcast_ref::<BindingsError>() {
+                    error.print_cargo_warning();
                 }
+                return Err(error);
             }
-        }
 
-        Ok(())
-    }
+            lock_file::check_lock_file(
+                lock_file_path,
+                &own_items
+                    .into_iter()
+                    .map(|item| item.manifest_item)
+                    .collect::<Vec<_>>(),
+                own_directions,
+                update,
+                allow_breaking,
+            )?;
+        }
 
-    let mut generated_items = Vec::new();
+        let secure_image_veneers = match (import_library_veneers, &self.veneer_lookup) {
+            (Some(veneers), _) => Some(veneers),
+            (None, VeneerLookup::Search) => None,
+            (None, VeneerLookup::SecureImage(secure_image_path)) => {
+                if secure {
+                    anyhow::bail!("Only the nonsecure side can be linked against the secure image");
+                }
 
-    generate_bindings_inner(module_file_path, secure, &mut generated_items)?;
+                println!("cargo:rerun-if-changed={}", secure_image_path.display());
 
-    // Check if there aren't any name and hash collisions
-    for (_, name, hash) in generated_items.iter() {
-        assert_eq!(
-            generated_items
-                .iter()
-                .filter(|(_, other_name, _)| name == other_name)
-                .count(),
-            1,
-            "Duplicate name found: {name}"
-        );
-        for (_, other_name, other_hash) in generated_items.iter() {
-            if name != other_name {
-                assert_ne!(hash, other_hash, "Hash collision found for `{name}` and `{other_name}`. To fix this, change one of the names. This is a limitation of how the trustzone-m-tools work.");
+                Some(secure_image::read_veneers(secure_image_path)?)
             }
+        };
+
+        if let Some(veneers) = &secure_image_veneers {
+            // The linker script goes next to the bindings
+            let linker_script_name = format!("{}_veneers.x", self.module_name);
+            let output_directory = output_path.parent().unwrap();
+
+            fs::write(
+                output_directory.join(&linker_script_name),
+                secure_image::veneer_linker_script(veneers),
+            )?;
+
+            println!("cargo:rustc-link-search={}", output_directory.display());
+            println!("cargo:rustc-link-arg=-T{linker_script_name}");
         }
-    }
 
-    let mut output_file = syn::File {
-        shebang: None,
-        attrs: Vec::new(),
-        items: vec![syn::ItemMod {
-            attrs: Vec::new(),
-            vis: syn::Visibility::Public(Default::default()),
-            unsafety: None,
-            mod_token: Default::default(),
-            ident: syn::Ident::new("trustzone_bindings", Span::call_site()),
-            content: Some((
-                syn::token::Brace::default(),
+        let mut root_module = BindingsModule::default();
+
+        if let Some(manifest_path) = &self.manifest_path {
+            Manifest::new(
                 generated_items
-                    .into_iter()
-                    .map(|(item, _, _)| item)
+                    .iter()
+                    .map(|item| item.manifest_item.clone())
                     .collect(),
-            )),
-            semi: None,
+            )
+            .write(manifest_path)?;
         }
-        .into()],
-    };
 
-    if secure {
-        output_file.items.push(
-            syn::parse_str::<syn::ItemFn>(FIND_NS_VECTOR_FUNCTION)
-                .unwrap()
-                .into(),
-        );
+        if let Some((header_path, source_path)) = &self.c_bindings_paths {
+            let manifest_items = generated_items
+                .iter()
+                .map(|item| item.manifest_item.clone())
+                .collect::<Vec<_>>();
+            let header_name = header_path
+                .file_name()
+                .context("The C header path has no file name")?
+                .to_string_lossy();
+
+            let c_bindings = c_bindings::generate_c_bindings(
+                &manifest_items,
+                &self.module_name,
+                &header_name,
+                self.emit_glue,
+            )?;
+
+            fs::write(header_path, c_bindings.header).with_context(|| {
+                format!("Could not write the C header {}", header_path.display())
+            })?;
+            fs::write(source_path, c_bindings.source).with_context(|| {
+                format!("Could not write the C source {}", source_path.display())
+            })?;
+        }
+
+        let mut api_functions = Vec::new();
+        for GeneratedItem {
+            mut bindings,
+            exported_item,
+            manifest_item,
+            hash,
+        } in generated_items
+        {
+            if let Some(veneers) = &secure_image_veneers {
+                bindings.veneer =
+                    generate_linked_veneer(&bindings, &exported_item.path, hash, veneers)?;
+            }
+            if let Some(peer) = &self.simulation_peer {
+                bindings.veneer.attrs.insert(0, api_trait::target_cfg());
+                bindings.simulated_veneer = Some(generate_simulated_veneer(
+                    &bindings,
+                    &simulation::simulation_symbol(
+                        peer,
+                        &exported_item.path,
+                        bindings.signature_hash,
+                    ),
+                ));
+            }
 
-        output_file.items.push(
-            syn::parse_str::<syn::ItemFn>(FIND_NSC_VECTOR_FUNCTION)
+            // The secure runtime checks that it exports what the nonsecure app imports. Veneers of
+            // a vendor secure image are linked against instead, so those can't be missing.
+            if !secure && secure_image_veneers.is_none() {
+                add_import_entry(&mut bindings, hash);
+            }
+
+            // Shared statics are no functions of the other side, so they don't get a method
+            let is_function =
+                !matches!(manifest_item.kind, ManifestItemKind::NonsecureShared { .. });
+            if let (true, Some(syn::Item::Fn(binding))) = (
+                self.generate_api_trait && is_function,
+                bindings.items.first(),
+            ) {
+                api_functions.push(ApiFunction {
+                    module_path: bindings.module_path.clone(),
+                    binding: binding.clone(),
+                });
+            }
+
+            root_module.add(bindings);
+        }
+
+        let mut bindings_items: Vec<syn::Item> =
+            vec![syn::parse_str::<syn::ItemEnum>(VENEER_ERROR_ENUM)
                 .unwrap()
-                .into(),
-        );
+                .into()];
+        bindings_items.extend(root_module.into_items());
+        if self.generate_api_trait {
+            // The app doesn't have to declare the feature that enables the mock
+            println!(
+                "cargo:rustc-check-cfg=cfg(feature, values(\"{}\"))",
+                api_trait::MOCK_FEATURE
+            );
+
+            // Without the simulation, the veneers can only be called on the Arm target, but the
+            // trait and the mock can be used anywhere
+            let simulation = self.simulation_peer.is_some();
+            if !simulation {
+                bindings_items
+                    .iter_mut()
+                    .for_each(|item| add_attr(item, api_trait::target_cfg()));
+            }
+            bindings_items.extend(api_trait::generate_api_trait(
+                &api_functions,
+                secure,
+                simulation,
+            ));
+        }
+
+        let mut output_file = syn::File {
+            shebang: None,
+            attrs: Vec::new(),
+            items: vec![syn::ItemMod {
+                attrs: Vec::new(),
+                vis: visibility,
+                unsafety: None,
+                mod_token: Default::default(),
+                ident: module_ident,
+                content: Some((syn::token::Brace::default(), bindings_items)),
+                semi: None,
+            }
+            .into()],
+        };
 
-        // If we're secure, then we have to create a veneer for the searcher
-        output_file.items.push(
-            syn::parse_str::<syn::Item>(
-                "
+        if !self.emit_glue {
+            // Another bindings file has the glue
+        } else if secure {
+            output_file.items.push(
+                syn::parse_str::<syn::ItemFn>(FIND_NS_VECTOR_FUNCTION)
+                    .unwrap()
+                    .into(),
+            );
+
+            output_file.items.push(
+                syn::parse_str::<syn::ItemFn>(FIND_NSC_VECTOR_FUNCTION)
+                    .unwrap()
+                    .into(),
+            );
+
+            // If we're secure, then we have to create a veneer for the searcher
+            output_file.items.push(
+                syn::parse_str::<syn::Item>(
+                    "
                 core::arch::global_asm!(
                     \".section .nsc_veneers.searcher, \\\"ax\\\"\",
                     \".global searcher_veneer\",
@@ -153,15 +747,16 @@ pub fn generate_bindings<P: AsRef<Path>>(
                     \"searcher_veneer:\",
                         \"SG\",
                         \"B.w find_nsc_veneer\",
+                        \".4byte 0\",
                         \".4byte 0\"
                 );
             ",
-            )
-            .unwrap(),
-        );
+                )
+                .unwrap(),
+            );
 
-        // If we're secure, then we have to create a function that calls the initializer veneer
-        output_file.items.push(syn::parse_str::<syn::Item>(
+            // If we're secure, then we have to create a function that calls the initializer veneer
+            output_file.items.push(syn::parse_str::<syn::Item>(
             "
                 #[no_mangle]
                 unsafe extern \"C\" fn initialize_ns_data() {
@@ -176,449 +771,1349 @@ pub fn generate_bindings<P: AsRef<Path>>(
                 }
             ").unwrap()
         );
-    } else {
-        // If we're nonsecure, then we have to create a function that calls the searcher veneer
-        output_file.items.push(syn::parse_str::<syn::Item>(
+        } else {
+            // If we're nonsecure and search for the veneers, then we have to create a function that calls the searcher veneer
+            if secure_image_veneers.is_none() {
+                output_file.items.push(syn::parse_str::<syn::Item>(
             "
-                extern \"C\" fn find_nsc_veneer(hash: u32) -> *const u32 {
+                extern \"C\" fn find_nsc_veneer(hash: u32, signature: u32) -> *const u32 {
                     extern \"C\" {
                         static _NSC_VENEERS: u32;
                     }
                     unsafe {
                         // Don't forget to set the thumb bit
                         let searcher_veneer_ptr = (&_NSC_VENEERS as *const u32 as usize | 1) as *const u32;
-                        let searcher_veneer_ptr = core::mem::transmute::<_, extern \"C\" fn(u32) -> *const u32>(searcher_veneer_ptr);
-                        searcher_veneer_ptr(hash)
+                        let searcher_veneer_ptr = core::mem::transmute::<_, extern \"C\" fn(u32, u32) -> *const u32>(searcher_veneer_ptr);
+                        searcher_veneer_ptr(hash, signature)
                     }
                 }
             ").unwrap()
-        );
+            );
+            }
 
-        // If we're nonsecure, then we have to create a veneer for the initializer
-        output_file.items.push(
-            syn::parse_str::<syn::Item>(
-                "
+            // If we're nonsecure, then we have to create a veneer for the initializer
+            output_file.items.push(
+                syn::parse_str::<syn::Item>(
+                    "
                 core::arch::global_asm!(
                     \".section .ns_veneers.initializer, \\\"ax\\\"\",
                     \".global initializer_veneer\",
                     \".thumb_func\",
                     \"initializer_veneer:\",
                         \"B.w initialize_ns_data\",
+                        \".4byte 0\",
                         \".4byte 0\"
                 );
             ",
+                )
+                .unwrap(),
+            );
+        }
+
+        // The glue is only needed to find the veneers on the device
+        if self.generate_api_trait || self.simulation_peer.is_some() {
+            output_file.items[1..]
+                .iter_mut()
+                .for_each(|item| add_attr(item, api_trait::target_cfg()));
+        }
+
+        if !self.generate_docs {
+            RemoveDocs.visit_file_mut(&mut output_file);
+        }
+
+        let mut output_bindings_file = fs::File::create(&output_path).with_context(|| {
+            format!(
+                "Could not create the bindings file {}",
+                output_path.display()
             )
-            .unwrap(),
-        );
+        })?;
+
+        output_bindings_file.write_all(prettyplease::unparse(&output_file).as_bytes())?;
+
+        Ok(())
+    }
+}
+
+/// Removes the doc comments of the kinds of items that are generated
+struct RemoveDocs;
+
+impl RemoveDocs {
+    fn remove_docs(attrs: &mut Vec<Attribute>) {
+        attrs.retain(|attr| !attr.path().is_ident("doc"));
+    }
+}
+
+impl VisitMut for RemoveDocs {
+    fn visit_item_mod_mut(&mut self, node: &mut syn::ItemMod) {
+        Self::remove_docs(&mut node.attrs);
+        visit_mut::visit_item_mod_mut(self, node);
+    }
+
+    fn visit_item_fn_mut(&mut self, node: &mut syn::ItemFn) {
+        Self::remove_docs(&mut node.attrs);
+        visit_mut::visit_item_fn_mut(self, node);
     }
 
-    let mut output_bindings_file =
-        fs::File::create(PathBuf::from(out_dir).join("trustzone_bindings.rs"))?;
+    fn visit_item_static_mut(&mut self, node: &mut syn::ItemStatic) {
+        Self::remove_docs(&mut node.attrs);
+        visit_mut::visit_item_static_mut(self, node);
+    }
+
+    fn visit_item_enum_mut(&mut self, node: &mut syn::ItemEnum) {
+        Self::remove_docs(&mut node.attrs);
+        visit_mut::visit_item_enum_mut(self, node);
+    }
+
+    fn visit_variant_mut(&mut self, node: &mut syn::Variant) {
+        Self::remove_docs(&mut node.attrs);
+        visit_mut::visit_variant_mut(self, node);
+    }
+}
+
+/// The directory of the crate with the root file, which is the closest directory with a
+/// `Cargo.toml` or the directory of the root file if there is none
+fn crate_directory(source_root: &Path) -> PathBuf {
+    let directory = source_root.parent().unwrap();
+
+    directory
+        .ancestors()
+        .find(|directory| directory.join("Cargo.toml").is_file())
+        .unwrap_or(directory)
+        .to_path_buf()
+}
+
+/// The root file of the crate whose build script runs the bindings generator
+fn own_crate_root() -> Result<PathBuf, anyhow::Error> {
+    let manifest_dir = PathBuf::from(std::env::var_os("CARGO_MANIFEST_DIR").context(
+        "CARGO_MANIFEST_DIR is not set, so the bindings generator doesn't run in a build script. Set the own source root instead.",
+    )?);
+
+    ["src/main.rs", "src/lib.rs"]
+        .into_iter()
+        .map(|root| manifest_dir.join(root))
+        .find(|root| root.exists())
+        .with_context(|| {
+            format!(
+                "Could not find the root file of the crate in {} to check its exports against the lock file. Set the own source root instead.",
+                manifest_dir.display()
+            )
+        })
+}
 
-    output_bindings_file.write_all(prettyplease::unparse(&output_file).as_bytes())?;
+/// Adds the id and signature hash of the veneer to the list of the items the nonsecure app
+/// imports.
+///
+/// The entry goes in its own `.trustzone_imports.*` section, which the linker script of the
+/// nonsecure runtime places after the `.ns_veneers` table. The secure runtime checks the list
+/// against its own veneers before it starts the nonsecure app. Only the binding that panics refers
+/// to the entry, so the linker drops it when that binding isn't used. An item that is only used
+/// through its `try_` binding or `is_available` may be missing from the secure app.
+fn add_import_entry(bindings: &mut FunctionBindings, id: u32) {
+    let Some(syn::Item::Fn(binding)) = bindings.items.first_mut() else {
+        unreachable!("The first item of the bindings is the binding that panics");
+    };
 
-    Ok(())
+    let section = format!(".trustzone_imports.{id}");
+    let signature_hash = bindings.signature_hash;
+    binding.block.stmts.insert(
+        0,
+        syn::parse_quote! {
+            #[cfg(target_arch = "arm")]
+            {
+                #[link_section = #section]
+                static IMPORT: [u32; 2] = [#id, #signature_hash];
+                // The read keeps the entry in the image for as long as this binding is
+                unsafe { core::ptr::read_volatile(&IMPORT) };
+            }
+        },
+    );
 }
 
-fn generate_file_bindings(
-    file: syn::File,
-    generated_items: &mut Vec<(syn::Item, String, u32)>,
-) -> Result<Vec<syn::ItemMod>, anyhow::Error> {
-    let found_exported_items = TrustzoneExportedItem::find(file.items.iter());
+/// Generates the bindings of the items of a module and of the inline modules in it.
+///
+/// Returns the files of the modules that refer to other files.
+/// Modules that are disabled by a `#[cfg(...)]` attribute are skipped.
+fn generate_module_bindings(
+    source_file: &SourceFile,
+    items: &[syn::Item],
+    module_path: &[String],
+    module_directory: &ModuleDirectory,
+    cfg: &CfgSet,
+    propagated_attributes: &[String],
+    generated_items: &mut Vec<GeneratedItem>,
+) -> Result<Vec<ModuleFile>, anyhow::Error> {
+    let found_exported_items =
+        TrustzoneExportedItem::find(items.iter(), cfg).map_err(|e| source_file.error(e))?;
+    // The types in the signatures are named like the other app can name them
+    let scope = ModuleScope::new(items, cfg).map_err(|e| source_file.error(e))?;
 
     for exported_item in found_exported_items {
-        match exported_item {
-            TrustzoneExportedItem::SecureCallableFunction { signature } => {
-                let function_call = syn::ExprCall {
-                    attrs: Vec::new(),
-                    func: Box::new(
-                        syn::ExprPath {
-                            attrs: Vec::new(),
-                            qself: None,
-                            path: syn::Path {
-                                leading_colon: None,
-                                segments: Punctuated::from_iter([PathSegment {
-                                    ident: syn::Ident::new("fn_ptr", Span::call_site()),
-                                    arguments: syn::PathArguments::None,
-                                }]),
-                            },
-                        }
-                        .into(),
-                    ),
-                    paren_token: Default::default(),
-                    args: Punctuated::from_iter(
-                        signature
-                            .inputs
-                            .iter()
-                            .filter_map(|input| match input {
-                                syn::FnArg::Typed(t) => Some(t),
-                                _ => None,
-                            })
-                            .map(|input| match input.pat.deref() {
-                                syn::Pat::Ident(i) => syn::Expr::Path(syn::ExprPath {
-                                    attrs: Vec::new(),
-                                    qself: None,
-                                    path: syn::Path {
-                                        leading_colon: None,
-                                        segments: Punctuated::from_iter([PathSegment {
-                                            ident: i.ident.clone(),
-                                            arguments: syn::PathArguments::None,
-                                        }]),
-                                    },
-                                }),
-                                _ => unreachable!(),
-                            }),
-                    ),
-                };
+        let (mut signature, attributes, attrs, secure_callable) = match exported_item {
+            TrustzoneExportedItem::SecureCallableFunction {
+                signature,
+                attributes,
+                attrs,
+            } => (signature, attributes, attrs, true),
+            TrustzoneExportedItem::NonSecureCallableFunction {
+                signature,
+                attributes,
+                attrs,
+            } => (signature, attributes, attrs, false),
+            TrustzoneExportedItem::NonSecureSharedStatic { item, attributes } => {
+                let mutable = matches!(item.mutability, syn::StaticMutability::Mut(_));
+                let mut ty = item.ty.as_ref().clone();
+                scope
+                    .qualify_type(&mut ty)
+                    .map_err(|e| source_file.error(e))?;
+                let manifest_item = ManifestItem::shared_static(
+                    module_path,
+                    &attributes.export_name(&item.ident),
+                    &ty,
+                    mutable,
+                    attributes.id(module_path, &item.ident),
+                    abi::static_hash(&item.ty, mutable),
+                );
+                let location = source_file.location(item.ident.span());
+                let manifest_item = manifest_item.with_origin(
+                    &find_propagated_attrs(&item.attrs, propagated_attributes),
+                    source_file.defined_at(&location),
+                );
 
-                let function_cast = syn::TypeBareFn {
-                    lifetimes: None,
-                    unsafety: signature.unsafety.clone(),
-                    abi: Some(syn::Abi {
-                        extern_token: Default::default(),
-                        name: Some(syn::LitStr::new("C-cmse-nonsecure-call", Span::call_site())),
-                    }),
-                    fn_token: Default::default(),
-                    paren_token: Default::default(),
-                    inputs: signature
-                        .inputs
-                        .iter()
-                        .filter_map(|arg| {
-                            if let syn::FnArg::Typed(t) = arg {
-                                Some(t)
-                            } else {
-                                None
-                            }
-                        })
-                        .map(|pat_type| syn::BareFnArg {
-                            attrs: pat_type.attrs.clone(),
-                            name: None,
-                            ty: *pat_type.ty.clone(),
+                generated_items.push(generate_item(
+                    manifest_item,
+                    Some(location),
+                    propagated_attributes,
+                )?);
+                continue;
+            }
+        };
+
+        let location = source_file.location(signature.ident.span());
+        let function_hash = attributes.id(module_path, &signature.ident);
+        let signature_hash = abi::signature_hash(&signature);
+        scope
+            .qualify_signature(&mut signature)
+            .map_err(|e| source_file.error(e))?;
+
+        // The binding is named after the exported name, not the name the function has in Rust
+        signature.ident = syn::Ident::new(
+            &attributes.export_name(&signature.ident),
+            signature.ident.span(),
+        );
+
+        let manifest_item = ManifestItem::function(
+            module_path,
+            &signature,
+            secure_callable,
+            function_hash,
+            signature_hash,
+        )
+        .with_origin(
+            &find_propagated_attrs(&attrs, propagated_attributes),
+            source_file.defined_at(&location),
+        );
+        generated_items.push(generate_item(
+            manifest_item,
+            Some(location),
+            propagated_attributes,
+        )?);
+    }
+
+    let mut module_files = Vec::new();
+
+    for module in items.iter().filter_map(|item| match item {
+        syn::Item::Mod(module) => Some(module),
+        _ => None,
+    }) {
+        if !cfg
+            .is_enabled(&module.attrs)
+            .map_err(|e| source_file.error(e))?
+        {
+            continue;
+        }
+
+        let mut child_module_path = module_path.to_vec();
+        child_module_path.push(module.ident.to_string());
+
+        let module_name = module.ident.unraw().to_string();
+        let path_attribute = find_path_attr(&module.attrs).map_err(|e| source_file.error(e))?;
+
+        match &module.content {
+            // The file modules of an inline module are in the directory with its name
+            Some((_, content)) => module_files.extend(generate_module_bindings(
+                source_file,
+                content,
+                &child_module_path,
+                &ModuleDirectory {
+                    file_directory: module_directory.file_directory.clone(),
+                    directory: module_directory
+                        .directory
+                        .join(path_attribute.as_deref().unwrap_or(&module_name)),
+                    inline: true,
+                },
+                cfg,
+                propagated_attributes,
+                generated_items,
+            )?),
+            None => {
+                let module_file = match path_attribute {
+                    // Outside of inline modules the path is relative to the directory of the file.
+                    // A file that is loaded with a path is treated like a mod.rs file.
+                    Some(path_attribute) => {
+                        let path = if module_directory.inline {
+                            module_directory.directory.join(path_attribute)
+                        } else {
+                            module_directory.file_directory.join(path_attribute)
+                        };
+
+                        Some(ModuleFile {
+                            directory: path.parent().unwrap().to_path_buf(),
+                            path,
+                            module_path: child_module_path,
                         })
-                        .collect(),
-                    variadic: signature.variadic.as_ref().map(|v| syn::BareVariadic {
-                        attrs: v.attrs.clone(),
-                        name: None,
-                        dots: v.dots,
-                        comma: v.comma,
+                    }
+                    // Both `name.rs` and `name/mod.rs` have their child modules in `name/`
+                    None => [
+                        module_directory
+                            .directory
+                            .join(&module_name)
+                            .with_extension("rs"),
+                        module_directory.directory.join(&module_name).join("mod.rs"),
+                    ]
+                    .into_iter()
+                    .find(|path| path.is_file())
+                    .map(|path| ModuleFile {
+                        path,
+                        module_path: child_module_path,
+                        directory: module_directory.directory.join(&module_name),
                     }),
-                    output: signature.output.clone(),
                 };
 
-                let function_name = signature.ident.to_string();
-                let function_hash = crate::hash_vector_name(&function_name);
-                let function_not_found_string =
-                    format!("Could not find the veneer of nonsecure '{}'", function_name);
-
-                generated_items.push((
-                    syn::ItemFn {
-                        attrs: vec![],
-                        vis: syn::Visibility::Public(Default::default()),
-                        sig: signature.clone(),
-                        block: Box::new(syn::parse_quote! {
-                            {
-                                const HASH: u32 = #function_hash;
-                                let fn_ptr = unsafe { super::find_ns_veneer(HASH) };
-
-                                if fn_ptr.is_null() {
-                                    panic!(#function_not_found_string);
-                                }
+                module_files.extend(module_file);
+            }
+        }
+    }
 
-                                // Don't forget to set the thumb bit
-                                let fn_ptr = unsafe {
-                                    core::mem::transmute::<_, #function_cast>(((fn_ptr as usize) | 1) as *const u32)
-                                };
+    Ok(module_files)
+}
 
-                                #function_call
-                            }
-                        }),
-                    }
-                    .into(),
-                    function_name,
-                    function_hash,
-                ));
+/// Where the exported items are read from
+enum BindingsSource {
+    /// The root file of the crate that exports them
+    Crate(PathBuf),
+    /// A manifest file
+    Manifest(PathBuf),
+    /// The CMSE import library of a secure image and the header with its prototypes
+    ImportLibrary { library: PathBuf, header: PathBuf },
+    /// The metadata records in a built ELF file or static library
+    Elf(PathBuf),
+}
+
+/// An exported item the bindings are generated for
+struct GeneratedItem {
+    bindings: FunctionBindings,
+    exported_item: ExportedItem,
+    manifest_item: ManifestItem,
+    /// The id of the veneer
+    hash: u32,
+}
+
+/// The names of the items every generated module has, which the bindings can't use
+const RESERVED_NAMES: &[&str] = &["resolve_all", "is_available", "veneers"];
+
+impl GeneratedItem {
+    /// The names of the binding and its `try_` variant
+    fn binding_names(&self) -> impl Iterator<Item = String> + '_ {
+        self.bindings.items.iter().filter_map(|item| match item {
+            syn::Item::Fn(function) => Some(function.sig.ident.to_string()),
+            _ => None,
+        })
+    }
+}
+
+/// Generates the bindings of an exported item.
+///
+/// The location is the place of the item in the source code, if it was found in the source code.
+/// The attributes of the item that are in the list of propagated attributes are put on the bindings.
+fn generate_item(
+    manifest_item: ManifestItem,
+    location: Option<SourceLocation>,
+    propagated_attributes: &[String],
+) -> Result<GeneratedItem, anyhow::Error> {
+    let (module_path, name) = manifest_item.module_path_and_name();
+
+    let mut bindings = match &manifest_item.kind {
+        ManifestItemKind::SecureCallable(function)
+        | ManifestItemKind::NonsecureCallable(function) => {
+            let signature = function.parse_signature().with_context(|| {
+                format!(
+                    "Invalid signature of `{}` in the manifest",
+                    manifest_item.path
+                )
+            })?;
+
+            generate_function_binding(
+                &signature,
+                matches!(manifest_item.kind, ManifestItemKind::SecureCallable(_)),
+                manifest_item.id,
+                manifest_item.signature_hash,
+                &module_path,
+            )
+        }
+        ManifestItemKind::NonsecureShared { ty, mutable } => {
+            let ty = syn::parse_str::<syn::Type>(ty).with_context(|| {
+                format!("Invalid type of `{}` in the manifest", manifest_item.path)
+            })?;
+
+            // The accessor is a function, so it gets a function name
+            generate_static_binding(
+                &syn::Ident::new(&name.to_lowercase(), Span::call_site()),
+                &ty,
+                *mutable,
+                manifest_item.id,
+                manifest_item.signature_hash,
+                &module_path,
+            )
+        }
+    };
+
+    let attrs = manifest_item.parse_attributes().with_context(|| {
+        format!(
+            "Invalid attributes of `{}` in the manifest",
+            manifest_item.path
+        )
+    })?;
+    let (mut docs, other_attrs): (Vec<Attribute>, Vec<Attribute>) =
+        find_propagated_attrs(&attrs, propagated_attributes)
+            .into_iter()
+            .partition(|attr| attr.path().is_ident("doc"));
+
+    // The doc comments end with where the veneer comes from
+    if !docs.is_empty() {
+        docs.push(syn::parse_quote!(#[doc = ""]));
+    }
+    let mut origin = format!(
+        " Veneer id `{:#010x}`, signature hash `{:#010x}`.",
+        manifest_item.id, manifest_item.signature_hash
+    );
+    if let Some(defined_at) = &manifest_item.defined_at {
+        origin.push_str(&format!(" Defined at `{defined_at}`."));
+    }
+    docs.push(syn::parse_quote!(#[doc = #origin]));
+
+    // The `try_` variant gets the same attributes, except for the documentation
+    if let [syn::Item::Fn(binding), syn::Item::Fn(try_binding)] = bindings.items.as_mut_slice() {
+        let try_doc = format!(
+            " Calls [`{}`], but returns an error instead of panicking if the veneer can't be used.",
+            binding.sig.ident
+        );
+        try_binding.attrs = std::iter::once(syn::parse_quote!(#[doc = #try_doc]))
+            .chain(other_attrs.iter().cloned())
+            .collect();
+        binding.attrs = docs.into_iter().chain(other_attrs).collect();
+
+        // The binding calls its deprecated `try_` variant
+        if binding
+            .attrs
+            .iter()
+            .any(|attr| attr.path().is_ident("deprecated"))
+        {
+            binding.attrs.push(syn::parse_quote!(#[allow(deprecated)]));
+        }
+    }
+
+    Ok(GeneratedItem {
+        bindings,
+        exported_item: ExportedItem {
+            path: manifest_item.path.clone(),
+            location,
+        },
+        hash: manifest_item.id,
+        manifest_item,
+    })
+}
+
+/// A scanned source file, which errors point into
+struct SourceFile<'a> {
+    path: &'a Path,
+    text: &'a str,
+    /// The directory of the crate the file is in
+    crate_directory: &'a Path,
+}
+
+impl SourceFile<'_> {
+    fn location(&self, span: Span) -> SourceLocation {
+        SourceLocation::new(self.path, self.text, span)
+    }
+
+    /// Where the item at the location is defined, relative to the directory of the crate, so it
+    /// doesn't depend on where the crate is or how the bindings generator was given its path
+    fn defined_at(&self, location: &SourceLocation) -> String {
+        let path = location
+            .file
+            .strip_prefix(self.crate_directory)
+            .unwrap_or(&location.file);
+        let path = path
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+
+        format!("{path}:{}:{}", location.line, location.column)
+    }
+
+    fn error(&self, error: syn::Error) -> BindingsError {
+        BindingsError::InvalidSource {
+            message: error.to_string(),
+            location: self.location(error.span()),
+        }
+    }
+}
+
+/// A source file of a module that still has to be scanned
+struct ModuleFile {
+    /// The path of the file
+    path: PathBuf,
+    /// The path of the module, relative to the crate root
+    module_path: Vec<String>,
+    /// The directory in which the files of the child modules are
+    directory: PathBuf,
+}
+
+/// Where the files of the child modules of a module are
+struct ModuleDirectory {
+    /// The directory of the file the module is in, which `#[path]` attributes are relative to
+    file_directory: PathBuf,
+    /// The directory the files of the child modules are in, which `#[path]` attributes inside
+    /// inline modules are relative to
+    directory: PathBuf,
+    /// Whether the module is an inline module
+    inline: bool,
+}
+
+/// Adds the attribute to the front of the attributes of a generated item
+fn add_attr(item: &mut syn::Item, attr: Attribute) {
+    let attrs = match item {
+        syn::Item::Fn(item) => &mut item.attrs,
+        syn::Item::Mod(item) => &mut item.attrs,
+        syn::Item::Use(item) => &mut item.attrs,
+        syn::Item::Macro(item) => &mut item.attrs,
+        syn::Item::Enum(item) => &mut item.attrs,
+        syn::Item::Static(item) => &mut item.attrs,
+        _ => unreachable!("Only functions, modules, imports, macros, enums and statics are generated"),
+    };
+    attrs.insert(0, attr);
+}
+
+/// Finds the attributes whose path is in the list of propagated attributes
+fn find_propagated_attrs(attrs: &[Attribute], propagated_attributes: &[String]) -> Vec<Attribute> {
+    attrs
+        .iter()
+        .filter(|attr| {
+            let path = attr
+                .path()
+                .segments
+                .iter()
+                .map(|segment| segment.ident.to_string())
+                .collect::<Vec<_>>()
+                .join("::");
+            propagated_attributes.contains(&path)
+        })
+        .cloned()
+        .collect()
+}
+
+/// Finds the file path of a `#[path = "..."]` attribute
+fn find_path_attr(attrs: &[Attribute]) -> syn::Result<Option<String>> {
+    let Some(attr) = attrs.iter().find(|attr| attr.path().is_ident("path")) else {
+        return Ok(None);
+    };
+
+    match &attr.meta.require_name_value()?.value {
+        syn::Expr::Lit(syn::ExprLit {
+            lit: syn::Lit::Str(path),
+            ..
+        }) => Ok(Some(path.value())),
+        value => Err(syn::Error::new_spanned(value, "Expected a string literal")),
+    }
+}
+
+/// A module of the `trustzone_bindings` module, which mirrors a module of the scanned source
+#[derive(Default)]
+struct BindingsModule {
+    /// The bindings of the items in the module
+    items: Vec<syn::Item>,
+    /// The functions that go in the `veneers` module
+    veneers: Vec<syn::ItemFn>,
+    /// The functions that go in the `is_available` module
+    is_available: Vec<syn::ItemFn>,
+    /// The child modules in the order they were found
+    children: Vec<(String, BindingsModule)>,
+}
+
+impl BindingsModule {
+    /// Adds the bindings to the module they belong to, which is created if needed
+    fn add(&mut self, function_bindings: FunctionBindings) {
+        let mut module = self;
+        for name in function_bindings.module_path.iter() {
+            let index = match module
+                .children
+                .iter()
+                .position(|(child_name, _)| child_name == name)
+            {
+                Some(index) => index,
+                None => {
+                    module
+                        .children
+                        .push((name.clone(), BindingsModule::default()));
+                    module.children.len() - 1
+                }
+            };
+            module = &mut module.children[index].1;
+        }
+
+        module.items.extend(function_bindings.items);
+        module.veneers.push(function_bindings.veneer);
+        module.veneers.extend(function_bindings.simulated_veneer);
+        module.is_available.push(function_bindings.is_available);
+    }
+
+    fn into_items(self) -> Vec<syn::Item> {
+        let mut items: Vec<syn::Item> = Vec::new();
+
+        // The types of bindings from a manifest or an image can be names the module that
+        // includes the bindings imports, and the child modules need `VeneerError`
+        items.push(syn::parse_quote!(
+            #[allow(unused_imports)]
+            use super::*;
+        ));
+        items.extend(self.items);
+
+        let veneer_idents = self.veneers.iter().map(|veneer| &veneer.sig.ident);
+        let child_idents = self
+            .children
+            .iter()
+            .map(|(name, _)| quote::format_ident!("{}", name));
+        items.push(syn::parse_quote! {
+            /// Looks up all veneers of this module and its child modules now instead of at the
+            /// first call of every binding.
+            ///
+            /// Veneers that can't be found are skipped. Their bindings report the error when they're called.
+            pub fn resolve_all() {
+                #(let _ = veneers::#veneer_idents();)*
+                #(#child_idents::resolve_all();)*
             }
-            TrustzoneExportedItem::NonSecureCallableFunction { signature } => {
-                let function_call = syn::ExprCall {
-                    attrs: Vec::new(),
-                    func: Box::new(
-                        syn::ExprPath {
-                            attrs: Vec::new(),
-                            qself: None,
-                            path: syn::Path {
-                                leading_colon: None,
-                                segments: Punctuated::from_iter([PathSegment {
-                                    ident: syn::Ident::new("fn_ptr", Span::call_site()),
-                                    arguments: syn::PathArguments::None,
-                                }]),
-                            },
-                        }
-                        .into(),
-                    ),
-                    paren_token: Default::default(),
-                    args: Punctuated::from_iter(
-                        signature
-                            .inputs
-                            .iter()
-                            .filter_map(|input| match input {
-                                syn::FnArg::Typed(t) => Some(t),
-                                _ => None,
-                            })
-                            .map(|input| match input.pat.deref() {
-                                syn::Pat::Ident(i) => syn::Expr::Path(syn::ExprPath {
-                                    attrs: Vec::new(),
-                                    qself: None,
-                                    path: syn::Path {
-                                        leading_colon: None,
-                                        segments: Punctuated::from_iter([PathSegment {
-                                            ident: i.ident.clone(),
-                                            arguments: syn::PathArguments::None,
-                                        }]),
-                                    },
-                                }),
-                                _ => unreachable!(),
-                            }),
-                    ),
+        });
+
+        let is_available = &self.is_available;
+        items.push(syn::parse_quote! {
+            /// Functions that tell if the other image exports a function with the expected signature
+            pub mod is_available {
+                #(#is_available)*
+            }
+        });
+        let veneers = &self.veneers;
+        items.push(syn::parse_quote! {
+            mod veneers {
+                #(#veneers)*
+            }
+        });
+
+        for (name, child) in self.children {
+            let ident = quote::format_ident!("{}", name);
+            let child_items = child.into_items();
+            items.push(syn::parse_quote! {
+                pub mod #ident {
+                    #(#child_items)*
+                }
+            });
+        }
+
+        items
+    }
+}
+
+/// The items that are generated for a single exported function
+struct FunctionBindings {
+    /// The binding and its `try_` variant that go in the `trustzone_bindings` module
+    items: Vec<syn::Item>,
+    /// The function that looks up and caches the veneer, which goes in the `veneers` module
+    veneer: syn::ItemFn,
+    /// The function that finds the entry function in the simulation on the host, which goes in
+    /// the `veneers` module as well
+    simulated_veneer: Option<syn::ItemFn>,
+    /// The function that goes in the `is_available` module
+    is_available: syn::ItemFn,
+    /// The hash of the signature the veneer must have
+    signature_hash: u32,
+    /// The path of the module the exported item is in, relative to the crate root
+    module_path: Vec<String>,
+}
+
+/// Creates the functions that look up the veneer of the exported function and call it.
+///
+/// A `secure_callable` function lives in the nonsecure world and is called from the secure world.
+/// A `nonsecure_callable` function lives in the secure world and is called from the nonsecure world.
+fn generate_function_binding(
+    signature: &syn::Signature,
+    secure_callable: bool,
+    function_hash: u32,
+    signature_hash: u32,
+    module_path: &[String],
+) -> FunctionBindings {
+    // The bindings of the secure callable functions are in the secure app
+    let runtime = if secure_callable {
+        quote::quote!(::trustzone_m_secure_rt)
+    } else {
+        quote::quote!(::trustzone_m_nonsecure_rt)
+    };
+    let lowered_signature = abi::LoweredSignature::new(signature, runtime);
+
+    // Functions that don't use the C ABI are called through the shim the macro generated for them
+    let mut binding_signature = signature.clone();
+    if !abi::is_extern_c(signature) {
+        binding_signature.abi = None;
+    }
+    // The arguments need a name so we can pass them on
+    for (input, lowered_input) in binding_signature
+        .inputs
+        .iter_mut()
+        .filter_map(|input| match input {
+            syn::FnArg::Typed(t) => Some(t),
+            _ => None,
+        })
+        .zip(lowered_signature.inputs.iter())
+    {
+        if !matches!(input.pat.deref(), syn::Pat::Ident(_)) {
+            let ident = &lowered_input.ident;
+            *input.pat = syn::parse_quote!(#ident);
+        }
+    }
+
+    let call_abi: syn::Abi = if secure_callable {
+        syn::parse_quote!(extern "C-cmse-nonsecure-call")
+    } else {
+        syn::parse_quote!(extern "C")
+    };
+
+    let function_cast = syn::TypeBareFn {
+        lifetimes: None,
+        unsafety: signature.unsafety,
+        abi: Some(call_abi),
+        fn_token: Default::default(),
+        paren_token: Default::default(),
+        inputs: lowered_signature.lowered_inputs().collect(),
+        variadic: signature.variadic.as_ref().map(|v| syn::BareVariadic {
+            attrs: v.attrs.clone(),
+            name: None,
+            dots: v.dots,
+            comma: v.comma,
+        }),
+        output: lowered_signature.lowered_output(),
+    };
+    // The simulation on the host calls the entry function with the C ABI
+    let mut host_function_cast = function_cast.clone();
+    host_function_cast.abi = Some(syn::parse_quote!(extern "C"));
+
+    let lowered_arguments = lowered_signature.lowered_arguments();
+    let function_call =
+        lowered_signature.lift_output(syn::parse_quote!(fn_ptr(#(#lowered_arguments),*)));
+    // The other side could return a value that isn't valid for the return type
+    let function_result: syn::Expr = if lowered_signature.has_checked_output() {
+        let function_call = abi::operand(function_call);
+        syn::parse_quote!(#function_call.ok_or(VeneerError::InvalidValue))
+    } else {
+        syn::parse_quote!(Ok(#function_call))
+    };
+
+    let function_ident = &signature.ident;
+    let try_ident = quote::format_ident!("try_{}", function_ident);
+    let (veneer, is_available) = generate_veneer_lookup(
+        function_ident,
+        secure_callable,
+        function_hash,
+        signature_hash,
+        module_path,
+    );
+
+    let output_type: syn::Type = match &signature.output {
+        syn::ReturnType::Default => syn::parse_quote!(()),
+        syn::ReturnType::Type(_, ty) => ty.as_ref().clone(),
+    };
+    let mut try_signature = binding_signature.clone();
+    try_signature.ident = try_ident.clone();
+    // The result isn't FFI-safe, but this is never called from the other side anyway
+    try_signature.abi = None;
+    try_signature.output = syn::parse_quote!(-> Result<#output_type, VeneerError>);
+
+    let try_binding = syn::ItemFn {
+        attrs: vec![],
+        vis: syn::Visibility::Public(Default::default()),
+        sig: try_signature,
+        block: Box::new(syn::parse_quote! {
+            {
+                let fn_ptr = veneers::#function_ident()?;
+
+                // Don't forget to set the thumb bit
+                #[cfg(target_arch = "arm")]
+                let fn_ptr = unsafe {
+                    core::mem::transmute::<_, #function_cast>(((fn_ptr as usize) | 1) as *const u32)
                 };
+                // The simulation on the host has no veneers and gives the entry function itself
+                #[cfg(not(target_arch = "arm"))]
+                let fn_ptr = unsafe { core::mem::transmute::<*const u32, #host_function_cast>(fn_ptr) };
 
-                let function_cast = syn::TypeBareFn {
-                    lifetimes: None,
-                    unsafety: signature.unsafety.clone(),
-                    abi: signature.abi.clone(),
-                    fn_token: Default::default(),
-                    paren_token: Default::default(),
-                    inputs: signature
-                        .inputs
-                        .iter()
-                        .filter_map(|arg| {
-                            if let syn::FnArg::Typed(t) = arg {
-                                Some(t)
-                            } else {
-                                None
-                            }
-                        })
-                        .map(|pat_type| syn::BareFnArg {
-                            attrs: pat_type.attrs.clone(),
-                            name: None,
-                            ty: *pat_type.ty.clone(),
-                        })
-                        .collect(),
-                    variadic: signature.variadic.as_ref().map(|v| syn::BareVariadic {
-                        attrs: v.attrs.clone(),
-                        name: None,
-                        dots: v.dots,
-                        comma: v.comma,
-                    }),
-                    output: signature.output.clone(),
+                #function_result
+            }
+        }),
+    };
+
+    let argument_idents = lowered_signature.inputs.iter().map(|input| &input.ident);
+    let binding = syn::ItemFn {
+        attrs: vec![],
+        vis: syn::Visibility::Public(Default::default()),
+        sig: binding_signature,
+        block: Box::new(generate_panicking_call(
+            syn::parse_quote!(#try_ident(#(#argument_idents),*)),
+            secure_callable,
+            module_path,
+            function_ident,
+        )),
+    };
+
+    FunctionBindings {
+        items: vec![binding.into(), try_binding.into()],
+        veneer,
+        simulated_veneer: None,
+        is_available,
+        signature_hash,
+        module_path: module_path.to_vec(),
+    }
+}
+
+/// Creates the accessor of a static that lives in the secure world and is shared with the
+/// nonsecure world.
+///
+/// The veneer of the static returns its address, which is cached after the first call.
+fn generate_static_binding(
+    static_ident: &syn::Ident,
+    ty: &syn::Type,
+    mutable: bool,
+    static_hash: u32,
+    signature_hash: u32,
+    module_path: &[String],
+) -> FunctionBindings {
+    let try_ident = quote::format_ident!("try_{}", static_ident);
+    let (veneer, is_available) = generate_veneer_lookup(
+        static_ident,
+        false,
+        static_hash,
+        signature_hash,
+        module_path,
+    );
+
+    // Only a `static mut` may be written to, so that's the only one that gives a mutable pointer
+    let (reference_type, convert_address): (syn::Type, syn::Expr) = if mutable {
+        (
+            syn::parse_quote!(*mut #ty),
+            syn::parse_quote!(address as *mut #ty),
+        )
+    } else {
+        (
+            syn::parse_quote!(&'static #ty),
+            syn::parse_quote!(unsafe { &*(address as *const #ty) }),
+        )
+    };
+
+    let try_binding: syn::ItemFn = syn::parse_quote! {
+        pub fn #try_ident() -> Result<#reference_type, VeneerError> {
+            static ADDRESS: core::sync::atomic::AtomicUsize = core::sync::atomic::AtomicUsize::new(0);
+
+            let mut address = ADDRESS.load(core::sync::atomic::Ordering::Relaxed);
+            if address == 0 {
+                let fn_ptr = veneers::#static_ident()?;
+
+                // Don't forget to set the thumb bit
+                #[cfg(target_arch = "arm")]
+                let fn_ptr = unsafe {
+                    core::mem::transmute::<_, extern "C" fn() -> *const ()>(((fn_ptr as usize) | 1) as *const u32)
+                };
+                // The simulation on the host has no veneers and gives the entry function itself
+                #[cfg(not(target_arch = "arm"))]
+                let fn_ptr = unsafe {
+                    core::mem::transmute::<*const u32, extern "C" fn() -> *const ()>(fn_ptr)
                 };
 
-                let function_name = signature.ident.to_string();
-                let function_hash = crate::hash_vector_name(&function_name);
-                let function_not_found_string =
-                    format!("Could not find the veneer of secure '{}'", function_name);
-
-                generated_items.push((syn::ItemFn {
-                    attrs: vec![],
-                    vis: syn::Visibility::Public(Default::default()),
-                    sig: signature.clone(),
-                    block: Box::new(syn::parse_quote! {
-                        {
-                            const HASH: u32 = #function_hash;
-                            let fn_ptr = super::find_nsc_veneer(HASH);
-
-                            if fn_ptr.is_null() {
-                                panic!(#function_not_found_string);
-                            }
+                address = fn_ptr() as usize;
+                ADDRESS.store(address, core::sync::atomic::Ordering::Relaxed);
+            }
 
-                            // Don't forget to set the thumb bit
-                            let fn_ptr = unsafe {
-                                core::mem::transmute::<_, #function_cast>(((fn_ptr as usize) | 1) as *const u32)
-                            };
-                            #function_call
-                        }
-                    }),
+            Ok(#convert_address)
+        }
+    };
+
+    let binding_block = generate_panicking_call(
+        syn::parse_quote!(#try_ident()),
+        false,
+        module_path,
+        static_ident,
+    );
+    let binding: syn::ItemFn = syn::parse_quote! {
+        pub fn #static_ident() -> #reference_type #binding_block
+    };
+
+    FunctionBindings {
+        items: vec![binding.into(), try_binding.into()],
+        veneer,
+        simulated_veneer: None,
+        is_available,
+        signature_hash,
+        module_path: module_path.to_vec(),
+    }
+}
+
+/// Creates the function that looks up and caches the veneer with the given hashes and the function
+/// that tells if the veneer is available
+fn generate_veneer_lookup(
+    ident: &syn::Ident,
+    secure_callable: bool,
+    function_hash: u32,
+    signature_hash: u32,
+    module_path: &[String],
+) -> (syn::ItemFn, syn::ItemFn) {
+    // The glue functions are next to the `trustzone_bindings` module, which is one level above
+    // the bindings module of the root module, which is one level above its `veneers` module
+    let supers = std::iter::repeat_n(quote::quote!(super), module_path.len() + 2);
+    let glue_path: syn::Path = syn::parse_quote!(#(#supers)::*);
+
+    let find_veneer = |signature: syn::Expr| -> syn::Expr {
+        if secure_callable {
+            syn::parse_quote!(unsafe { #glue_path::find_ns_veneer(HASH, #signature) })
+        } else {
+            syn::parse_quote!(#glue_path::find_nsc_veneer(HASH, #signature))
+        }
+    };
+    let find_exact_veneer = find_veneer(syn::parse_quote!(SIGNATURE));
+    let find_any_veneer = find_veneer(syn::parse_quote!(0));
+
+    // The found veneer is cached. An interrupt that races with the first lookup does its own lookup
+    // and stores the same pointer, so a relaxed atomic is all that's needed.
+    let veneer = syn::parse_quote! {
+        pub(super) fn #ident() -> Result<*const u32, super::VeneerError> {
+            const HASH: u32 = #function_hash;
+            const SIGNATURE: u32 = #signature_hash;
+            static CACHE: core::sync::atomic::AtomicUsize = core::sync::atomic::AtomicUsize::new(0);
+
+            let cached_ptr = CACHE.load(core::sync::atomic::Ordering::Relaxed);
+            if cached_ptr != 0 {
+                return Ok(cached_ptr as *const u32);
+            }
+
+            let fn_ptr = #find_exact_veneer;
+
+            if fn_ptr.is_null() {
+                // Find out why we couldn't find it
+                if !#find_any_veneer.is_null() {
+                    return Err(super::VeneerError::SignatureMismatch);
                 }
-                .into(), function_name, function_hash));
+                return Err(super::VeneerError::NotFound);
             }
+
+            CACHE.store(fn_ptr as usize, core::sync::atomic::Ordering::Relaxed);
+            Ok(fn_ptr)
         }
-    }
+    };
+
+    let is_available = syn::parse_quote! {
+        pub fn #ident() -> bool {
+            super::veneers::#ident().is_ok()
+        }
+    };
 
-    let found_modules = file
-        .items
+    (veneer, is_available)
+}
+
+/// Creates the body of a binding that calls its `try_` variant and panics if that fails
+fn generate_panicking_call(
+    try_call: syn::Expr,
+    secure_callable: bool,
+    module_path: &[String],
+    ident: &syn::Ident,
+) -> syn::Block {
+    let path = module_path
         .iter()
-        // Get all modules
-        .filter_map(|item| match item {
-            syn::Item::Mod(module) => Some(module),
-            _ => None,
-        })
-        // Keep only the modules that refer to other files
-        .filter(|module| module.content.is_none())
         .cloned()
-        .collect();
+        .chain([ident.to_string()])
+        .collect::<Vec<_>>()
+        .join("::");
+    let world = if secure_callable {
+        "nonsecure"
+    } else {
+        "secure"
+    };
+    let not_found_string = format!("Could not find the veneer of {world} '{path}'");
+    let signature_mismatch_string = format!(
+        "Signature mismatch for {world} '{path}'. The images are built from different versions of it"
+    );
+    let invalid_value_string = format!("The {world} '{path}' returned an invalid value");
 
-    Ok(found_modules)
+    syn::parse_quote! {
+        {
+            match #try_call {
+                Ok(value) => value,
+                Err(VeneerError::NotFound) => panic!(#not_found_string),
+                Err(VeneerError::SignatureMismatch) => panic!(#signature_mismatch_string),
+                Err(VeneerError::InvalidValue) => panic!(#invalid_value_string),
+            }
+        }
+    }
+}
+
+/// Creates the function that returns the address of the veneer as provided by the linker, after
+/// checking that the secure image has the veneer we expect
+fn generate_linked_veneer(
+    function_bindings: &FunctionBindings,
+    function_name: &str,
+    function_hash: u32,
+    secure_image_veneers: &[SecureImageVeneer],
+) -> Result<syn::ItemFn, anyhow::Error> {
+    // The veneers are identified by their id, which includes the module path
+    let Some(secure_image_veneer) = secure_image_veneers
+        .iter()
+        .find(|veneer| veneer.id == function_hash)
+    else {
+        anyhow::bail!("The secure image doesn't export `{function_name}`");
+    };
+    anyhow::ensure!(
+        secure_image_veneer.signature == function_bindings.signature_hash,
+        "Signature mismatch for secure `{function_name}`. The images are built from different versions of it"
+    );
+
+    let function_ident = &function_bindings.veneer.sig.ident;
+    let veneer_ident = syn::Ident::new(&secure_image_veneer.name, Span::call_site());
+
+    Ok(syn::parse_quote! {
+        // Taking the address of an extern static is only safe in newer Rust versions
+        #[allow(unused_unsafe)]
+        pub(super) fn #function_ident() -> Result<*const u32, super::VeneerError> {
+            extern "C" {
+                static #veneer_ident: u32;
+            }
+
+            Ok(unsafe { core::ptr::addr_of!(#veneer_ident) })
+        }
+    })
+}
+
+/// Creates the function that gives the entry function of the exported item in the simulation on
+/// the host, which the macro exported with the given symbol
+fn generate_simulated_veneer(function_bindings: &FunctionBindings, symbol: &str) -> syn::ItemFn {
+    let function_ident = &function_bindings.veneer.sig.ident;
+
+    syn::parse_quote! {
+        #[cfg(not(target_arch = "arm"))]
+        pub(super) fn #function_ident() -> Result<*const u32, super::VeneerError> {
+            extern "C" {
+                #[link_name = #symbol]
+                static SIMULATED_VENEER: *const u32;
+            }
+
+            Ok(unsafe { SIMULATED_VENEER })
+        }
+    }
 }
 
 #[allow(dead_code)]
 enum TrustzoneExportedItem {
-    SecureCallableFunction { signature: syn::Signature },
-    NonSecureCallableFunction { signature: syn::Signature },
+    SecureCallableFunction {
+        signature: syn::Signature,
+        attributes: ExportAttributes,
+        attrs: Vec<Attribute>,
+    },
+    NonSecureCallableFunction {
+        signature: syn::Signature,
+        attributes: ExportAttributes,
+        attrs: Vec<Attribute>,
+    },
+    NonSecureSharedStatic {
+        item: syn::ItemStatic,
+        attributes: ExportAttributes,
+    },
 }
 
 impl std::fmt::Debug for TrustzoneExportedItem {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::SecureCallableFunction { signature } => f
+            Self::SecureCallableFunction { signature, .. } => f
                 .debug_struct("SecureCallableFunction")
                 .field("ident", &signature.ident.to_string())
                 .finish(),
-            Self::NonSecureCallableFunction { signature } => f
+            Self::NonSecureCallableFunction { signature, .. } => f
                 .debug_struct("NonSecureCallableFunction")
                 .field("ident", &signature.ident.to_string())
                 .finish(),
+            Self::NonSecureSharedStatic { item, .. } => f
+                .debug_struct("NonSecureSharedStatic")
+                .field("ident", &item.ident.to_string())
+                .finish(),
         }
     }
 }
 
 impl TrustzoneExportedItem {
-    fn find<'i, I: IntoIterator<Item = &'i syn::Item>>(items: I) -> Vec<TrustzoneExportedItem> {
+    /// Finds the exported items that are enabled in the cfg set
+    fn find<'i, I: IntoIterator<Item = &'i syn::Item>>(
+        items: I,
+        cfg: &CfgSet,
+    ) -> syn::Result<Vec<TrustzoneExportedItem>> {
         let mut exported_items = Vec::new();
 
-        fn find_inner<'i>(
-            items: &mut dyn Iterator<Item = &'i syn::Item>,
+        fn find_inner(
+            items: &mut dyn Iterator<Item = &syn::Item>,
+            cfg: &CfgSet,
             exported_items: &mut Vec<TrustzoneExportedItem>,
-        ) {
+        ) -> syn::Result<()> {
             for item in items {
                 match item {
-                    syn::Item::Fn(function) => {
-                        if contains_secure_callable_attr(&function.attrs) {
-                            exported_items.push(TrustzoneExportedItem::SecureCallableFunction {
-                                signature: function.sig.clone(),
-                            });
-                        }
-                        if contains_nonsecure_callable_attr(&function.attrs) {
-                            exported_items.push(TrustzoneExportedItem::NonSecureCallableFunction {
-                                signature: function.sig.clone(),
-                            });
-                        }
+                    syn::Item::Fn(function) if cfg.is_enabled(&function.attrs)? => {
+                        find_exported_function(&function.attrs, &function.sig, exported_items)?;
 
                         find_inner(
                             &mut function.block.stmts.iter().filter_map(|stmt| match stmt {
                                 syn::Stmt::Item(item) => Some(item),
                                 _ => None,
                             }),
+                            cfg,
                             exported_items,
-                        );
+                        )?;
                     }
-                    syn::Item::Impl(implementation) => {
+                    syn::Item::Impl(implementation) if cfg.is_enabled(&implementation.attrs)? => {
                         for impl_item in implementation.items.iter() {
-                            match impl_item {
-                                syn::ImplItem::Fn(method) => {
-                                    if contains_secure_callable_attr(&method.attrs) {
-                                        exported_items.push(
-                                            TrustzoneExportedItem::SecureCallableFunction {
-                                                signature: method.sig.clone(),
-                                            },
-                                        );
-                                    }
-                                    if contains_nonsecure_callable_attr(&method.attrs) {
-                                        exported_items.push(
-                                            TrustzoneExportedItem::NonSecureCallableFunction {
-                                                signature: method.sig.clone(),
-                                            },
-                                        );
-                                    }
-
-                                    find_inner(
-                                        &mut method.block.stmts.iter().filter_map(
-                                            |stmt| match stmt {
-                                                syn::Stmt::Item(item) => Some(item),
-                                                _ => None,
-                                            },
-                                        ),
-                                        exported_items,
-                                    );
+                            if let syn::ImplItem::Fn(method) = impl_item {
+                                if !cfg.is_enabled(&method.attrs)? {
+                                    continue;
                                 }
-                                _ => {}
+
+                                find_exported_function(&method.attrs, &method.sig, exported_items)?;
+
+                                find_inner(
+                                    &mut method.block.stmts.iter().filter_map(|stmt| match stmt {
+                                        syn::Stmt::Item(item) => Some(item),
+                                        _ => None,
+                                    }),
+                                    cfg,
+                                    exported_items,
+                                )?;
                             }
                         }
                     }
-                    syn::Item::Static(_) => {
-                        // TODO
+                    syn::Item::Static(item) if cfg.is_enabled(&item.attrs)? => {
+                        if let Some(attr) = find_nonsecure_shared_attr(&item.attrs) {
+                            exported_items.push(TrustzoneExportedItem::NonSecureSharedStatic {
+                                item: item.clone(),
+                                attributes: ExportAttributes::from_attribute(attr)?,
+                            });
+                        }
                     }
                     _ => {}
                 }
             }
+
+            Ok(())
+        }
+
+        fn find_exported_function(
+            attrs: &[Attribute],
+            signature: &syn::Signature,
+            exported_items: &mut Vec<TrustzoneExportedItem>,
+        ) -> syn::Result<()> {
+            if let Some(attr) = find_secure_callable_attr(attrs) {
+                exported_items.push(TrustzoneExportedItem::SecureCallableFunction {
+                    signature: signature.clone(),
+                    attributes: ExportAttributes::from_attribute(attr)?,
+                    attrs: attrs.to_vec(),
+                });
+            }
+            if let Some(attr) = find_nonsecure_callable_attr(attrs) {
+                exported_items.push(TrustzoneExportedItem::NonSecureCallableFunction {
+                    signature: signature.clone(),
+                    attributes: ExportAttributes::from_attribute(attr)?,
+                    attrs: attrs.to_vec(),
+                });
+            }
+
+            Ok(())
         }
 
-        find_inner(&mut items.into_iter(), &mut exported_items);
+        find_inner(&mut items.into_iter(), cfg, &mut exported_items)?;
 
-        exported_items
+        Ok(exported_items)
     }
 }
 
-fn contains_secure_callable_attr(attrs: &[Attribute]) -> bool {
+fn find_secure_callable_attr(attrs: &[Attribute]) -> Option<&Attribute> {
     attrs
         .iter()
-        .any(|attr| attr.path().segments.last().unwrap().ident.to_string() == "secure_callable")
+        .find(|attr| attr.path().segments.last().unwrap().ident == "secure_callable")
 }
 
-fn contains_nonsecure_callable_attr(attrs: &[Attribute]) -> bool {
+fn find_nonsecure_callable_attr(attrs: &[Attribute]) -> Option<&Attribute> {
     attrs
         .iter()
-        .any(|attr| attr.path().segments.last().unwrap().ident.to_string() == "nonsecure_callable")
+        .find(|attr| attr.path().segments.last().unwrap().ident == "nonsecure_callable")
+}
+
+fn find_nonsecure_shared_attr(attrs: &[Attribute]) -> Option<&Attribute> {
+    attrs
+        .iter()
+        .find(|attr| attr.path().segments.last().unwrap().ident == "nonsecure_shared")
+}
+
+/// The attributes that are propagated to the bindings by default
+const DEFAULT_PROPAGATED_ATTRIBUTES: &[&str] = &["doc", "must_use", "deprecated", "inline"];
+
+const VENEER_ERROR_ENUM: &str = "
+/// The reason a veneer couldn't be used
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VeneerError {
+    /// The other image doesn't export the function
+    NotFound,
+    /// The other image exports the function, but with a different signature
+    SignatureMismatch,
+    /// The function returned a value that isn't valid for its return type
+    InvalidValue,
 }
+";
 
-const FIND_NS_VECTOR_FUNCTION: &'static str = "
-unsafe extern \"C\" fn find_ns_veneer(name_hash: u32) -> *const u32 {
+const FIND_NS_VECTOR_FUNCTION: &str = "
+/// Finds the veneer with the given hash and signature. A signature of 0 matches any signature.
+unsafe extern \"C\" fn find_ns_veneer(name_hash: u32, signature_hash: u32) -> *const u32 {
     extern \"C\" {
         static _NS_VENEERS: u32;
+        static _ns_flash_end: u32;
     }
 
-    let mut ns_veneers_ptr = (&_NS_VENEERS as *const u32 as *const ([u8; 4], u32)).offset(1);
+    // A veneer is the branch instruction followed by the name hash and the signature hash.
+    // The initializer veneer is followed by the magic, the version, the number of veneers, the checksum
+    // and the veneers sorted by hash. The secure runtime has checked the header before it started the nonsecure app.
+    let count_ptr = (&_NS_VENEERS as *const u32).offset(5);
+    let ns_veneers_ptr = count_ptr.offset(2) as *const [u32; 3];
 
-    loop {
-        let (_, vector_hash) = *ns_veneers_ptr;
+    // The number is read from the nonsecure image, so don't let it point past its flash
+    let max_count = (&_ns_flash_end as *const u32 as usize - ns_veneers_ptr as usize) / 12;
+    let ns_veneers = core::slice::from_raw_parts(ns_veneers_ptr, (*count_ptr as usize).min(max_count));
 
-        if vector_hash == 0 {
-            // We've reached the end
-            return core::ptr::null();
-        }
-
-        if vector_hash == name_hash {
+    match ns_veneers.binary_search_by_key(&name_hash, |[_, vector_hash, _]| *vector_hash) {
+        Ok(index) if signature_hash == 0 || ns_veneers[index][2] == signature_hash => {
             // We've found the vector we've been looking for
-            return ns_veneers_ptr as _;
+            &ns_veneers[index] as *const [u32; 3] as _
         }
-
-        ns_veneers_ptr = ns_veneers_ptr.offset(1);
+        _ => core::ptr::null(),
     }
 }
 ";
 
-const FIND_NSC_VECTOR_FUNCTION: &'static str = "
+const FIND_NSC_VECTOR_FUNCTION: &str = "
+/// Finds the veneer with the given hash and signature. A signature of 0 matches any signature.
 #[no_mangle]
 #[cmse_nonsecure_entry]
-unsafe extern \"C\" fn find_nsc_veneer(name_hash: u32) -> *const u32 {
+unsafe extern \"C\" fn find_nsc_veneer(name_hash: u32, signature_hash: u32) -> *const u32 {
     extern \"C\" {
         static _NSC_VENEERS: u32;
     }
 
-    let mut nsc_veneers_ptr = (&_NSC_VENEERS as *const u32 as *const ([u8; 8], u32)).offset(1);
-
-    loop {
-        let (_, vector_hash) = *nsc_veneers_ptr;
+    // A veneer is the SG instruction, the branch instruction, the name hash and the signature hash.
+    // The searcher veneer is followed by the magic, the version, the number of veneers, the checksum
+    // and the veneers sorted by hash.
+    let count_ptr = (&_NSC_VENEERS as *const u32).offset(6);
+    let nsc_veneers = core::slice::from_raw_parts(count_ptr.offset(2) as *const [u32; 4], *count_ptr as usize);
 
-        if vector_hash == 0 {
-            // We've reached the end
-            return core::ptr::null();
-        }
-
-        if vector_hash == name_hash {
+    match nsc_veneers.binary_search_by_key(&name_hash, |[_, _, vector_hash, _]| *vector_hash) {
+        Ok(index) if signature_hash == 0 || nsc_veneers[index][3] == signature_hash => {
             // We've found the vector we've been looking for
-            return nsc_veneers_ptr as _;
+            &nsc_veneers[index] as *const [u32; 4] as _
         }
-
-        nsc_veneers_ptr = nsc_veneers_ptr.offset(1);
+        _ => core::ptr::null(),
     }
 }
 ";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates an empty directory for the output of a test
+    fn output_directory(name: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("trustzone-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    /// Generates the bindings with the builder into the directory and compares them with the
+    /// expected bindings
+    fn test_generation(builder: BindingsBuilder, directory: &Path, output_text: &str) {
+        let output_path = directory.join("trustzone_bindings.rs");
+        builder.output_path(&output_path).generate().unwrap();
+
+        pretty_assertions::assert_eq!(
+            fs::read_to_string(&output_path)
+                .unwrap()
+                .replace("\r\n", "\n"),
+            output_text.replace("\r\n", "\n")
+        );
+    }
+
+    #[test]
+    fn test_module_bindings() {
+        let directory = output_directory("modules");
+
+        test_generation(
+            BindingsBuilder::new(
+                concat!(
+                    env!("CARGO_MANIFEST_DIR"),
+                    "/test-sources/bindings_modules/src/lib.rs"
+                ),
+                false,
+            )
+            .cfg(CfgSet::new()),
+            &directory,
+            include_str!("../test-sources/bindings_modules_result.txt"),
+        );
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_secure_image_bindings() {
+        let directory = output_directory("secure_image");
+
+        test_generation(
+            BindingsBuilder::new(
+                concat!(
+                    env!("CARGO_MANIFEST_DIR"),
+                    "/test-sources/bindings_secure_image/src/lib.rs"
+                ),
+                false,
+            )
+            .cfg(CfgSet::new())
+            .veneer_lookup(VeneerLookup::SecureImage(PathBuf::from(concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/test-sources/secure_image.elf"
+            )))),
+            &directory,
+            include_str!("../test-sources/bindings_secure_image_result.txt"),
+        );
+
+        // The veneer addresses are given to the linker next to the bindings
+        assert_eq!(
+            fs::read_to_string(directory.join("trustzone_bindings_veneers.x")).unwrap(),
+            "/* Generated by trustzone-m-tools from the secure image */\n\
+            PROVIDE(HALVE_934609468_veneer = 0x00010008);\n\
+            PROVIDE(DOUBLE_2737647201_veneer = 0x00010018);\n"
+        );
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,593 @@
+//! C bindings for nonsecure apps that are written in C.
+//!
+//! The C bindings are generated from the same manifest items as the Rust bindings. The functions
+//! the secure app exports get a prototype in the header and an implementation in the source file
+//! that looks up the veneer through the searcher veneer of the secure app. The functions the secure
+//! app calls get a prototype the C app has to implement and an entry in the `.ns_veneers` table.
+
+use anyhow::Context;
+use std::fmt::Write;
+
+use crate::{
+    abi::{self, Lowering},
+    export_attributes,
+    manifest::{ManifestItem, ManifestItemKind},
+};
+
+/// The generated header and source file
+pub(crate) struct CBindings {
+    pub(crate) header: String,
+    pub(crate) source: String,
+}
+
+/// Generates the C bindings of the items.
+///
+/// The module name prefixes the names of the functions that aren't generated for an item, so the C
+/// bindings of multiple interfaces can be used in the same app. Only one of them may have the glue.
+pub(crate) fn generate_c_bindings(
+    items: &[ManifestItem],
+    module_name: &str,
+    header_name: &str,
+    emit_glue: bool,
+) -> Result<CBindings, anyhow::Error> {
+    let mut header = String::new();
+    let mut source = String::new();
+    let mut imports = Vec::new();
+
+    let include_guard = format!("{}_H", module_name.to_uppercase());
+    writeln!(
+        header,
+        "/* Generated by trustzone-m-tools. Do not edit. */
+#ifndef {include_guard}
+#define {include_guard}
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+/* Types that aren't C primitives keep their Rust name and have to be declared before this header is included. */
+
+#ifdef __cplusplus
+extern \"C\" {{
+#endif
+
+#ifndef TRUSTZONE_VENEER_ERROR_DEFINED
+#define TRUSTZONE_VENEER_ERROR_DEFINED
+/* The reason a veneer couldn't be used */
+typedef enum {{
+    TRUSTZONE_VENEER_OK = 0,
+    /* The secure image doesn't export the function */
+    TRUSTZONE_VENEER_NOT_FOUND,
+    /* The secure image exports the function, but with a different signature */
+    TRUSTZONE_VENEER_SIGNATURE_MISMATCH,
+}} trustzone_veneer_error_t;
+#endif
+
+/* Called when a function of the secure image is called, but its veneer can't be used.
+ * The default implementation loops forever. */
+__attribute__((noreturn)) void trustzone_veneer_error(const char *path, trustzone_veneer_error_t error);
+
+/* Called by the secure image before it starts this image, through the first veneer of the `.ns_veneers` section.
+ * The default implementation does nothing, because the startup code of a C app initializes the RAM itself. */
+void trustzone_initialize_ns_data(void);
+
+/* Looks up all veneers now instead of at the first call of every function.
+ * Veneers that can't be found are skipped. Their functions report the error when they're called. */
+void {module_name}_resolve_all(void);"
+    )?;
+
+    writeln!(
+        source,
+        "/* Generated by trustzone-m-tools. Do not edit. */
+#include \"{header_name}\"
+
+/* The searcher veneer is the first veneer of the `.nsc_veneers` section of the secure image */
+extern const uint32_t _NSC_VENEERS;
+
+typedef const uint32_t *(*trustzone_searcher_t)(uint32_t id, uint32_t signature);
+
+/* Finds the veneer with the given id and signature. A signature of 0 matches any signature. */
+static const uint32_t *find_nsc_veneer(uint32_t id, uint32_t signature)
+{{
+    /* Don't forget to set the thumb bit */
+    trustzone_searcher_t searcher = (trustzone_searcher_t)((uintptr_t)&_NSC_VENEERS | 1);
+    return searcher(id, signature);
+}}
+
+/* Looks up the veneer and caches its address with the thumb bit set */
+static trustzone_veneer_error_t find_veneer(uintptr_t *cache, uint32_t id, uint32_t signature)
+{{
+    if (*cache != 0) {{
+        return TRUSTZONE_VENEER_OK;
+    }}
+
+    const uint32_t *veneer = find_nsc_veneer(id, signature);
+    if (veneer == NULL) {{
+        /* Find out why we couldn't find it */
+        if (find_nsc_veneer(id, 0) != NULL) {{
+            return TRUSTZONE_VENEER_SIGNATURE_MISMATCH;
+        }}
+        return TRUSTZONE_VENEER_NOT_FOUND;
+    }}
+
+    *cache = (uintptr_t)veneer | 1;
+    return TRUSTZONE_VENEER_OK;
+}}
+
+__attribute__((weak)) void trustzone_veneer_error(const char *path, trustzone_veneer_error_t error)
+{{
+    (void)path;
+    (void)error;
+    for (;;) {{
+    }}
+}}
+
+__attribute__((weak)) void trustzone_initialize_ns_data(void)
+{{
+}}"
+    )?;
+
+    if emit_glue {
+        writeln!(
+            source,
+            "
+/* The secure image calls the first veneer of the `.ns_veneers` section to initialize this image */
+__asm__(
+    \".pushsection .ns_veneers.initializer, \\\"ax\\\"\\n\"
+    \".global initializer_veneer\\n\"
+    \".thumb_func\\n\"
+    \"initializer_veneer:\\n\"
+    \"    b.w trustzone_initialize_ns_data\\n\"
+    \"    .4byte 0\\n\"
+    \"    .4byte 0\\n\"
+    \".popsection\\n\"
+);"
+        )?;
+    }
+
+    for item in items {
+        let (module_path, name) = item.module_path_and_name();
+        let c_name = module_path
+            .iter()
+            .map(String::as_str)
+            .chain([name.as_str()])
+            .collect::<Vec<_>>()
+            .join("_")
+            .to_lowercase();
+        let constant_name = c_name.to_uppercase();
+
+        writeln!(header, "\n/* {} */", item.path)?;
+        writeln!(header, "#define {constant_name}_ID {:#010x}u", item.id)?;
+        writeln!(
+            header,
+            "#define {constant_name}_SIGNATURE {:#010x}u",
+            item.signature_hash
+        )?;
+
+        match &item.kind {
+            ManifestItemKind::NonsecureCallable(function) => {
+                let signature = function.parse_signature().with_context(|| {
+                    format!("Invalid signature of `{}` in the manifest", item.path)
+                })?;
+                let prototype = CPrototype::new(&item.path, &c_name, &signature)?;
+
+                writeln!(header, "{};", prototype.declaration())?;
+                writeln!(
+                    header,
+                    "/* Tells if the secure image exports `{}` with the expected signature */",
+                    item.path
+                )?;
+                writeln!(header, "bool {c_name}_is_available(void);")?;
+
+                let return_statement = if prototype.return_type == "void" {
+                    ""
+                } else {
+                    "return "
+                };
+                writeln!(
+                    source,
+                    "
+/* {path} */
+static uintptr_t {c_name}_veneer;
+
+bool {c_name}_is_available(void)
+{{
+    return find_veneer(&{c_name}_veneer, {constant_name}_ID, {constant_name}_SIGNATURE) == TRUSTZONE_VENEER_OK;
+}}
+
+{import_entry}
+{declaration}
+{{
+    trustzone_veneer_error_t error = find_veneer(&{c_name}_veneer, {constant_name}_ID, {constant_name}_SIGNATURE);
+    if (error != TRUSTZONE_VENEER_OK) {{
+        trustzone_veneer_error(\"{path}\", error);
+    }}
+    (void)*(const volatile uint32_t *){c_name}_import;
+
+    {return_statement}(({function_pointer}){c_name}_veneer)({arguments});
+}}",
+                    path = item.path,
+                    import_entry = import_entry(&c_name, &constant_name, item.id),
+                    declaration = prototype.declaration(),
+                    function_pointer = prototype.function_pointer(),
+                    arguments = prototype.argument_names().join(", "),
+                )?;
+
+                imports.push((c_name, item));
+            }
+            ManifestItemKind::NonsecureShared { ty, mutable } => {
+                let ty = syn::parse_str::<syn::Type>(ty)
+                    .with_context(|| format!("Invalid type of `{}` in the manifest", item.path))?;
+                // An array is accessed through a pointer to its first element
+                let element_type = match abi::unparenthesize(&ty) {
+                    syn::Type::Array(array) => array.elem.as_ref(),
+                    ty => ty,
+                };
+                let element_type = c_type(element_type)
+                    .with_context(|| format!("The static `{}` has no C equivalent", item.path))?;
+                let qualifiers = if *mutable {
+                    "volatile"
+                } else {
+                    "const volatile"
+                };
+
+                writeln!(
+                    header,
+                    "/* Returns the address of the shared static `{}` */",
+                    item.path
+                )?;
+                writeln!(header, "{qualifiers} {element_type} *{c_name}(void);")?;
+                writeln!(
+                    header,
+                    "/* Tells if the secure image exports `{}` with the expected type */",
+                    item.path
+                )?;
+                writeln!(header, "bool {c_name}_is_available(void);")?;
+
+                writeln!(
+                    source,
+                    "
+/* {path} */
+static uintptr_t {c_name}_veneer;
+
+bool {c_name}_is_available(void)
+{{
+    return find_veneer(&{c_name}_veneer, {constant_name}_ID, {constant_name}_SIGNATURE) == TRUSTZONE_VENEER_OK;
+}}
+
+{import_entry}
+{qualifiers} {element_type} *{c_name}(void)
+{{
+    static {qualifiers} {element_type} *address;
+
+    if (address == NULL) {{
+        trustzone_veneer_error_t error = find_veneer(&{c_name}_veneer, {constant_name}_ID, {constant_name}_SIGNATURE);
+        if (error != TRUSTZONE_VENEER_OK) {{
+            trustzone_veneer_error(\"{path}\", error);
+        }}
+        (void)*(const volatile uint32_t *){c_name}_import;
+
+        /* The veneer of a static returns its address */
+        address = (({qualifiers} {element_type} *(*)(void)){c_name}_veneer)();
+    }}
+
+    return address;
+}}",
+                    path = item.path,
+                    import_entry = import_entry(&c_name, &constant_name, item.id),
+                )?;
+
+                imports.push((c_name, item));
+            }
+            ManifestItemKind::SecureCallable(function) => {
+                let signature = function.parse_signature().with_context(|| {
+                    format!("Invalid signature of `{}` in the manifest", item.path)
+                })?;
+                let prototype = CPrototype::new(&item.path, &c_name, &signature)?;
+
+                writeln!(
+                    header,
+                    "/* Implemented by this app and called by the secure image */"
+                )?;
+                writeln!(header, "{};", prototype.declaration())?;
+
+                // The same label and section as the `secure_callable` macro gives the veneer
+                let section_key = export_attributes::section_key(item.id);
+                let veneer_label = format!("{}_{}_veneer", name.to_uppercase(), item.id);
+                writeln!(
+                    source,
+                    "
+/* {path} */
+__asm__(
+    \".pushsection .ns_veneers.{section_key}, \\\"ax\\\"\\n\"
+    \".balign 4\\n\"
+    \".global {veneer_label}\\n\"
+    \".thumb_func\\n\"
+    \"{veneer_label}:\\n\"
+    \"    b.w {c_name}\\n\"
+    \"    .4byte {id:#010x}\\n\"
+    \"    .4byte {signature_hash:#010x}\\n\"
+    \"    .pushsection .ns_veneers_checksum, \\\"\\\", %nobits\\n\"
+    \"    .space {checksum}\\n\"
+    \"    .popsection\\n\"
+    \".popsection\\n\"
+);",
+                    path = item.path,
+                    id = item.id,
+                    signature_hash = item.signature_hash,
+                    checksum = trustzone_m_common::veneer_checksum(item.id, item.signature_hash),
+                )?;
+            }
+        }
+    }
+
+    writeln!(
+        source,
+        "
+void {module_name}_resolve_all(void)
+{{"
+    )?;
+    for (c_name, _) in imports.iter() {
+        writeln!(
+            source,
+            "    (void)find_veneer(&{c_name}_veneer, {0}_ID, {0}_SIGNATURE);",
+            c_name.to_uppercase()
+        )?;
+    }
+    writeln!(source, "}}")?;
+
+    writeln!(
+        header,
+        "
+#ifdef __cplusplus
+}}
+#endif
+
+#endif /* {include_guard} */"
+    )?;
+
+    Ok(CBindings { header, source })
+}
+
+/// The entry of an item in the list of the imports the secure image checks before it starts the app.
+///
+/// Like in the Rust bindings, only the function that reports the errors refers to the entry, so the
+/// linker drops it when that function isn't used and the item is only checked with `_is_available`.
+fn import_entry(c_name: &str, constant_name: &str, id: u32) -> String {
+    format!(
+        "/* Checked by the secure image before it starts this app, as long as the linker keeps it */
+static const uint32_t {c_name}_import[2] __attribute__((section(\".trustzone_imports.{id}\"))) = {{
+    {constant_name}_ID, {constant_name}_SIGNATURE
+}};
+"
+    )
+}
+
+/// The C version of the lowered signature of a function
+struct CPrototype {
+    name: String,
+    return_type: String,
+    /// The names and types of the arguments
+    arguments: Vec<(String, String)>,
+}
+
+impl CPrototype {
+    fn new(path: &str, c_name: &str, signature: &syn::Signature) -> Result<Self, anyhow::Error> {
+        anyhow::ensure!(
+            signature.variadic.is_none(),
+            "`{path}` is variadic, which the C bindings don't support"
+        );
+
+        // The C app doesn't have the runtime, but the types that would need it keep their name
+        let runtime = quote::quote!(::trustzone_m_nonsecure_rt);
+        let lowered_signature = abi::LoweredSignature::new(signature, runtime.clone());
+
+        let arguments = lowered_signature
+            .inputs
+            .iter()
+            .map(|input| {
+                let ty = lowered_c_type(&input.lowering, &input.lowered_ty).with_context(|| {
+                    format!(
+                        "The argument `{}` of `{path}` has no C equivalent",
+                        input.ident
+                    )
+                })?;
+                Ok((input.ident.to_string(), ty))
+            })
+            .collect::<Result<Vec<_>, anyhow::Error>>()?;
+
+        let return_type = match &lowered_signature.output {
+            None => String::from("void"),
+            Some((ty, lowering)) => lowered_c_type(lowering, &lowering.lowered_type(ty, &runtime))
+                .with_context(|| format!("The return type of `{path}` has no C equivalent"))?,
+        };
+
+        Ok(Self {
+            name: c_name.to_string(),
+            return_type,
+            arguments,
+        })
+    }
+
+    fn declaration(&self) -> String {
+        let arguments = if self.arguments.is_empty() {
+            String::from("void")
+        } else {
+            self.arguments
+                .iter()
+                .map(|(name, ty)| declare(ty, name))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+
+        format!("{}({arguments})", declare(&self.return_type, &self.name))
+    }
+
+    fn function_pointer(&self) -> String {
+        let arguments = if self.arguments.is_empty() {
+            String::from("void")
+        } else {
+            self.arguments
+                .iter()
+                .map(|(_, ty)| ty.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+
+        format!("{}({arguments})", declare(&self.return_type, "(*)"))
+    }
+
+    fn argument_names(&self) -> Vec<&str> {
+        self.arguments
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect()
+    }
+}
+
+/// The C type of a lowered value. A `bool` stays a `bool`, because C passes it as a 0 or 1 in a
+/// full register as well. Types that are checked by their `Boundary` implementation keep their
+/// name, like enums and structs that the C app declares itself.
+fn lowered_c_type(lowering: &Lowering, lowered_ty: &syn::Type) -> Result<String, anyhow::Error> {
+    match lowering {
+        Lowering::Bool => Ok(String::from("bool")),
+        Lowering::Checked(ty) => c_type(ty),
+        _ => c_type(lowered_ty),
+    }
+}
+
+/// The C type of a Rust type. Types that aren't known keep their name, so they have to be declared
+/// in C with the same name.
+fn c_type(ty: &syn::Type) -> Result<String, anyhow::Error> {
+    let unsupported = || {
+        let ty = abi::unparenthesize(ty);
+        anyhow::anyhow!("`{}` has no C equivalent", quote::quote!(#ty))
+    };
+
+    match abi::unparenthesize(ty) {
+        syn::Type::Ptr(syn::TypePtr {
+            mutability, elem, ..
+        }) => pointer_c_type(elem, mutability.is_some()),
+        syn::Type::Reference(syn::TypeReference {
+            mutability, elem, ..
+        }) => pointer_c_type(elem, mutability.is_some()),
+        syn::Type::Tuple(tuple) if tuple.elems.is_empty() => Ok(String::from("void")),
+        syn::Type::Path(type_path) if type_path.qself.is_none() => {
+            let last_segment = type_path.path.segments.last().ok_or_else(unsupported)?;
+            let name = last_segment.ident.to_string();
+
+            // References and pointers that can't be null are passed as a nullable pointer
+            if name == "Option" || name == "NonNull" {
+                return match &last_segment.arguments {
+                    syn::PathArguments::AngleBracketed(arguments) => match arguments.args.first() {
+                        Some(syn::GenericArgument::Type(inner)) if name == "Option" => {
+                            c_type(inner)
+                        }
+                        Some(syn::GenericArgument::Type(inner)) => pointer_c_type(inner, true),
+                        _ => Err(unsupported()),
+                    },
+                    _ => Err(unsupported()),
+                };
+            }
+
+            if !last_segment.arguments.is_none() {
+                return Err(unsupported());
+            }
+
+            let integer = name
+                .strip_prefix("NonZero")
+                .or_else(|| name.strip_prefix("Atomic"))
+                .map(str::to_lowercase);
+            let primitive = integer.as_deref().unwrap_or(&name);
+
+            Ok(match primitive {
+                "u8" => "uint8_t",
+                "u16" => "uint16_t",
+                "u32" => "uint32_t",
+                "u64" => "uint64_t",
+                "usize" => "uintptr_t",
+                "i8" => "int8_t",
+                "i16" => "int16_t",
+                "i32" => "int32_t",
+                "i64" => "int64_t",
+                "isize" => "intptr_t",
+                "f32" => "float",
+                "f64" => "double",
+                "bool" => "bool",
+                "c_void" => "void",
+                "c_char" => "char",
+                "c_schar" => "signed char",
+                "c_uchar" => "unsigned char",
+                "c_short" => "short",
+                "c_ushort" => "unsigned short",
+                "c_int" => "int",
+                "c_uint" => "unsigned int",
+                "c_long" => "long",
+                "c_ulong" => "unsigned long",
+                "c_longlong" => "long long",
+                "c_ulonglong" => "unsigned long long",
+                "c_float" => "float",
+                "c_double" => "double",
+                _ if integer.is_some() => return Err(unsupported()),
+                "char" | "u128" | "i128" | "str" => return Err(unsupported()),
+                _ => return Ok(name),
+            }
+            .to_string())
+        }
+        _ => Err(unsupported()),
+    }
+}
+
+/// The C type of a pointer to the given type. A pointer to an array points to its first element.
+fn pointer_c_type(elem: &syn::Type, mutable: bool) -> Result<String, anyhow::Error> {
+    let elem = match abi::unparenthesize(elem) {
+        syn::Type::Array(array) => array.elem.as_ref(),
+        elem => elem,
+    };
+    let pointee = c_type(elem)?;
+
+    Ok(match (mutable, pointee.ends_with('*')) {
+        (true, _) => declare(&pointee, "*"),
+        // The const of a pointer to a pointer goes after the pointee
+        (false, true) => format!("{pointee}const *"),
+        (false, false) => format!("const {pointee} *"),
+    })
+}
+
+/// Declares a name with the given type, like `uint8_t *buffer`
+fn declare(ty: &str, name: &str) -> String {
+    if ty.ends_with('*') {
+        format!("{ty}{name}")
+    } else {
+        format!("{ty} {name}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::Manifest;
+
+    #[test]
+    fn test_c_bindings() {
+        let manifest: Manifest =
+            serde_json::from_str(include_str!("../test-sources/c_bindings_manifest.json")).unwrap();
+
+        let bindings = generate_c_bindings(
+            &manifest.items,
+            "trustzone_bindings",
+            "trustzone_bindings.h",
+            true,
+        )
+        .unwrap();
+
+        pretty_assertions::assert_eq!(
+            bindings.header,
+            include_str!("../test-sources/c_bindings_result.h").replace("\r\n", "\n")
+        );
+        pretty_assertions::assert_eq!(
+            bindings.source,
+            include_str!("../test-sources/c_bindings_result.c").replace("\r\n", "\n")
+        );
+    }
+}
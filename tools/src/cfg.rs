@@ -0,0 +1,225 @@
+//! Evaluating `#[cfg(...)]` attributes of the scanned crate.
+//!
+//! Items that aren't compiled don't get bindings, so the bindings generator has to know the
+//! features and target of the crate it scans.
+
+use std::collections::HashSet;
+use syn::{punctuated::Punctuated, Attribute, Meta, Token};
+
+/// The configuration options that `#[cfg(...)]` attributes are evaluated against
+#[derive(Debug, Clone, Default)]
+pub struct CfgSet {
+    /// Options without a value, like `debug_assertions`
+    names: HashSet<String>,
+    /// Options with a value. An option can be set to multiple values, like `target_feature`.
+    values: HashSet<(String, String)>,
+}
+
+impl CfgSet {
+    /// An empty set, in which only `not(...)` predicates hold
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The options cargo gives to the build script of the crate that is being built.
+    ///
+    /// The features are read from the `CARGO_FEATURE_*` variables and the other options from the
+    /// `CARGO_CFG_*` variables.
+    pub fn from_env() -> Self {
+        let mut cfg = Self::from_target_env();
+
+        for (key, _) in std::env::vars() {
+            if let Some(feature) = key.strip_prefix("CARGO_FEATURE_") {
+                cfg = cfg.with_feature(feature);
+            }
+        }
+
+        cfg
+    }
+
+    /// The options of the target cargo gives to the build script, without the features.
+    ///
+    /// The options are read from the `CARGO_CFG_*` variables. Those are the same for every crate
+    /// that is built for the target, but the features are those of the crate that is being built,
+    /// so another crate has to get its features with [CfgSet::with_feature].
+    pub fn from_target_env() -> Self {
+        let mut cfg = Self::new();
+
+        for (key, value) in std::env::vars() {
+            let Some(name) = key.strip_prefix("CARGO_CFG_") else {
+                continue;
+            };
+            let name = name.to_lowercase();
+
+            if name == "feature" {
+                continue;
+            } else if value.is_empty() {
+                cfg = cfg.with_name(name);
+            } else {
+                for value in value.split(',') {
+                    cfg = cfg.with_value(name.clone(), value);
+                }
+            }
+        }
+
+        cfg
+    }
+
+    /// Sets an option without a value, like `#[cfg(debug_assertions)]`
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.names.insert(name.into());
+        self
+    }
+
+    /// Sets an option to a value, like `#[cfg(target_os = "none")]`
+    pub fn with_value(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        let name = name.into();
+        let value = value.into();
+
+        let value = if name == "feature" {
+            normalize_feature(&value)
+        } else {
+            value
+        };
+
+        self.values.insert((name, value));
+        self
+    }
+
+    /// Enables a feature, like `#[cfg(feature = "nrf9160")]`
+    pub fn with_feature(self, feature: impl Into<String>) -> Self {
+        self.with_value("feature", feature)
+    }
+
+    /// Returns whether all `#[cfg(...)]` attributes in the list hold
+    pub(crate) fn is_enabled(&self, attrs: &[Attribute]) -> syn::Result<bool> {
+        for attr in attrs.iter().filter(|attr| attr.path().is_ident("cfg")) {
+            if !self.evaluate(&attr.parse_args::<Meta>()?)? {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    fn evaluate(&self, predicate: &Meta) -> syn::Result<bool> {
+        match predicate {
+            Meta::Path(path) => Ok(self.names.contains(&path_name(path)?)),
+            Meta::NameValue(name_value) => {
+                let name = path_name(&name_value.path)?;
+                let syn::Expr::Lit(syn::ExprLit {
+                    lit: syn::Lit::Str(value),
+                    ..
+                }) = &name_value.value
+                else {
+                    return Err(syn::Error::new_spanned(
+                        &name_value.value,
+                        "Expected a string literal",
+                    ));
+                };
+
+                let value = if name == "feature" {
+                    normalize_feature(&value.value())
+                } else {
+                    value.value()
+                };
+
+                Ok(self.values.contains(&(name, value)))
+            }
+            Meta::List(list) => {
+                let predicates =
+                    list.parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)?;
+
+                if list.path.is_ident("all") {
+                    for predicate in predicates.iter() {
+                        if !self.evaluate(predicate)? {
+                            return Ok(false);
+                        }
+                    }
+                    Ok(true)
+                } else if list.path.is_ident("any") {
+                    for predicate in predicates.iter() {
+                        if self.evaluate(predicate)? {
+                            return Ok(true);
+                        }
+                    }
+                    Ok(false)
+                } else if list.path.is_ident("not") && predicates.len() == 1 {
+                    Ok(!self.evaluate(&predicates[0])?)
+                } else {
+                    Err(syn::Error::new_spanned(
+                        list,
+                        "Unsupported cfg predicate. Expected `all(...)`, `any(...)` or `not(...)`",
+                    ))
+                }
+            }
+        }
+    }
+}
+
+fn path_name(path: &syn::Path) -> syn::Result<String> {
+    path.get_ident()
+        .map(ToString::to_string)
+        .ok_or_else(|| syn::Error::new_spanned(path, "Expected a cfg option name"))
+}
+
+/// Cargo only gives the build script the feature names in uppercase and with `-` replaced by `_`
+fn normalize_feature(feature: &str) -> String {
+    feature.to_uppercase().replace('-', "_")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_enabled(cfg: &CfgSet, attr: &str) -> bool {
+        let attrs = syn::parse::Parser::parse_str(Attribute::parse_outer, attr).unwrap();
+        cfg.is_enabled(&attrs).unwrap()
+    }
+
+    #[test]
+    fn test_predicates() {
+        let cfg = CfgSet::new()
+            .with_feature("nrf-9160")
+            .with_name("debug_assertions")
+            .with_value("target_os", "none")
+            .with_value("target_feature", "dsp")
+            .with_value("target_feature", "thumb2");
+
+        assert!(is_enabled(&cfg, "#[cfg(feature = \"nrf-9160\")]"));
+        assert!(is_enabled(&cfg, "#[cfg(feature = \"NRF_9160\")]"));
+        assert!(!is_enabled(&cfg, "#[cfg(feature = \"nrf5340\")]"));
+        assert!(is_enabled(&cfg, "#[cfg(debug_assertions)]"));
+        assert!(!is_enabled(&cfg, "#[cfg(test)]"));
+        assert!(is_enabled(
+            &cfg,
+            "#[cfg(all(target_os = \"none\", target_feature = \"thumb2\"))]"
+        ));
+        assert!(!is_enabled(
+            &cfg,
+            "#[cfg(all(target_os = \"none\", target_feature = \"mve\"))]"
+        ));
+        assert!(is_enabled(
+            &cfg,
+            "#[cfg(any(test, target_feature = \"dsp\"))]"
+        ));
+        assert!(is_enabled(&cfg, "#[cfg(not(test))]"));
+        assert!(!is_enabled(&cfg, "#[cfg(debug_assertions)] #[cfg(test)]"));
+        assert!(is_enabled(&cfg, "#[inline] #[doc = \"Not a cfg\"]"));
+    }
+
+    #[test]
+    fn test_env() {
+        std::env::set_var("CARGO_FEATURE_TZ_CFG_TEST", "1");
+        std::env::set_var("CARGO_CFG_FEATURE", "tz-cfg-test");
+        std::env::set_var("CARGO_CFG_TZ_CFG_TEST", "a,b");
+
+        let target_cfg = CfgSet::from_target_env();
+        assert!(is_enabled(&target_cfg, "#[cfg(tz_cfg_test = \"b\")]"));
+        assert!(!is_enabled(&target_cfg, "#[cfg(feature = \"tz-cfg-test\")]"));
+
+        let cfg = CfgSet::from_env();
+        assert!(is_enabled(&cfg, "#[cfg(tz_cfg_test = \"a\")]"));
+        assert!(is_enabled(&cfg, "#[cfg(feature = \"tz-cfg-test\")]"));
+    }
+}
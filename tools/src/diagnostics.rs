@@ -0,0 +1,284 @@
+//! The errors the bindings generator reports about the scanned source code.
+//!
+//! A build script that panics buries the message in the cargo output, so every error points at the
+//! offending source code and is printed as a cargo warning before the build script fails.
+
+use std::{
+    fmt::{self, Display},
+    path::{Path, PathBuf},
+};
+
+/// A place in a source file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLocation {
+    /// The path of the file
+    pub file: PathBuf,
+    /// The line, starting at 1
+    pub line: usize,
+    /// The column, starting at 1
+    pub column: usize,
+    /// The number of characters the location spans on its line
+    pub length: usize,
+    /// The line of source code the location is on
+    pub source_line: String,
+}
+
+impl SourceLocation {
+    pub(crate) fn new(file: &Path, source_text: &str, span: proc_macro2::Span) -> Self {
+        let start = span.start();
+        let end = span.end();
+
+        let source_line = source_text
+            .lines()
+            .nth(start.line.saturating_sub(1))
+            .unwrap_or_default()
+            .to_string();
+        let length = if end.line == start.line {
+            end.column.saturating_sub(start.column)
+        } else {
+            source_line.chars().count().saturating_sub(start.column)
+        };
+
+        Self {
+            file: file.to_path_buf(),
+            line: start.line,
+            column: start.column + 1,
+            length: length.max(1),
+            source_line,
+        }
+    }
+
+    /// Writes the location and the line of code with the location underlined, like rustc does
+    fn write_snippet(&self, f: &mut fmt::Formatter<'_>, label: &str) -> fmt::Result {
+        let line_number = self.line.to_string();
+        let gutter = " ".repeat(line_number.len());
+
+        writeln!(f, "{gutter}--> {self}")?;
+        writeln!(f, "{gutter} |")?;
+        writeln!(f, "{line_number} | {}", self.source_line)?;
+        write!(
+            f,
+            "{gutter} | {}{}",
+            " ".repeat(self.column - 1),
+            "^".repeat(self.length)
+        )?;
+        if !label.is_empty() {
+            write!(f, " {label}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file.display(), self.line, self.column)
+    }
+}
+
+/// An exported item, identified by its path relative to the crate root
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExportedItem {
+    /// The path of the item, like `storage::read`
+    pub path: String,
+    /// Where the name of the item is, if the item was found in the source code instead of in a
+    /// manifest
+    pub location: Option<SourceLocation>,
+}
+
+/// An error in the source code of the crate the bindings are generated for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BindingsError {
+    /// The source code couldn't be parsed or has an invalid attribute
+    InvalidSource {
+        message: String,
+        location: SourceLocation,
+    },
+    /// Two exported items have the same path
+    DuplicateExport {
+        first: ExportedItem,
+        second: ExportedItem,
+    },
+    /// The bindings of two exported items in the same module have the same name, like `read` and
+    /// `try_read`, or the static `READ` and the function `read`
+    NameClash {
+        first: ExportedItem,
+        second: ExportedItem,
+        /// The path of the binding both items need
+        name: String,
+    },
+    /// The binding of an exported item has the name of an item every bindings module has
+    ReservedName { item: ExportedItem, name: String },
+    /// Two exported items have the same veneer id
+    HashCollision {
+        a: ExportedItem,
+        b: ExportedItem,
+        hash: u32,
+    },
+}
+
+impl ExportedItem {
+    fn write_snippet(&self, f: &mut fmt::Formatter<'_>, label: &str) -> fmt::Result {
+        match &self.location {
+            Some(location) => location.write_snippet(f, label),
+            None => write!(f, "  = note: `{}` is from a manifest", self.path),
+        }
+    }
+}
+
+impl BindingsError {
+    /// Prints the error as cargo warnings, so it shows up in the build output
+    pub(crate) fn print_cargo_warning(&self) {
+        for line in self.to_string().lines() {
+            println!("cargo:warning={line}");
+        }
+    }
+}
+
+impl Display for BindingsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BindingsError::InvalidSource { message, location } => {
+                writeln!(f, "error: {message}")?;
+                location.write_snippet(f, "")
+            }
+            BindingsError::DuplicateExport { first, second } => {
+                writeln!(f, "error: `{}` is exported more than once", second.path)?;
+                second.write_snippet(f, "exported again here")?;
+                writeln!(f)?;
+                first.write_snippet(f, "first exported here")
+            }
+            BindingsError::NameClash {
+                first,
+                second,
+                name,
+            } => {
+                writeln!(
+                    f,
+                    "error: the bindings of `{}` and `{}` are both named `{name}`",
+                    first.path, second.path
+                )?;
+                second.write_snippet(f, "this binding")?;
+                writeln!(f)?;
+                first.write_snippet(f, "clashes with this one")?;
+                writeln!(f)?;
+                write!(f, "help: rename one of them")
+            }
+            BindingsError::ReservedName { item, name } => {
+                writeln!(
+                    f,
+                    "error: the binding of `{}` would be named `{name}`, which the generated module already uses",
+                    item.path
+                )?;
+                item.write_snippet(f, "exported here")?;
+                writeln!(f)?;
+                write!(
+                    f,
+                    "help: rename it, the names `resolve_all`, `is_available` and `veneers` are reserved"
+                )
+            }
+            BindingsError::HashCollision { a, b, hash } => {
+                writeln!(
+                    f,
+                    "error: `{}` and `{}` have the same veneer id {hash:#010x}",
+                    a.path, b.path
+                )?;
+                a.write_snippet(f, "this has the same id")?;
+                writeln!(f)?;
+                b.write_snippet(f, "as this")?;
+                writeln!(f)?;
+                write!(
+                    f,
+                    "help: rename one of them or give one of them another explicit id with the `id = ...` argument"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for BindingsError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_duplicate_export_snippet() {
+        let source = "mod storage;\n\n#[nonsecure_callable]\npub fn init() {}\n";
+        let file = syn::parse_file(source).unwrap();
+        let syn::Item::Fn(function) = &file.items[1] else {
+            panic!()
+        };
+        let location =
+            SourceLocation::new(Path::new("src/lib.rs"), source, function.sig.ident.span());
+
+        let error = BindingsError::DuplicateExport {
+            first: ExportedItem {
+                path: String::from("init"),
+                location: Some(location.clone()),
+            },
+            second: ExportedItem {
+                path: String::from("init"),
+                location: Some(location),
+            },
+        };
+
+        pretty_assertions::assert_eq!(
+            error.to_string(),
+            "error: `init` is exported more than once
+ --> src/lib.rs:4:8
+  |
+4 | pub fn init() {}
+  |        ^^^^ exported again here
+ --> src/lib.rs:4:8
+  |
+4 | pub fn init() {}
+  |        ^^^^ first exported here"
+        );
+    }
+
+    #[test]
+    fn test_name_clash_snippet() {
+        let source = "#[nonsecure_shared]\npub static READ: u32 = 0;\n\n#[nonsecure_callable]\npub fn read() {}\n";
+        let file = syn::parse_file(source).unwrap();
+        let (syn::Item::Static(item_static), syn::Item::Fn(function)) =
+            (&file.items[0], &file.items[1])
+        else {
+            panic!()
+        };
+
+        let error = BindingsError::NameClash {
+            first: ExportedItem {
+                path: String::from("READ"),
+                location: Some(SourceLocation::new(
+                    Path::new("src/lib.rs"),
+                    source,
+                    item_static.ident.span(),
+                )),
+            },
+            second: ExportedItem {
+                path: String::from("read"),
+                location: Some(SourceLocation::new(
+                    Path::new("src/lib.rs"),
+                    source,
+                    function.sig.ident.span(),
+                )),
+            },
+            name: String::from("read"),
+        };
+
+        pretty_assertions::assert_eq!(
+            error.to_string(),
+            "error: the bindings of `READ` and `read` are both named `read`
+ --> src/lib.rs:5:8
+  |
+5 | pub fn read() {}
+  |        ^^^^ this binding
+ --> src/lib.rs:2:12
+  |
+2 | pub static READ: u32 = 0;
+  |            ^^^^ clashes with this one
+help: rename one of them"
+        );
+    }
+}
@@ -0,0 +1,173 @@
+//! The arguments of the `secure_callable`, `nonsecure_callable` and `nonsecure_shared` attributes.
+//!
+//! These are read by the macros and by the bindings generator, so both sides agree on the name and
+//! id of an exported function.
+
+use proc_macro2::TokenStream;
+
+/// The arguments that can be given to the `secure_callable`, `nonsecure_callable` and
+/// `nonsecure_shared` attributes.
+///
+/// - `name = "..."`: The name the function is exported with. The bindings use this name and by
+///   default the id is derived from it. This allows the function to be renamed in Rust without
+///   changing the interface.
+/// - `id = 0x...`: The id of the function. This overrides the id that is derived from the name,
+///   which can be used to fix a hash collision.
+/// - `on_reject = ...`: Only for `nonsecure_callable`. The value the function returns when the
+///   nonsecure caller passes a pointer it has no access to. It doesn't change the interface.
+#[derive(Default, Clone)]
+pub(crate) struct ExportAttributes {
+    pub(crate) name: Option<syn::LitStr>,
+    pub(crate) id: Option<u32>,
+    pub(crate) on_reject: Option<syn::Expr>,
+}
+
+impl ExportAttributes {
+    /// Parses the arguments as given to the attribute macro
+    pub(crate) fn parse(attr: TokenStream) -> syn::Result<Self> {
+        let mut attributes = Self::default();
+
+        let parser = syn::meta::parser(|meta| attributes.parse_meta(meta));
+        syn::parse::Parser::parse2(parser, attr)?;
+
+        Ok(attributes)
+    }
+
+    /// Parses the arguments of the attribute as found on a function in the source code
+    pub(crate) fn from_attribute(attr: &syn::Attribute) -> syn::Result<Self> {
+        let mut attributes = Self::default();
+
+        if let syn::Meta::List(_) = attr.meta {
+            attr.parse_nested_meta(|meta| attributes.parse_meta(meta))?;
+        }
+
+        Ok(attributes)
+    }
+
+    fn parse_meta(&mut self, meta: syn::meta::ParseNestedMeta) -> syn::Result<()> {
+        if meta.path.is_ident("name") {
+            let name: syn::LitStr = meta.value()?.parse()?;
+            if syn::parse_str::<syn::Ident>(&name.value()).is_err() {
+                return Err(syn::Error::new_spanned(
+                    name,
+                    "The name must be a valid Rust identifier",
+                ));
+            }
+            self.name = Some(name);
+            Ok(())
+        } else if meta.path.is_ident("id") {
+            let id: syn::LitInt = meta.value()?.parse()?;
+            let value = id.base10_parse::<u32>()?;
+            if value == 0 {
+                return Err(syn::Error::new_spanned(
+                    id,
+                    "The id can't be 0, that marks the end of the veneer table",
+                ));
+            }
+            self.id = Some(value);
+            Ok(())
+        } else if meta.path.is_ident("on_reject") {
+            self.on_reject = Some(meta.value()?.parse()?);
+            Ok(())
+        } else {
+            Err(meta.error(
+                "Unknown argument. Expected `name = \"...\"`, `id = ...` or `on_reject = ...`",
+            ))
+        }
+    }
+
+    /// Fails if `on_reject` is given to an attribute whose item doesn't check the arguments of
+    /// the nonsecure world
+    pub(crate) fn without_on_reject(self) -> syn::Result<Self> {
+        match &self.on_reject {
+            Some(on_reject) => Err(syn::Error::new_spanned(
+                on_reject,
+                "`on_reject` is only supported by `nonsecure_callable`",
+            )),
+            None => Ok(self),
+        }
+    }
+
+    /// The name the item with the given identifier is exported as
+    pub(crate) fn export_name(&self, ident: &syn::Ident) -> String {
+        match &self.name {
+            Some(name) => name.value(),
+            None => ident.to_string(),
+        }
+    }
+
+    /// The id of the veneer of the item with the given identifier in the module with the given path.
+    /// The module path is relative to the crate root.
+    pub(crate) fn id(&self, module_path: &[String], ident: &syn::Ident) -> u32 {
+        match self.id {
+            Some(id) => id,
+            None => namespaced_id(module_path, &self.export_name(ident)),
+        }
+    }
+
+    /// The expression for the id of the veneer that the macros put in the veneer.
+    ///
+    /// The macros don't know in which module they are, so unless the id is given explicitly the id
+    /// is calculated at compile time by `veneer_id` of the runtime.
+    pub(crate) fn id_expression(&self, ident: &syn::Ident, runtime: TokenStream) -> TokenStream {
+        match self.id {
+            Some(id) => quote::quote!(#id),
+            None => {
+                let export_name = self.export_name(ident);
+                quote::quote!(#runtime::veneer_id(module_path!(), #export_name))
+            }
+        }
+    }
+}
+
+/// The number that is added to the id in the name of the section of a veneer. It gives the names
+/// of all veneer sections the same number of digits, so sorting them by name sorts them by id.
+const SECTION_KEY_OFFSET: u64 = 10_000_000_000;
+
+/// The expression of the number the macros put in the name of the section of a veneer.
+/// The linker script sorts the veneer tables by it.
+pub(crate) fn section_key_expression(id_expression: &TokenStream) -> TokenStream {
+    quote::quote!((#id_expression) as u64 + #SECTION_KEY_OFFSET)
+}
+
+/// The number in the name of the section of the veneer with the given id
+pub(crate) fn section_key(id: u32) -> u64 {
+    id as u64 + SECTION_KEY_OFFSET
+}
+
+/// The hash of the path of the item relative to the crate root.
+/// This must match `veneer_id` of the runtimes.
+fn namespaced_id(module_path: &[String], name: &str) -> u32 {
+    if module_path.is_empty() {
+        crate::hash_vector_name(name)
+    } else {
+        crate::hash_vector_name(&format!("{}::{name}", module_path.join("::")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_namespaced_id_matches_runtime() {
+        // Both runtimes re-export the same function, but the apps only see their own runtime
+        for veneer_id in [
+            trustzone_m_nonsecure_rt::veneer_id,
+            trustzone_m_secure_rt::veneer_id,
+        ] {
+            assert_eq!(
+                namespaced_id(&[], "double"),
+                veneer_id("my_crate", "double")
+            );
+            assert_eq!(
+                namespaced_id(&["storage".into(), "flash".into()], "init"),
+                veneer_id("my_crate::storage::flash", "init")
+            );
+        }
+        assert_ne!(
+            namespaced_id(&["storage".into()], "init"),
+            namespaced_id(&["radio".into()], "init")
+        );
+    }
+}
@@ -0,0 +1,582 @@
+//! Reading a CMSE import library and the C header of a secure image that isn't built with this crate.
+//!
+//! Vendor secure images, like TF-M, ship an import library that `--cmse-implib` created. It is an
+//! object file with an absolute symbol at the address of the `SG` veneer of every entry function.
+//! Those veneers don't have an id and signature hash, so the nonsecure app is linked against their
+//! addresses directly and the signatures come from the prototypes in the header.
+
+use anyhow::Context;
+use object::{Object, ObjectSymbol};
+use std::{collections::HashMap, path::Path};
+
+use crate::{abi, manifest::ManifestItem, secure_image::SecureImageVeneer};
+
+/// The prefix of the symbols of the secure entry functions the compiler creates
+const SECURE_ENTRY_PREFIX: &str = "__acle_se_";
+
+/// The functions of the import library that have a prototype in the header
+pub(crate) struct ImportLibrary {
+    pub(crate) items: Vec<ManifestItem>,
+    /// The veneers of the items, with the same id and signature hash as the items
+    pub(crate) veneers: Vec<SecureImageVeneer>,
+}
+
+/// Reads the veneers of the import library and the prototypes of the functions from the header.
+///
+/// Functions without a prototype or with a prototype that can't be translated are skipped with a
+/// cargo warning.
+pub(crate) fn read_import_library(
+    library_path: &Path,
+    header_path: &Path,
+) -> Result<ImportLibrary, anyhow::Error> {
+    let data = std::fs::read(library_path).with_context(|| {
+        format!(
+            "Could not read the import library {}",
+            library_path.display()
+        )
+    })?;
+    let file = object::File::parse(&*data).with_context(|| {
+        format!(
+            "Could not parse the import library {}",
+            library_path.display()
+        )
+    })?;
+
+    let header_text = std::fs::read_to_string(header_path)
+        .with_context(|| format!("Could not read the header {}", header_path.display()))?;
+    let prototypes = parse_prototypes(&header_text);
+
+    let mut import_library = ImportLibrary {
+        items: Vec::new(),
+        veneers: Vec::new(),
+    };
+
+    // The veneer has the name of the function. Libraries that only have the symbol of the entry
+    // function have it at the address of the veneer.
+    let mut symbols: Vec<(String, String, u64)> = Vec::new();
+    for symbol in file.symbols() {
+        let symbol_name = symbol.name()?;
+        if !symbol.is_global() || symbol.is_undefined() {
+            continue;
+        }
+
+        let name = symbol_name
+            .strip_prefix(SECURE_ENTRY_PREFIX)
+            .unwrap_or(symbol_name);
+        match symbols
+            .iter_mut()
+            .find(|(other_name, ..)| other_name == name)
+        {
+            Some(existing) if name == symbol_name => {
+                *existing = (name.to_string(), symbol_name.to_string(), symbol.address())
+            }
+            Some(_) => {}
+            None => symbols.push((name.to_string(), symbol_name.to_string(), symbol.address())),
+        }
+    }
+
+    for (name, symbol_name, address) in symbols {
+        let name = name.as_str();
+        let signature = match prototypes.get(name) {
+            Some(Ok(signature)) => signature,
+            Some(Err(error)) => {
+                println!("cargo:warning=Skipped `{name}` of the import library: {error}");
+                continue;
+            }
+            None => {
+                println!(
+                    "cargo:warning=Skipped `{name}` of the import library, because it has no prototype in {}",
+                    header_path.display()
+                );
+                continue;
+            }
+        };
+
+        let id = crate::hash_vector_name(name);
+        let signature_hash = abi::signature_hash(signature);
+
+        import_library.items.push(ManifestItem::function(
+            &[],
+            signature,
+            false,
+            id,
+            signature_hash,
+        ));
+        import_library.veneers.push(SecureImageVeneer {
+            name: symbol_name,
+            // Clear the thumb bit
+            address: address & !1,
+            id,
+            signature: signature_hash,
+        });
+    }
+
+    Ok(import_library)
+}
+
+/// Finds the function prototypes in a C header and translates them to Rust signatures.
+///
+/// This only understands what headers of secure images usually contain: function prototypes, and
+/// typedefs of primitive types that the prototypes use. Everything else is skipped.
+fn parse_prototypes(header_text: &str) -> HashMap<String, Result<syn::Signature, String>> {
+    let mut typedefs = HashMap::new();
+    let mut prototypes = HashMap::new();
+
+    for declaration in declarations(&tokenize(header_text)) {
+        // Everything that has a body or is an object is of no interest
+        if declaration.iter().any(|token| token == "{" || token == "=") {
+            continue;
+        }
+
+        if declaration.first().is_some_and(|token| token == "typedef") {
+            // Only typedefs of plain types, like `typedef int32_t psa_status_t;`
+            if let Some((name, base)) = declaration[1..].split_last() {
+                if !base.is_empty() && !base.iter().any(|token| token == "(") {
+                    if let Ok(ty) = c_type(base, &typedefs) {
+                        typedefs.insert(name.clone(), ty);
+                    }
+                }
+            }
+            continue;
+        }
+
+        // The name of a function is the identifier in front of the first parenthesis
+        let Some(open) = declaration.iter().position(|token| token == "(") else {
+            continue;
+        };
+        if open == 0 || !is_identifier(&declaration[open - 1]) {
+            continue;
+        }
+        let name = declaration[open - 1].clone();
+
+        prototypes.insert(
+            name.clone(),
+            function_signature(
+                &name,
+                &declaration[..open - 1],
+                &declaration[open..],
+                &typedefs,
+            ),
+        );
+    }
+
+    prototypes
+}
+
+/// Translates a C function prototype to the Rust signature of an `extern "C"` function
+fn function_signature(
+    name: &str,
+    return_type: &[String],
+    parameters: &[String],
+    typedefs: &HashMap<String, syn::Type>,
+) -> Result<syn::Signature, String> {
+    // The parameters have to be the last thing in the declaration, so function pointers are out
+    if parameters.last().map(String::as_str) != Some(")")
+        || parameters[1..parameters.len() - 1]
+            .iter()
+            .any(|token| token == "(" || token == ")")
+    {
+        return Err(String::from("its prototype is too complex"));
+    }
+    let parameters = &parameters[1..parameters.len() - 1];
+
+    let mut inputs = Vec::new();
+    let mut is_unsafe = false;
+    let is_void = parameters.is_empty() || (parameters.len() == 1 && parameters[0] == "void");
+    if !is_void {
+        for (index, parameter) in parameters.split(|token| token == ",").enumerate() {
+            if parameter.iter().any(|token| token == "...") {
+                return Err(String::from("variadic functions aren't supported"));
+            }
+
+            // A parameter may have a name and array parameters are pointers
+            let (mut type_tokens, parameter_name) = match parameter.iter().position(|t| t == "[") {
+                Some(bracket) => {
+                    let mut type_tokens = parameter[..bracket].to_vec();
+                    let parameter_name = type_tokens.pop();
+                    type_tokens.push(String::from("*"));
+                    (type_tokens, parameter_name)
+                }
+                None => match parameter.split_last() {
+                    Some((last, rest))
+                        if has_base_type(rest) && is_identifier(last) && !is_type_keyword(last) =>
+                    {
+                        (rest.to_vec(), Some(last.clone()))
+                    }
+                    _ => (parameter.to_vec(), None),
+                },
+            };
+            type_tokens.retain(|token| token != "register");
+
+            let ty = c_type(&type_tokens, typedefs)?;
+            is_unsafe |= matches!(ty, syn::Type::Ptr(_));
+
+            let ident = parameter_name
+                .filter(|parameter_name| syn::parse_str::<syn::Ident>(parameter_name).is_ok())
+                .map(|parameter_name| quote::format_ident!("{}", parameter_name))
+                .unwrap_or_else(|| quote::format_ident!("arg{}", index));
+            inputs.push(quote::quote!(#ident: #ty));
+        }
+    }
+
+    let return_type = c_type(return_type, typedefs)?;
+    let output = if matches!(&return_type, syn::Type::Tuple(tuple) if tuple.elems.is_empty()) {
+        quote::quote!()
+    } else {
+        quote::quote!(-> #return_type)
+    };
+
+    let ident =
+        syn::parse_str::<syn::Ident>(name).map_err(|_| format!("`{name}` is a keyword in Rust"))?;
+    // Passing pointers to the secure image is unsafe, because it trusts them to be valid
+    let unsafety = is_unsafe.then(|| quote::quote!(unsafe));
+
+    syn::parse2(quote::quote!(#unsafety extern "C" fn #ident(#(#inputs),*) #output))
+        .map_err(|e| e.to_string())
+}
+
+/// Translates a C type to a Rust type. Pointers to types that aren't known become void pointers.
+fn c_type(tokens: &[String], typedefs: &HashMap<String, syn::Type>) -> Result<syn::Type, String> {
+    // The qualifiers and specifiers in front of the first `*` belong to the base type
+    let pointer_start = tokens
+        .iter()
+        .position(|token| token == "*")
+        .unwrap_or(tokens.len());
+    let base = &tokens[..pointer_start];
+
+    let mut const_pointee = base.iter().any(|token| token == "const");
+    let base_specifiers = base
+        .iter()
+        .map(String::as_str)
+        .filter(|token| !is_ignored_qualifier(token))
+        .collect::<Vec<_>>();
+
+    let base_type = base_type(&base_specifiers, typedefs);
+    if pointer_start == tokens.len() {
+        return match base_type {
+            Some(ty) => Ok(ty),
+            None => Err(format!(
+                "`{}` can't be passed by value, because its definition isn't known",
+                base_specifiers.join(" ")
+            )),
+        };
+    }
+
+    let mut ty = base_type.unwrap_or_else(|| syn::parse_quote!(core::ffi::c_void));
+    if matches!(&ty, syn::Type::Tuple(tuple) if tuple.elems.is_empty()) {
+        ty = syn::parse_quote!(core::ffi::c_void);
+    }
+
+    // Every `*` can be followed by a `const` that makes the next pointer point to a const
+    for token in tokens[pointer_start..].iter() {
+        match token.as_str() {
+            "*" => {
+                ty = if const_pointee {
+                    syn::parse_quote!(*const #ty)
+                } else {
+                    syn::parse_quote!(*mut #ty)
+                };
+                const_pointee = false;
+            }
+            "const" => const_pointee = true,
+            token if is_ignored_qualifier(token) => {}
+            token => return Err(format!("unexpected `{token}` in a type")),
+        }
+    }
+
+    Ok(ty)
+}
+
+/// The Rust type of a C type without pointers, if it is known
+fn base_type(specifiers: &[&str], typedefs: &HashMap<String, syn::Type>) -> Option<syn::Type> {
+    let rust_type = match specifiers {
+        ["void"] => "()",
+        ["_Bool"] | ["bool"] => "bool",
+        ["char"] => "core::ffi::c_char",
+        ["signed", "char"] | ["int8_t"] => "i8",
+        ["unsigned", "char"] | ["uint8_t"] => "u8",
+        ["short"] | ["short", "int"] | ["signed", "short"] | ["int16_t"] => "i16",
+        ["unsigned", "short"] | ["unsigned", "short", "int"] | ["uint16_t"] => "u16",
+        ["int"] | ["signed"] | ["signed", "int"] | ["long"] | ["long", "int"] | ["int32_t"] => {
+            "i32"
+        }
+        ["unsigned"] | ["unsigned", "int"] | ["unsigned", "long"] | ["uint32_t"] => "u32",
+        ["long", "long"] | ["long", "long", "int"] | ["int64_t"] => "i64",
+        ["unsigned", "long", "long"] | ["unsigned", "long", "long", "int"] | ["uint64_t"] => "u64",
+        ["intptr_t"] | ["ptrdiff_t"] | ["ssize_t"] => "isize",
+        ["uintptr_t"] | ["size_t"] => "usize",
+        ["float"] => "f32",
+        ["double"] => "f64",
+        [name] => return typedefs.get(*name).cloned(),
+        _ => return None,
+    };
+
+    Some(syn::parse_str(rust_type).unwrap())
+}
+
+/// Qualifiers that don't change how a value is passed
+fn is_ignored_qualifier(token: &str) -> bool {
+    matches!(
+        token,
+        "const" | "volatile" | "restrict" | "__restrict" | "struct" | "enum" | "union"
+    )
+}
+
+/// Whether the tokens still have a type when the last identifier is the name of the parameter
+fn has_base_type(tokens: &[String]) -> bool {
+    tokens
+        .iter()
+        .any(|token| is_identifier(token) && !is_ignored_qualifier(token))
+        && !matches!(
+            tokens.last().map(String::as_str),
+            Some("struct" | "enum" | "union")
+        )
+}
+
+/// The keywords that can be the last word of a C type
+fn is_type_keyword(token: &str) -> bool {
+    matches!(
+        token,
+        "void"
+            | "char"
+            | "short"
+            | "int"
+            | "long"
+            | "float"
+            | "double"
+            | "signed"
+            | "unsigned"
+            | "_Bool"
+            | "bool"
+    )
+}
+
+fn is_identifier(token: &str) -> bool {
+    token
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+}
+
+/// Splits the C source into identifiers, numbers, string literals and punctuation, without
+/// comments and preprocessor directives
+fn tokenize(text: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    let mut at_line_start = true;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => {
+                at_line_start = true;
+                continue;
+            }
+            c if c.is_whitespace() => continue,
+            // A preprocessor directive continues until a line that doesn't end with a backslash
+            '#' if at_line_start => {
+                let mut previous = '#';
+                for c in chars.by_ref() {
+                    if c == '\n' && previous != '\\' {
+                        break;
+                    }
+                    if !c.is_whitespace() || c == '\n' {
+                        previous = c;
+                    }
+                }
+                at_line_start = true;
+                continue;
+            }
+            '/' if chars.peek() == Some(&'/') => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+                at_line_start = true;
+                continue;
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = ' ';
+                for c in chars.by_ref() {
+                    if previous == '*' && c == '/' {
+                        break;
+                    }
+                    previous = c;
+                }
+                continue;
+            }
+            '"' => {
+                let mut literal = String::from('"');
+                while let Some(c) = chars.next() {
+                    literal.push(c);
+                    if c == '\\' {
+                        literal.extend(chars.next());
+                    } else if c == '"' {
+                        break;
+                    }
+                }
+                tokens.push(literal);
+            }
+            '.' if chars.peek() == Some(&'.') => {
+                chars.next();
+                chars.next();
+                tokens.push(String::from("..."));
+            }
+            c if c.is_ascii_alphanumeric() || c == '_' => {
+                let mut word = String::from(c);
+                while let Some(&c) = chars.peek() {
+                    if !c.is_ascii_alphanumeric() && c != '_' {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                tokens.push(word);
+            }
+            c => tokens.push(c.to_string()),
+        }
+
+        at_line_start = false;
+    }
+
+    tokens
+}
+
+/// Splits the tokens into declarations, without the attributes and the `extern "C" { ... }` blocks
+/// around them. The body of a function definition ends its declaration.
+fn declarations(tokens: &[String]) -> Vec<Vec<String>> {
+    let mut declarations = Vec::new();
+    let mut declaration: Vec<String> = Vec::new();
+    let mut depth = 0;
+    let mut index = 0;
+
+    while index < tokens.len() {
+        let token = &tokens[index];
+        index += 1;
+
+        match token.as_str() {
+            // `extern "C" {` only changes the linkage
+            "extern" if tokens.get(index).is_some_and(|t| t.starts_with('"')) => {
+                index += 1;
+                if tokens.get(index).is_some_and(|t| t == "{") {
+                    index += 1;
+                }
+            }
+            "extern" | "static" | "inline" | "__inline" | "__STATIC_INLINE" => {}
+            // Attributes like `__attribute__((cmse_nonsecure_entry))` don't change the signature
+            "__attribute__" | "__declspec" | "__asm__" | "__asm" => {
+                let mut parenthesis_depth = 0;
+                while let Some(token) = tokens.get(index) {
+                    index += 1;
+                    match token.as_str() {
+                        "(" => parenthesis_depth += 1,
+                        ")" => parenthesis_depth -= 1,
+                        _ => {}
+                    }
+                    if parenthesis_depth == 0 {
+                        break;
+                    }
+                }
+            }
+            "{" => {
+                depth += 1;
+                declaration.push(token.clone());
+            }
+            "}" if depth == 0 => {
+                // The end of an `extern "C"` block
+            }
+            "}" => {
+                depth -= 1;
+                declaration.push(token.clone());
+                // A function definition isn't followed by a semicolon
+                let parenthesis = declaration.iter().position(|t| t == "(");
+                let brace = declaration.iter().position(|t| t == "{");
+                if depth == 0 && matches!((parenthesis, brace), (Some(p), Some(b)) if p < b) {
+                    declarations.push(std::mem::take(&mut declaration));
+                }
+            }
+            ";" if depth == 0 => declarations.push(std::mem::take(&mut declaration)),
+            _ => declaration.push(token.clone()),
+        }
+    }
+
+    declarations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prototypes() {
+        let header = r#"
+#ifndef __TFM_API_H__
+#define __TFM_API_H__ \
+    1
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef int32_t psa_status_t;
+typedef int32_t psa_handle_t;
+
+struct psa_invec {
+    const void *base;
+    size_t len;
+};
+
+/* Returns the version of the framework */
+uint32_t psa_framework_version(void);
+
+psa_status_t psa_call(psa_handle_t handle, int32_t type,
+                      const struct psa_invec *in_vec, size_t in_len,
+                      struct psa_outvec *out_vec, size_t out_len);
+
+__attribute__((cmse_nonsecure_entry)) void psa_close(psa_handle_t handle);
+
+static inline int helper(int x) { return x; }
+
+void log_names(const char *const *names, uint8_t buffer[16], unsigned int);
+int printf(const char *format, ...);
+struct psa_invec by_value(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
+"#;
+
+        let prototypes = parse_prototypes(header);
+        let signature = |name: &str| {
+            let signature = prototypes[name].as_ref().unwrap();
+            quote::quote!(#signature).to_string()
+        };
+
+        assert_eq!(
+            signature("psa_framework_version"),
+            quote::quote!(extern "C" fn psa_framework_version() -> u32).to_string()
+        );
+        assert_eq!(
+            signature("psa_call"),
+            // `type` is a keyword in Rust
+            quote::quote!(unsafe extern "C" fn psa_call(handle: i32, arg1: i32, in_vec: *const core::ffi::c_void, in_len: usize, out_vec: *mut core::ffi::c_void, out_len: usize) -> i32).to_string()
+        );
+        assert_eq!(
+            signature("psa_close"),
+            quote::quote!(extern "C" fn psa_close(handle: i32)).to_string()
+        );
+        // Inline functions are part of the nonsecure app
+        assert!(!prototypes.contains_key("helper"));
+        assert_eq!(
+            signature("log_names"),
+            quote::quote!(unsafe extern "C" fn log_names(names: *const *const core::ffi::c_char, buffer: *mut u8, arg2: u32)).to_string()
+        );
+        assert!(prototypes["printf"].is_err());
+        assert!(prototypes["by_value"].is_err());
+    }
+}
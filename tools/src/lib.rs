@@ -1,8 +1,24 @@
+mod abi;
+mod api_trait;
 mod bindings_generator;
+mod c_bindings;
+mod cfg;
+mod diagnostics;
+mod export_attributes;
+mod import_library;
+mod lock_file;
+mod manifest;
+mod metadata;
+mod secure_image;
+mod simulation;
+mod type_paths;
 pub mod secure_callable_macro;
 pub mod nonsecure_callable_macro;
+pub mod nonsecure_shared_macro;
 
-pub use bindings_generator::generate_bindings;
+pub use bindings_generator::{generate_bindings, BindingsBuilder, VeneerLookup};
+pub use cfg::CfgSet;
+pub use diagnostics::{BindingsError, ExportedItem, SourceLocation};
 
 pub const fn hash_vector_name(name: &str) -> u32 {
     crc::Crc::<u32>::new(&crc::CRC_32_CKSUM).checksum(name.as_bytes())
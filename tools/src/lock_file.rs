@@ -0,0 +1,540 @@
+//! The lock file of the interface between the secure and the nonsecure app.
+//!
+//! The two apps are often built and released separately, so an accidental change of the interface
+//! is only noticed when the images are combined. The lock file records the path, veneer id and
+//! canonical signature of every exported item. It is committed with the source code and the builds
+//! of both apps check the items they generate bindings for and the items they export against it.
+//!
+//! The builds only read the lock file. Any difference to it fails the build with a diff, until the
+//! lock file is updated by a build that asks for it. Removing an item or changing its id or
+//! signature is a breaking change, which is only written when breaking changes are allowed as well.
+//!
+//! The builds of both apps can update the lock file at the same time, each with its own items. An
+//! update holds a file lock while it reads, merges and writes the lock file, so the other build
+//! waits and merges into what was written. The lock file is replaced with a complete new file, so
+//! a build that only reads it never sees half of an update.
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    fs::{self, File},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    abi,
+    manifest::{ManifestItem, ManifestItemKind},
+};
+
+/// The version of the lock file format. Lock files with another version are rejected.
+const LOCK_FILE_VERSION: u32 = 1;
+
+/// The environment variable that updates the lock file when it's set to anything but `0`
+pub(crate) const UPDATE_ENV: &str = "TRUSTZONE_UPDATE_LOCK";
+
+/// The environment variable that allows breaking changes when it's set to anything but `0`
+pub(crate) const ALLOW_BREAKING_ENV: &str = "TRUSTZONE_ALLOW_BREAKING";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct LockFile {
+    version: u32,
+    items: Vec<LockedItem>,
+}
+
+/// An item of the interface as it's recorded in the lock file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct LockedItem {
+    /// The path of the item relative to the crate root, like `storage::read`
+    path: String,
+    /// The direction of the item, like in the manifest
+    direction: String,
+    /// The id of the veneer
+    id: u32,
+    /// The signature without names, or the type of a static
+    signature: String,
+}
+
+impl LockedItem {
+    fn new(item: &ManifestItem) -> Result<Self, anyhow::Error> {
+        // The same canonical form the signature hash of the veneer is calculated from
+        let signature = match &item.kind {
+            ManifestItemKind::SecureCallable(function)
+            | ManifestItemKind::NonsecureCallable(function) => abi::canonical_signature(
+                &function
+                    .parse_signature()
+                    .with_context(|| format!("Invalid signature of `{}`", item.path))?,
+            ),
+            ManifestItemKind::NonsecureShared { ty, mutable } => abi::canonical_static(
+                &syn::parse_str(ty).with_context(|| format!("Invalid type of `{}`", item.path))?,
+                *mutable,
+            ),
+        };
+
+        Ok(Self {
+            path: item.path.clone(),
+            direction: item.direction().to_string(),
+            id: item.id,
+            signature,
+        })
+    }
+    /// The line of the item in a diff of the lock file
+    fn diff_line(&self, sign: char) -> String {
+        format!(
+            "{sign} {} `{}` {:#010x} `{}`",
+            self.direction, self.path, self.id, self.signature
+        )
+    }
+}
+
+/// A difference between the lock file and the items that are built
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Change {
+    Added(LockedItem),
+    Removed(LockedItem),
+    Changed {
+        locked: LockedItem,
+        built: LockedItem,
+    },
+}
+
+impl Change {
+    fn is_breaking(&self) -> bool {
+        !matches!(self, Change::Added(_))
+    }
+
+    /// The lines of the items of the lock file that are removed and added by the change
+    fn diff(&self) -> String {
+        match self {
+            Change::Added(item) => item.diff_line('+'),
+            Change::Removed(item) => item.diff_line('-'),
+            Change::Changed { locked, built } => {
+                format!("{}\n{}", locked.diff_line('-'), built.diff_line('+'))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::Added(item) => write!(f, "`{}` was added", item.path),
+            Change::Removed(item) => write!(f, "`{}` was removed", item.path),
+            Change::Changed { locked, built } if locked.signature != built.signature => write!(
+                f,
+                "the signature of `{}` changed from `{}` to `{}`",
+                built.path, locked.signature, built.signature
+            ),
+            Change::Changed { locked, built } => write!(
+                f,
+                "the veneer id of `{}` changed from {:#010x} to {:#010x}",
+                built.path, locked.id, built.id
+            ),
+        }
+    }
+}
+
+/// Compares the locked items of the given directions with the built items
+fn compare(locked: &[LockedItem], built: &[LockedItem], directions: &[&str]) -> Vec<Change> {
+    let mut changes = Vec::new();
+
+    let find = |items: &[LockedItem], item: &LockedItem| {
+        items
+            .iter()
+            .find(|other| other.path == item.path && other.direction == item.direction)
+            .cloned()
+    };
+
+    for locked_item in locked
+        .iter()
+        .filter(|item| directions.contains(&item.direction.as_str()))
+    {
+        match find(built, locked_item) {
+            None => changes.push(Change::Removed(locked_item.clone())),
+            Some(built_item) if built_item != *locked_item => changes.push(Change::Changed {
+                locked: locked_item.clone(),
+                built: built_item,
+            }),
+            Some(_) => {}
+        }
+    }
+
+    for built_item in built {
+        if find(locked, built_item).is_none() {
+            changes.push(Change::Added(built_item.clone()));
+        }
+    }
+
+    changes
+}
+
+/// Checks the built items against the lock file.
+///
+/// The items are of one direction, the exports of one of the apps, so only the locked items of the
+/// given directions are checked. A difference fails the build with a diff of the items, unless the
+/// lock file is updated. The lock file is only written or created when it's updated, and breaking
+/// changes are only written when they're allowed as well.
+pub(crate) fn check_lock_file(
+    path: &Path,
+    items: &[ManifestItem],
+    directions: &[&str],
+    update: bool,
+    allow_breaking: bool,
+) -> Result<(), anyhow::Error> {
+    println!("cargo:rerun-if-changed={}", path.display());
+
+    // The lock is released when the update is done and the file is dropped
+    let _update_lock = if update {
+        Some(lock_for_update(path)?)
+    } else {
+        None
+    };
+
+    let mut lock_file = if path.exists() {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Could not read the lock file {}", path.display()))?;
+        let lock_file: LockFile = serde_json::from_str(&text)
+            .with_context(|| format!("Could not parse the lock file {}", path.display()))?;
+
+        anyhow::ensure!(
+            lock_file.version == LOCK_FILE_VERSION,
+            "The lock file {} has version {}, but only version {LOCK_FILE_VERSION} is supported",
+            path.display(),
+            lock_file.version
+        );
+
+        lock_file
+    } else {
+        anyhow::ensure!(
+            update,
+            "The lock file {} doesn't exist. \
+            Set {UPDATE_ENV}=1 or use `BindingsBuilder::update_lock_file` to create it.",
+            path.display()
+        );
+
+        LockFile {
+            version: LOCK_FILE_VERSION,
+            items: Vec::new(),
+        }
+    };
+
+    let built = items
+        .iter()
+        .map(LockedItem::new)
+        .collect::<Result<Vec<_>, _>>()?;
+    let changes = compare(&lock_file.items, &built, directions);
+
+    if changes.is_empty() {
+        // Writing the lock file would run the build script again
+        return Ok(());
+    }
+
+    let breaking_changes = changes
+        .iter()
+        .filter(|change| change.is_breaking())
+        .collect::<Vec<_>>();
+    for change in breaking_changes.iter() {
+        println!("cargo:warning=Breaking change of the interface: {change}");
+    }
+    let diff = changes
+        .iter()
+        .map(Change::diff)
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let allow_breaking_hint = if breaking_changes.is_empty() {
+        String::new()
+    } else {
+        format!(
+            ", and allow the breaking changes with {ALLOW_BREAKING_ENV}=1 or `BindingsBuilder::allow_breaking_changes`"
+        )
+    };
+    anyhow::ensure!(
+        update,
+        "The interface differs from the lock file {}:\n{diff}\n\
+        Set {UPDATE_ENV}=1 or use `BindingsBuilder::update_lock_file` to update it{allow_breaking_hint}.",
+        path.display()
+    );
+    anyhow::ensure!(
+        breaking_changes.is_empty() || allow_breaking,
+        "The interface has {} breaking changes compared to the lock file {}:\n{diff}\n\
+        Set {ALLOW_BREAKING_ENV}=1 or use `BindingsBuilder::allow_breaking_changes` to update the lock file with them.",
+        breaking_changes.len(),
+        path.display()
+    );
+
+    lock_file
+        .items
+        .retain(|item| !directions.contains(&item.direction.as_str()));
+    lock_file.items.extend(built);
+    lock_file.items.sort_by(|a, b| {
+        (a.direction.as_str(), a.path.as_str()).cmp(&(b.direction.as_str(), b.path.as_str()))
+    });
+
+    let mut text = serde_json::to_string_pretty(&lock_file)?;
+    text.push('\n');
+    write_lock_file(path, &text)
+        .with_context(|| format!("Could not write the lock file {}", path.display()))
+}
+
+/// Waits until no other build updates the lock file, and locks it for this build.
+///
+/// The lock can't be on the lock file itself, because that is replaced when it's written. It's on a
+/// file in the temporary directory that is named after the full path of the lock file instead, so
+/// the repository isn't cluttered by it.
+fn lock_for_update(path: &Path) -> Result<File, anyhow::Error> {
+    let update_lock_path = update_lock_path(path)?;
+    let update_lock = File::options()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&update_lock_path)
+        .with_context(|| {
+            format!(
+                "Could not open {} to lock the lock file",
+                update_lock_path.display()
+            )
+        })?;
+    update_lock.lock().with_context(|| {
+        format!(
+            "Could not lock {} to update the lock file",
+            update_lock_path.display()
+        )
+    })?;
+
+    Ok(update_lock)
+}
+
+/// The path of the file that is locked while the lock file is updated
+fn update_lock_path(path: &Path) -> Result<PathBuf, anyhow::Error> {
+    // Both builds have to end up at the same file, no matter how they refer to the lock file
+    let directory = match path.parent() {
+        Some(directory) if !directory.as_os_str().is_empty() => directory,
+        _ => Path::new("."),
+    };
+    let full_path = directory
+        .canonicalize()
+        .with_context(|| format!("Could not find the directory of {}", path.display()))?
+        .join(
+            path.file_name()
+                .context("The lock file path has no file name")?,
+        );
+
+    let hash = crc::Crc::<u32>::new(&crc::CRC_32_ISO_HDLC)
+        .checksum(full_path.as_os_str().as_encoded_bytes());
+    Ok(std::env::temp_dir().join(format!("trustzone-lock-{hash:08x}")))
+}
+
+/// Writes the lock file by writing a temporary file next to it and renaming that to the lock file
+fn write_lock_file(path: &Path, text: &str) -> Result<(), std::io::Error> {
+    let mut temporary_file_name = path.file_name().unwrap_or_default().to_os_string();
+    temporary_file_name.push(".tmp");
+    let temporary_path = path.with_file_name(temporary_file_name);
+
+    fs::write(&temporary_path, text)?;
+    fs::rename(&temporary_path, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn locked_item(item: &ManifestItem) -> LockedItem {
+        LockedItem::new(item).unwrap()
+    }
+
+    #[test]
+    fn test_compare() {
+        let read: syn::Signature = syn::parse_quote!(fn read<'a>(key: &'a [u8]) -> Option<&'a u32>);
+        let write: syn::Signature =
+            syn::parse_quote!(unsafe extern "C" fn write(key: u32, value: u32));
+        let renamed_write: syn::Signature =
+            syn::parse_quote!(unsafe extern "C" fn write(k: u32, v: u32));
+        let changed_write: syn::Signature =
+            syn::parse_quote!(unsafe extern "C" fn write(key: u32, value: u64));
+        let erase: syn::Signature = syn::parse_quote!(fn erase());
+        let ty: syn::Type = syn::parse_quote!([AtomicU32; 4]);
+
+        let storage = [String::from("storage")];
+        let locked = [
+            locked_item(&ManifestItem::function(&storage, &read, false, 1, 2)),
+            locked_item(&ManifestItem::function(&storage, &write, false, 3, 4)),
+            locked_item(&ManifestItem::function(&[], &erase, false, 5, 6)),
+            locked_item(&ManifestItem::shared_static(
+                &[],
+                "COUNTERS",
+                &ty,
+                true,
+                7,
+                8,
+            )),
+            locked_item(&ManifestItem::function(&[], &erase, true, 9, 10)),
+        ];
+        assert_eq!(locked[0].signature, "fn(&[u8])->Option<&u32>");
+        assert_eq!(locked[1].signature, "extern \"C\" fn(u32,u32)");
+        assert_eq!(locked[3].signature, "static mut [AtomicU32;4]");
+
+        // Renaming the arguments doesn't change the interface
+        let built = [
+            locked_item(&ManifestItem::function(&storage, &read, false, 1, 2)),
+            locked_item(&ManifestItem::function(
+                &storage,
+                &renamed_write,
+                false,
+                3,
+                4,
+            )),
+            locked_item(&ManifestItem::function(&[], &erase, false, 5, 6)),
+            locked_item(&ManifestItem::shared_static(
+                &[],
+                "COUNTERS",
+                &ty,
+                true,
+                7,
+                8,
+            )),
+        ];
+        let directions = ["nonsecure_callable", "nonsecure_shared"];
+        assert_eq!(compare(&locked, &built, &directions), []);
+
+        // The secure callable function of the lock file isn't checked by this build
+        let built = [
+            locked_item(&ManifestItem::function(&storage, &read, false, 11, 2)),
+            locked_item(&ManifestItem::function(
+                &storage,
+                &changed_write,
+                false,
+                3,
+                12,
+            )),
+            locked_item(&ManifestItem::shared_static(
+                &[],
+                "COUNTERS",
+                &ty,
+                true,
+                7,
+                8,
+            )),
+            locked_item(&ManifestItem::shared_static(
+                &[],
+                "EVENTS",
+                &ty,
+                false,
+                13,
+                14,
+            )),
+        ];
+        let changes = compare(&locked, &built, &directions);
+        assert_eq!(
+            changes.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            [
+                "the veneer id of `storage::read` changed from 0x00000001 to 0x0000000b",
+                "the signature of `storage::write` changed from `extern \"C\" fn(u32,u32)` to `extern \"C\" fn(u32,u64)`",
+                "`erase` was removed",
+                "`EVENTS` was added",
+            ]
+        );
+        assert_eq!(
+            changes.iter().filter(|change| change.is_breaking()).count(),
+            3
+        );
+    }
+
+    #[test]
+    fn test_check_lock_file() {
+        let path = std::env::temp_dir().join(format!("trustzone-{}.lock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let read: syn::Signature = syn::parse_quote!(fn read(key: u32) -> u32);
+        let changed_read: syn::Signature = syn::parse_quote!(fn read(key: u64) -> u32);
+        let erase: syn::Signature = syn::parse_quote!(fn erase());
+        let items = [ManifestItem::function(&[], &read, false, 1, 2)];
+        let added_items = [
+            ManifestItem::function(&[], &read, false, 1, 2),
+            ManifestItem::function(&[], &erase, false, 3, 4),
+        ];
+        let changed_items = [ManifestItem::function(&[], &changed_read, false, 1, 5)];
+        let directions = ["nonsecure_callable"];
+
+        // The lock file is only created when it's updated
+        let error = check_lock_file(&path, &items, &directions, false, false).unwrap_err();
+        assert!(error.to_string().contains("doesn't exist"));
+        assert!(!path.exists());
+        check_lock_file(&path, &items, &directions, true, false).unwrap();
+        let locked_text = std::fs::read_to_string(&path).unwrap();
+        check_lock_file(&path, &items, &directions, false, false).unwrap();
+
+        // An added item fails the build with a diff until the lock file is updated
+        let error = check_lock_file(&path, &added_items, &directions, false, false).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "The interface differs from the lock file {}:\n\
+                + nonsecure_callable `erase` 0x00000003 `fn()`\n\
+                Set TRUSTZONE_UPDATE_LOCK=1 or use `BindingsBuilder::update_lock_file` to update it.",
+                path.display()
+            )
+        );
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), locked_text);
+
+        // A breaking change is only written when it's allowed
+        let error = check_lock_file(&path, &changed_items, &directions, false, true).unwrap_err();
+        assert!(error.to_string().contains(
+            "- nonsecure_callable `read` 0x00000001 `fn(u32)->u32`\n\
+            + nonsecure_callable `read` 0x00000001 `fn(u64)->u32`"
+        ));
+        check_lock_file(&path, &changed_items, &directions, true, false).unwrap_err();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), locked_text);
+        check_lock_file(&path, &changed_items, &directions, true, true).unwrap();
+        check_lock_file(&path, &changed_items, &directions, false, false).unwrap();
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_concurrent_updates() {
+        let path =
+            std::env::temp_dir().join(format!("trustzone-concurrent-{}.lock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        // Both apps update their own items at the same time, and neither update is lost
+        std::thread::scope(|scope| {
+            for (secure_callable, name) in [(false, "read"), (true, "notify")] {
+                let path = &path;
+                scope.spawn(move || {
+                    let directions = if secure_callable {
+                        ["secure_callable"]
+                    } else {
+                        ["nonsecure_callable"]
+                    };
+
+                    for index in 0..20u32 {
+                        let signature: syn::Signature =
+                            syn::parse_str(&format!("fn {name}()")).unwrap();
+                        let items = [ManifestItem::function(
+                            &[],
+                            &signature,
+                            secure_callable,
+                            index,
+                            0,
+                        )];
+                        check_lock_file(path, &items, &directions, true, true).unwrap();
+                    }
+                });
+            }
+        });
+
+        let lock_file: LockFile =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(
+            lock_file
+                .items
+                .iter()
+                .map(|item| (item.path.as_str(), item.id))
+                .collect::<Vec<_>>(),
+            [("read", 19), ("notify", 19)]
+        );
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
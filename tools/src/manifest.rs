@@ -0,0 +1,335 @@
+//! The machine-readable description of the interface between the secure and the nonsecure app.
+//!
+//! The bindings are generated from the manifest items, whether they were found in the source code
+//! or read from a manifest file. This means that one side can be built against the published
+//! manifest of the other side, without having its source code.
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// The version of the manifest format. Manifests with another version are rejected.
+const MANIFEST_VERSION: u32 = 1;
+
+/// A manifest file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Manifest {
+    pub(crate) version: u32,
+    pub(crate) items: Vec<ManifestItem>,
+}
+
+impl Manifest {
+    pub(crate) fn new(items: Vec<ManifestItem>) -> Self {
+        Self {
+            version: MANIFEST_VERSION,
+            items,
+        }
+    }
+
+    pub(crate) fn read(path: &Path) -> Result<Self, anyhow::Error> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Could not read the manifest {}", path.display()))?;
+        let manifest: Manifest = serde_json::from_str(&text)
+            .with_context(|| format!("Could not parse the manifest {}", path.display()))?;
+
+        anyhow::ensure!(
+            manifest.version == MANIFEST_VERSION,
+            "The manifest {} has version {}, but only version {MANIFEST_VERSION} is supported",
+            path.display(),
+            manifest.version
+        );
+
+        Ok(manifest)
+    }
+
+    pub(crate) fn write(&self, path: &Path) -> Result<(), anyhow::Error> {
+        let mut text = serde_json::to_string_pretty(self)?;
+        text.push('\n');
+
+        std::fs::write(path, text)
+            .with_context(|| format!("Could not write the manifest {}", path.display()))
+    }
+}
+
+/// An item that is exported to the other side
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct ManifestItem {
+    /// The path of the item relative to the crate root, like `storage::read`
+    pub(crate) path: String,
+    /// The id of the veneer
+    pub(crate) id: u32,
+    /// The hash of the signature or the type of the item
+    pub(crate) signature_hash: u32,
+    /// The attributes of the item that are carried over to the bindings, like doc comments
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) attributes: Vec<String>,
+    /// The file and line the item is defined at
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) defined_at: Option<String>,
+    #[serde(flatten)]
+    pub(crate) kind: ManifestItemKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "direction", rename_all = "snake_case")]
+pub(crate) enum ManifestItemKind {
+    /// A function of the nonsecure app that the secure app calls
+    SecureCallable(ManifestFunction),
+    /// A function of the secure app that the nonsecure app calls
+    NonsecureCallable(ManifestFunction),
+    /// A static of the secure app that the nonsecure app can access
+    NonsecureShared {
+        #[serde(rename = "type")]
+        ty: String,
+        mutable: bool,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct ManifestFunction {
+    /// The signature of the function with the exported name
+    pub(crate) signature: String,
+    /// The types of the arguments
+    pub(crate) arguments: Vec<String>,
+    /// The return type, which is `()` when the function doesn't return anything
+    pub(crate) return_type: String,
+}
+
+impl ManifestItem {
+    /// Describes an exported function. The identifier of the signature is the exported name.
+    pub(crate) fn function(
+        module_path: &[String],
+        signature: &syn::Signature,
+        secure_callable: bool,
+        id: u32,
+        signature_hash: u32,
+    ) -> Self {
+        let function = ManifestFunction {
+            signature: unparse_signature(signature),
+            arguments: signature
+                .inputs
+                .iter()
+                .map(|input| match input {
+                    syn::FnArg::Receiver(receiver) => unparse_type(&receiver.ty),
+                    syn::FnArg::Typed(input) => unparse_type(&input.ty),
+                })
+                .collect(),
+            return_type: match &signature.output {
+                syn::ReturnType::Default => String::from("()"),
+                syn::ReturnType::Type(_, ty) => unparse_type(ty),
+            },
+        };
+
+        Self {
+            path: item_path(module_path, &signature.ident.to_string()),
+            id,
+            signature_hash,
+            attributes: Vec::new(),
+            defined_at: None,
+            kind: if secure_callable {
+                ManifestItemKind::SecureCallable(function)
+            } else {
+                ManifestItemKind::NonsecureCallable(function)
+            },
+        }
+    }
+
+    /// Describes a shared static with the given exported name
+    pub(crate) fn shared_static(
+        module_path: &[String],
+        name: &str,
+        ty: &syn::Type,
+        mutable: bool,
+        id: u32,
+        signature_hash: u32,
+    ) -> Self {
+        Self {
+            path: item_path(module_path, name),
+            id,
+            signature_hash,
+            attributes: Vec::new(),
+            defined_at: None,
+            kind: ManifestItemKind::NonsecureShared {
+                ty: unparse_type(ty),
+                mutable,
+            },
+        }
+    }
+
+    /// Records the attributes that are carried over to the bindings and where the item is defined
+    pub(crate) fn with_origin(self, attributes: &[syn::Attribute], defined_at: String) -> Self {
+        let mut item = self.with_attributes(attributes);
+        item.defined_at = Some(defined_at);
+        item
+    }
+
+    /// Records the attributes that are carried over to the bindings
+    pub(crate) fn with_attributes(mut self, attributes: &[syn::Attribute]) -> Self {
+        self.attributes = attributes.iter().map(unparse_attribute).collect();
+        self
+    }
+
+    /// Parses the attributes that are carried over to the bindings
+    pub(crate) fn parse_attributes(&self) -> syn::Result<Vec<syn::Attribute>> {
+        let mut attributes = Vec::new();
+        for attribute in self.attributes.iter() {
+            attributes.extend(syn::parse::Parser::parse_str(
+                syn::Attribute::parse_outer,
+                attribute,
+            )?);
+        }
+
+        Ok(attributes)
+    }
+
+    /// The direction of the item, like it's written in the manifest
+    pub(crate) fn direction(&self) -> &'static str {
+        match self.kind {
+            ManifestItemKind::SecureCallable(_) => "secure_callable",
+            ManifestItemKind::NonsecureCallable(_) => "nonsecure_callable",
+            ManifestItemKind::NonsecureShared { .. } => "nonsecure_shared",
+        }
+    }
+
+    /// The path of the module the item is in and the name of the item
+    pub(crate) fn module_path_and_name(&self) -> (Vec<String>, String) {
+        let mut module_path = self.path.split("::").map(String::from).collect::<Vec<_>>();
+        let name = module_path.pop().unwrap();
+
+        (module_path, name)
+    }
+}
+
+impl ManifestFunction {
+    pub(crate) fn parse_signature(&self) -> syn::Result<syn::Signature> {
+        syn::parse_str(&self.signature)
+    }
+}
+
+pub(crate) fn item_path(module_path: &[String], name: &str) -> String {
+    module_path
+        .iter()
+        .map(String::as_str)
+        .chain([name])
+        .collect::<Vec<_>>()
+        .join("::")
+}
+
+fn unparse_signature(signature: &syn::Signature) -> String {
+    let file: syn::File = syn::parse_quote!(#signature {});
+    let text = prettyplease::unparse(&file);
+
+    text.trim_end()
+        .trim_end_matches("{}")
+        .trim_end()
+        .to_string()
+}
+
+fn unparse_attribute(attribute: &syn::Attribute) -> String {
+    let file: syn::File = syn::parse_quote!(#attribute struct T;);
+    let text = prettyplease::unparse(&file);
+
+    text.trim().trim_end_matches("struct T;").trim().to_string()
+}
+
+fn unparse_type(ty: &syn::Type) -> String {
+    let file: syn::File = syn::parse_quote!(type T = #ty;);
+    let text = prettyplease::unparse(&file);
+
+    text.trim()
+        .trim_start_matches("type T = ")
+        .trim_end_matches(';')
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let signature: syn::Signature = syn::parse_quote!(unsafe extern "C" fn read(key: u32, buffer: &mut [u8; 16]) -> Option<NonZeroU32>);
+        let ty: syn::Type = syn::parse_quote!([AtomicU32; 4]);
+
+        let manifest = Manifest::new(vec![
+            ManifestItem::function(&["storage".into()], &signature, false, 1, 2),
+            ManifestItem::shared_static(&[], "COUNTERS", &ty, false, 3, 4),
+        ]);
+
+        let text = serde_json::to_string_pretty(&manifest).unwrap();
+        pretty_assertions::assert_eq!(
+            text,
+            r#"{
+  "version": 1,
+  "items": [
+    {
+      "path": "storage::read",
+      "id": 1,
+      "signature_hash": 2,
+      "direction": "nonsecure_callable",
+      "signature": "unsafe extern \"C\" fn read(key: u32, buffer: &mut [u8; 16]) -> Option<NonZeroU32>",
+      "arguments": [
+        "u32",
+        "&mut [u8; 16]"
+      ],
+      "return_type": "Option<NonZeroU32>"
+    },
+    {
+      "path": "COUNTERS",
+      "id": 3,
+      "signature_hash": 4,
+      "direction": "nonsecure_shared",
+      "type": "[AtomicU32; 4]",
+      "mutable": false
+    }
+  ]
+}"#
+        );
+
+        let read_manifest: Manifest = serde_json::from_str(&text).unwrap();
+        assert_eq!(read_manifest, manifest);
+
+        let ManifestItemKind::NonsecureCallable(function) = &read_manifest.items[0].kind else {
+            panic!()
+        };
+        let read_signature = function.parse_signature().unwrap();
+        assert_eq!(
+            quote::quote!(#read_signature).to_string(),
+            quote::quote!(#signature).to_string()
+        );
+        assert_eq!(
+            read_manifest.items[0].module_path_and_name(),
+            (vec![String::from("storage")], String::from("read"))
+        );
+    }
+
+    #[test]
+    fn test_attributes_round_trip() {
+        let function: syn::ItemFn = syn::parse_quote! {
+            /// Reads a value.
+            ///
+            /// Returns 0 when the key doesn't exist.
+            #[deprecated(note = "use `read_v2`")]
+            fn read(key: u32) -> u32 {}
+        };
+
+        let item = ManifestItem::function(&[], &function.sig, false, 1, 2)
+            .with_origin(&function.attrs, String::from("src/lib.rs:5:4"));
+        assert_eq!(
+            item.attributes,
+            [
+                "/// Reads a value.",
+                "///",
+                "/// Returns 0 when the key doesn't exist.",
+                "#[deprecated(note = \"use `read_v2`\")]",
+            ]
+        );
+
+        // Doc comments are parsed as `#[doc = "..."]` attributes
+        let attributes = item.parse_attributes().unwrap();
+        assert_eq!(
+            attributes.iter().map(unparse_attribute).collect::<Vec<_>>(),
+            item.attributes
+        );
+    }
+}
@@ -0,0 +1,184 @@
+//! The description of the exported items that the macros put in the built image.
+//!
+//! Scanning the source code misses items that are generated by other macros, items in dependencies
+//! and items behind cfg logic that can't be evaluated in a build script. The macros therefore also
+//! put a record of every exported item in the `.trustzone_meta` section, which isn't loaded into
+//! the device. The bindings generator can read the records from the built ELF file or static
+//! library, so the bindings match exactly what was compiled.
+//!
+//! A record is created by the `veneer_metadata` function of the runtime crates. It is its length,
+//! the id and the signature hash, followed by the module path, the file and line of the item and
+//! the manifest item in JSON, each with its length in front.
+
+use anyhow::Context;
+use object::{Object, ObjectSection};
+use proc_macro2::TokenStream;
+use std::path::Path;
+
+use crate::manifest::ManifestItem;
+
+/// The section the records are in
+const METADATA_SECTION: &str = ".trustzone_meta";
+
+/// Creates the static with the metadata record of an exported item.
+///
+/// The path of the manifest item is only the exported name, the module path is added by the
+/// compiler. The id of the manifest item isn't used, because it is only known at compile time.
+pub(crate) fn metadata_record(
+    manifest_item: &ManifestItem,
+    id_expression: &TokenStream,
+    runtime: &TokenStream,
+) -> TokenStream {
+    let json = serde_json::to_string(manifest_item).unwrap();
+    let signature_hash = manifest_item.signature_hash;
+
+    quote::quote! {
+        const _: () = {
+            const STRINGS: &[&str] = &[module_path!(), concat!(file!(), ":", line!()), #json];
+
+            #[used]
+            #[link_section = ".trustzone_meta"]
+            static METADATA: [u8; #runtime::veneer_metadata_len(STRINGS)] =
+                #runtime::veneer_metadata(#id_expression, #signature_hash, STRINGS);
+        };
+    }
+}
+
+/// Reads the exported items from the records in a built ELF file or in the objects of a static
+/// library
+pub(crate) fn read_metadata(path: &Path) -> Result<Vec<ManifestItem>, anyhow::Error> {
+    let data = std::fs::read(path).with_context(|| format!("Could not read {}", path.display()))?;
+
+    let mut items = Vec::new();
+
+    if data.starts_with(b"!<arch>\n") {
+        let archive = object::read::archive::ArchiveFile::parse(&*data)
+            .with_context(|| format!("Could not parse the static library {}", path.display()))?;
+
+        for member in archive.members() {
+            let member = member?;
+            let member_data = member.data(&*data)?;
+
+            // Static libraries can have members that aren't objects, like the rlib metadata
+            let Ok(file) = object::File::parse(member_data) else {
+                continue;
+            };
+            read_object_metadata(&file, &mut items)?;
+        }
+    } else {
+        let file = object::File::parse(&*data)
+            .with_context(|| format!("Could not parse {}", path.display()))?;
+        read_object_metadata(&file, &mut items)?;
+    }
+
+    Ok(items)
+}
+
+fn read_object_metadata(
+    file: &object::File,
+    items: &mut Vec<ManifestItem>,
+) -> Result<(), anyhow::Error> {
+    for section in file
+        .sections()
+        .filter(|section| section.name() == Ok(METADATA_SECTION))
+    {
+        for item in parse_records(section.data()?)? {
+            // The same object may be in the image twice, for example through a static library
+            if !items.contains(&item) {
+                items.push(item);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Parses the records in the data of a metadata section
+fn parse_records(mut data: &[u8]) -> Result<Vec<ManifestItem>, anyhow::Error> {
+    let word = |data: &[u8], offset: usize| -> Result<u32, anyhow::Error> {
+        let bytes = data
+            .get(offset..offset + 4)
+            .context("A metadata record is cut off")?;
+        Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+    };
+
+    let mut items = Vec::new();
+
+    // The linker may pad the section with zeros
+    while data.len() >= 4 && word(data, 0)? != 0 {
+        let length = word(data, 0)? as usize;
+        let record = data.get(..length).context("A metadata record is cut off")?;
+        data = &data[length..];
+
+        let id = word(record, 4)?;
+        let signature_hash = word(record, 8)?;
+
+        let mut strings = Vec::new();
+        let mut offset = 12;
+        for _ in 0..3 {
+            let length = word(record, offset)? as usize;
+            let string = record
+                .get(offset + 4..offset + 4 + length)
+                .context("A metadata record is cut off")?;
+            strings.push(std::str::from_utf8(string)?);
+            offset += 4 + length;
+        }
+        let [module_path, defined_at, json] = strings[..] else {
+            unreachable!()
+        };
+
+        let mut item: ManifestItem = serde_json::from_str(json)
+            .with_context(|| format!("Invalid metadata record in {module_path}"))?;
+
+        // The module path starts with the name of the crate, which isn't part of the item path
+        item.path = module_path
+            .split("::")
+            .skip(1)
+            .chain([item.path.as_str()])
+            .collect::<Vec<_>>()
+            .join("::");
+        item.id = id;
+        item.signature_hash = signature_hash;
+        item.defined_at = Some(defined_at.to_string());
+
+        items.push(item);
+    }
+
+    Ok(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_records() {
+        let signature: syn::Signature = syn::parse_quote!(fn read(key: u32) -> u32);
+        let item = ManifestItem::function(&[], &signature, false, 0, 1234);
+        let json = serde_json::to_string(&item).unwrap();
+
+        let strings = ["app::storage", "src/storage.rs:12", json.as_str()];
+        const LEN: usize = 212;
+        assert_eq!(trustzone_m_nonsecure_rt::veneer_metadata_len(&strings), LEN);
+        assert_eq!(trustzone_m_secure_rt::veneer_metadata_len(&strings), LEN);
+
+        // The records of both runtimes are read the same way
+        let mut section = Vec::new();
+        section.extend(trustzone_m_nonsecure_rt::veneer_metadata::<LEN>(
+            5678, 1234, &strings,
+        ));
+        section.extend(trustzone_m_secure_rt::veneer_metadata::<LEN>(
+            5678, 1234, &strings,
+        ));
+        section.extend([0; 8]);
+
+        let items = parse_records(&section).unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0], items[1]);
+        assert_eq!(items[0].path, "storage::read");
+        assert_eq!(items[0].id, 5678);
+        assert_eq!(items[0].signature_hash, 1234);
+        assert_eq!(items[0].defined_at.as_deref(), Some("src/storage.rs:12"));
+        assert_eq!(items[0].kind, item.kind);
+    }
+}
@@ -1,7 +1,19 @@
 use proc_macro2::TokenStream;
-use syn::parse2;
+use syn::{parse2, spanned::Spanned};
+
+use crate::{
+    abi,
+    export_attributes::{self, ExportAttributes},
+    manifest::ManifestItem,
+    metadata, simulation,
+};
+
+pub fn nonsecure_callable(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let attributes = match ExportAttributes::parse(attr) {
+        Ok(attributes) => attributes,
+        Err(e) => return e.into_compile_error(),
+    };
 
-pub fn nonsecure_callable(_attr: TokenStream, item: TokenStream) -> TokenStream {
     let function = parse2::<syn::ItemFn>(item);
 
     let function = match function {
@@ -10,59 +22,289 @@ pub fn nonsecure_callable(_attr: TokenStream, item: TokenStream) -> TokenStream
             return e.into_compile_error();
         }
     };
-    let function = &function;
-
-    if !function.sig.abi.as_ref().map_or(false, |abi| {
-        abi.name
-            .as_ref()
-            .map_or(false, |abi_name| abi_name.value().to_uppercase() == "C")
-    }) {
-        return quote::quote! {
-            compile_error!("Function must be 'extern \"C\"'");
-        };
-    }
+    let mut function = function;
+
+    let runtime = quote::quote!(::trustzone_m_secure_rt);
+    let signature_assertions = match abi::check_signature(&function.sig, &runtime) {
+        Ok(assertions) => assertions,
+        Err(e) => {
+            // Keep the function around so the error doesn't cause errors everywhere it's used
+            let mut output = e.into_compile_error();
+            output.extend(quote::quote!(#function));
+            return output;
+        }
+    };
 
     let function_name = function.sig.ident.to_string();
-    let function_veneer_name = format!("{}_veneer", function_name.to_uppercase());
-    let function_name_hash = crate::hash_vector_name(&function_name);
+    // The id is part of the name, so functions with the same name in different modules don't clash
+    let function_veneer_name = format!(
+        "{}_{{id}}_veneer",
+        attributes.export_name(&function.sig.ident).to_uppercase()
+    );
+    let function_id = attributes.id_expression(&function.sig.ident, runtime.clone());
+    let function_signature_hash = abi::signature_hash(&function.sig);
+
+    // The bindings are named after the exported name
+    let mut exported_signature = function.sig.clone();
+    exported_signature.ident = syn::Ident::new(
+        &attributes.export_name(&function.sig.ident),
+        function.sig.ident.span(),
+    );
+    let metadata = metadata::metadata_record(
+        &ManifestItem::function(&[], &exported_signature, false, 0, function_signature_hash)
+            .with_attributes(&function.attrs),
+        &function_id,
+        &runtime,
+    );
+
+    // What a call with an invalid argument returns instead of running the body
+    let on_reject = attributes
+        .on_reject
+        .clone()
+        .or_else(|| returns_option(&function.sig).then(|| syn::parse_quote!(None)));
+    let lowered_signature = abi::LoweredSignature::new(&function.sig, runtime.clone());
+
+    // Functions that don't use the C ABI get a shim that does, which is what the veneer calls.
+    // Functions with arguments that can have invalid values get one as well, which checks them.
+    let (entry_ident, entry_functions) = if abi::is_extern_c(&function.sig)
+        && !lowered_signature.has_checked_inputs()
+    {
+        add_pointer_argument_checks(&function_name, &mut function, on_reject.as_ref());
 
+        (
+            function.sig.ident.clone(),
+            quote::quote! {
+                #[cfg_attr(target_arch = "arm", cmse_nonsecure_entry)]
+                #function
+            },
+        )
+    } else {
+        // The shim returns the lowered value
+        let on_reject = on_reject.map(|on_reject| lowered_signature.lower_output(on_reject));
+
+        let shim_ident = quote::format_ident!("__trustzone_{}", function_name);
+        let mut shim = abi::rust_abi_shim(
+            &function,
+            &shim_ident,
+            vec![syn::parse_quote!(#[cfg_attr(target_arch = "arm", cmse_nonsecure_entry)])],
+            runtime.clone(),
+            |ident| {
+                rejection(
+                    on_reject.as_ref(),
+                    format!("Nonsecure caller of '{function_name}' passed an invalid '{ident}'"),
+                )
+            },
+        );
+        add_pointer_argument_checks(&function_name, &mut shim, on_reject.as_ref());
+
+        (
+            shim_ident,
+            quote::quote! {
+                #function
+
+                #shim
+            },
+        )
+    };
+
+    let simulated_veneer = simulation::simulated_veneer(
+        &attributes.export_name(&function.sig.ident),
+        function_signature_hash,
+        &entry_ident,
+        &runtime,
+    );
+
+    let section_key = export_attributes::section_key_expression(&function_id);
     let global = format!(".global {function_veneer_name}");
     let label = format!("{function_veneer_name}:");
-    let branch = format!("B.w {function_name}");
-    let hash = format!(".4byte {function_name_hash}");
+    let signature_hash = format!(".4byte {function_signature_hash}");
 
     quote::quote! {
+        // The veneers only exist on the device, the simulation on the host calls the entry directly
+        #[cfg(target_arch = "arm")]
         core::arch::global_asm!(
-            ".section .nsc_veneers, \"ax\"",
+            ".section .nsc_veneers.{section_key}, \"ax\"",
+            ".balign 4",
             #global,
             ".thumb_func",
             #label,
                 "SG",
-                #branch,
-                #hash,
+                "B.w {entry}",
+                ".4byte {id}",
+                #signature_hash,
+            // The size of this section is the part of the checksum of the table the veneer adds
+            ".pushsection .nsc_veneers_checksum, \"\", %nobits",
+            ".space {checksum}",
+            ".popsection",
+            id = const #function_id,
+            checksum = const #runtime::veneer_checksum(#function_id, #function_signature_hash),
+            section_key = const #section_key,
+            entry = sym #entry_ident,
         );
 
-        #[cmse_nonsecure_entry]
-        #[no_mangle]
-        #function
+        #entry_functions
+
+        #simulated_veneer
+
+        #signature_assertions
+
+        #metadata
+    }
+}
+
+/// Returns `on_reject` from the function, or panics with the message if there's no value to return
+fn rejection(on_reject: Option<&syn::Expr>, panic_message: String) -> TokenStream {
+    match on_reject {
+        Some(on_reject) => quote::quote!(return #on_reject;),
+        None => quote::quote!(panic!(#panic_message);),
+    }
+}
+
+/// The nonsecure world must not be able to make us read or write secure memory
+/// on its behalf, so every pointer it hands us is checked before the body runs
+fn add_pointer_argument_checks(
+    function_name: &str,
+    function: &mut syn::ItemFn,
+    on_reject: Option<&syn::Expr>,
+) {
+    let pointer_checks = pointer_argument_checks(function_name, &function.sig, on_reject);
+    function.block.stmts.splice(0..0, pointer_checks);
+}
+
+/// Returns true if the function returns an `Option`, which is `None` when a call is rejected
+fn returns_option(signature: &syn::Signature) -> bool {
+    match &signature.output {
+        syn::ReturnType::Type(_, ty) => match abi::unparenthesize(ty) {
+            syn::Type::Path(type_path) => type_path
+                .path
+                .segments
+                .last()
+                .is_some_and(|segment| segment.ident == "Option"),
+            _ => false,
+        },
+        syn::ReturnType::Default => false,
     }
 }
 
+/// Creates a check for every pointer and reference argument that returns `on_reject` when the
+/// nonsecure caller doesn't have the required access to the whole pointed-to range, or panics if
+/// there's no value to return.
+///
+/// Null raw pointers aren't checked, because they can't be used to access anything and are often
+/// used for optional arguments. The body has to check them like any other raw pointer.
+///
+/// References are also rejected when they aren't aligned, and their type has to implement
+/// `AnyBitPattern`, because the body reads what the nonsecure caller put behind them without
+/// checking it.
+fn pointer_argument_checks(
+    function_name: &str,
+    signature: &syn::Signature,
+    on_reject: Option<&syn::Expr>,
+) -> Vec<syn::Stmt> {
+    signature
+        .inputs
+        .iter()
+        .filter_map(|input| match input {
+            syn::FnArg::Typed(t) => Some(t),
+            _ => None,
+        })
+        .flat_map(|input| {
+            // Arguments that aren't bound to a name can't be dereferenced, so they don't need a check
+            let syn::Pat::Ident(pat_ident) = input.pat.as_ref() else {
+                return Vec::new();
+            };
+            let ident = &pat_ident.ident;
+
+            let (address, size, mutable, null_check) = match abi::unparenthesize(&input.ty) {
+                syn::Type::Ptr(pointer) => {
+                    let elem = &pointer.elem;
+                    (
+                        quote::quote!(#ident.cast::<u8>()),
+                        quote::quote!(core::mem::size_of::<#elem>()),
+                        pointer.mutability.is_some(),
+                        quote::quote!(!#ident.is_null() &&),
+                    )
+                }
+                syn::Type::Reference(reference) => {
+                    let elem = &reference.elem;
+                    (
+                        quote::quote!((#ident as *const #elem).cast::<u8>()),
+                        quote::quote!(core::mem::size_of_val(#ident)),
+                        reference.mutability.is_some(),
+                        TokenStream::new(),
+                    )
+                }
+                _ => return Vec::new(),
+            };
+
+            let check_function = if mutable {
+                quote::quote!(is_nonsecure_read_writable)
+            } else {
+                quote::quote!(is_nonsecure_readable)
+            };
+            let access_rejection = rejection(
+                on_reject,
+                format!(
+                    "Nonsecure caller of '{function_name}' passed a '{ident}' it has no {} access to",
+                    if mutable { "read/write" } else { "read" }
+                ),
+            );
+
+            let mut checks: Vec<syn::Stmt> = vec![syn::parse_quote! {
+                if #null_check !::trustzone_m_secure_rt::cmse::#check_function(#address, #size) {
+                    #access_rejection
+                }
+            }];
+
+            if let syn::Type::Reference(reference) = abi::unparenthesize(&input.ty) {
+                let elem = &reference.elem;
+                checks.insert(
+                    0,
+                    syn::parse_quote_spanned! {elem.span()=>
+                        const _: () = {
+                            const fn assert_any_bit_pattern<
+                                T: ?Sized + ::trustzone_m_secure_rt::AnyBitPattern,
+                            >() {
+                            }
+                            assert_any_bit_pattern::<#elem>();
+                        };
+                    },
+                );
+
+                let misaligned_rejection = rejection(
+                    on_reject,
+                    format!("Nonsecure caller of '{function_name}' passed a misaligned '{ident}'"),
+                );
+                checks.push(syn::parse_quote! {
+                    if #address as usize % core::mem::align_of_val(#ident) != 0 {
+                        #misaligned_rejection
+                    }
+                });
+            }
+
+            checks
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use std::str::FromStr;
 
     use super::*;
 
-    #[test]
-    fn test_name() {
-        let input_text = include_str!("../test-sources/nonsecure_callable_simple_test.txt");
-        let output_text = include_str!("../test-sources/nonsecure_callable_simple_result.txt");
-
+    fn test_expansion(input_text: &str, output_text: &str) {
         let attr: String = input_text.lines().take(1).collect();
         let item: String = input_text.lines().skip(1).collect();
 
-        let attr_stream = TokenStream::from_str(&attr).unwrap();
+        // The first line is the attribute, of which the macro only gets the arguments
+        let attr_stream = match syn::parse::Parser::parse_str(syn::Attribute::parse_outer, &attr)
+            .unwrap()[0]
+            .meta
+        {
+            syn::Meta::List(ref list) => list.tokens.clone(),
+            _ => TokenStream::new(),
+        };
         let item_stream = TokenStream::from_str(&item).unwrap();
 
         let output = nonsecure_callable(attr_stream, item_stream);
@@ -73,4 +315,103 @@ mod tests {
             output_text.replace("\r\n", "\n")
         );
     }
+
+    #[test]
+    fn test_name() {
+        test_expansion(
+            include_str!("../test-sources/nonsecure_callable_simple_test.txt"),
+            include_str!("../test-sources/nonsecure_callable_simple_result.txt"),
+        );
+    }
+
+    #[test]
+    fn test_pointer_arguments() {
+        test_expansion(
+            include_str!("../test-sources/nonsecure_callable_pointer_test.txt"),
+            include_str!("../test-sources/nonsecure_callable_pointer_result.txt"),
+        );
+    }
+
+    #[test]
+    fn test_on_reject() {
+        test_expansion(
+            include_str!("../test-sources/nonsecure_callable_on_reject_test.txt"),
+            include_str!("../test-sources/nonsecure_callable_on_reject_result.txt"),
+        );
+    }
+
+    #[test]
+    fn test_reference_arguments() {
+        // The types behind the references are asserted to implement AnyBitPattern, so the bool
+        // and the enum don't compile, and every reference is checked to be aligned
+        test_expansion(
+            include_str!("../test-sources/nonsecure_callable_references_test.txt"),
+            include_str!("../test-sources/nonsecure_callable_references_result.txt"),
+        );
+    }
+
+    #[test]
+    fn test_array_reference() {
+        test_expansion(
+            include_str!("../test-sources/nonsecure_callable_array_reference_test.txt"),
+            include_str!("../test-sources/nonsecure_callable_array_reference_result.txt"),
+        );
+    }
+
+    #[test]
+    fn test_rust_abi() {
+        test_expansion(
+            include_str!("../test-sources/nonsecure_callable_rust_abi_test.txt"),
+            include_str!("../test-sources/nonsecure_callable_rust_abi_result.txt"),
+        );
+    }
+
+    #[test]
+    fn test_unsafe_rust_abi() {
+        test_expansion(
+            include_str!("../test-sources/nonsecure_callable_unsafe_bool_test.txt"),
+            include_str!("../test-sources/nonsecure_callable_unsafe_bool_result.txt"),
+        );
+    }
+
+    #[test]
+    fn test_checked_c_abi() {
+        test_expansion(
+            include_str!("../test-sources/nonsecure_callable_checked_c_abi_test.txt"),
+            include_str!("../test-sources/nonsecure_callable_checked_c_abi_result.txt"),
+        );
+    }
+
+    #[test]
+    fn test_ffi_types() {
+        // The C types and the paths of the primitives are passed like the primitives themselves
+        test_expansion(
+            include_str!("../test-sources/nonsecure_callable_ffi_types_test.txt"),
+            include_str!("../test-sources/nonsecure_callable_ffi_types_result.txt"),
+        );
+    }
+
+    #[test]
+    fn test_stack_arguments() {
+        test_expansion(
+            include_str!("../test-sources/nonsecure_callable_stack_arguments_test.txt"),
+            include_str!("../test-sources/nonsecure_callable_stack_arguments_result.txt"),
+        );
+    }
+
+    #[test]
+    fn test_unknown_types() {
+        test_expansion(
+            include_str!("../test-sources/nonsecure_callable_unknown_types_test.txt"),
+            include_str!("../test-sources/nonsecure_callable_unknown_types_result.txt"),
+        );
+    }
+
+    #[test]
+    fn test_attributes() {
+        test_expansion(
+            include_str!("../test-sources/nonsecure_callable_attributes_test.txt"),
+            include_str!("../test-sources/nonsecure_callable_attributes_result.txt"),
+        );
+    }
 }
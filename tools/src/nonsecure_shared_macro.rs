@@ -0,0 +1,173 @@
+use proc_macro2::TokenStream;
+use syn::parse2;
+
+use crate::{
+    abi,
+    export_attributes::{self, ExportAttributes},
+    manifest::ManifestItem,
+    metadata, simulation,
+};
+
+pub fn nonsecure_shared(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let attributes =
+        match ExportAttributes::parse(attr).and_then(ExportAttributes::without_on_reject) {
+            Ok(attributes) => attributes,
+            Err(e) => return e.into_compile_error(),
+        };
+
+    let item_static = parse2::<syn::ItemStatic>(item);
+
+    let mut item_static = match item_static {
+        Ok(s) => s,
+        Err(e) => {
+            return e.into_compile_error();
+        }
+    };
+
+    let runtime = quote::quote!(::trustzone_m_secure_rt);
+    let type_assertion = match abi::check_shared_static_type(&item_static.ty, &runtime) {
+        Ok(assertion) => assertion,
+        Err(e) => {
+            // Keep the static around so the error doesn't cause errors everywhere it's used
+            let mut output = e.into_compile_error();
+            output.extend(quote::quote!(#item_static));
+            return output;
+        }
+    };
+
+    let static_ident = &item_static.ident;
+    // The id is part of the name, so statics with the same name in different modules don't clash
+    let static_veneer_name = format!(
+        "{}_{{id}}_veneer",
+        attributes.export_name(static_ident).to_uppercase()
+    );
+    let static_id = attributes.id_expression(static_ident, runtime.clone());
+    let mutable = matches!(item_static.mutability, syn::StaticMutability::Mut(_));
+    let static_type_hash = abi::static_hash(&item_static.ty, mutable);
+    let metadata = metadata::metadata_record(
+        &ManifestItem::shared_static(
+            &[],
+            &attributes.export_name(static_ident),
+            &item_static.ty,
+            mutable,
+            0,
+            static_type_hash,
+        )
+        .with_attributes(&item_static.attrs),
+        &static_id,
+        &runtime,
+    );
+
+    // The static lives in the part of the nonsecure RAM that is reserved for shared statics.
+    // The secure runtime initializes it.
+    item_static
+        .attrs
+        .push(syn::parse_quote!(#[link_section = ".ns_shared"]));
+
+    // The veneer of a static returns its address
+    let entry_ident = quote::format_ident!("__trustzone_{}", static_ident);
+
+    let simulated_veneer = simulation::simulated_veneer(
+        &attributes.export_name(static_ident),
+        static_type_hash,
+        &entry_ident,
+        &runtime,
+    );
+
+    let section_key = export_attributes::section_key_expression(&static_id);
+    let global = format!(".global {static_veneer_name}");
+    let label = format!("{static_veneer_name}:");
+    let type_hash = format!(".4byte {static_type_hash}");
+
+    quote::quote! {
+        // The veneers only exist on the device, the simulation on the host calls the entry directly
+        #[cfg(target_arch = "arm")]
+        core::arch::global_asm!(
+            ".section .nsc_veneers.{section_key}, \"ax\"",
+            ".balign 4",
+            #global,
+            ".thumb_func",
+            #label,
+                "SG",
+                "B.w {entry}",
+                ".4byte {id}",
+                #type_hash,
+            // The size of this section is the part of the checksum of the table the veneer adds
+            ".pushsection .nsc_veneers_checksum, \"\", %nobits",
+            ".space {checksum}",
+            ".popsection",
+            id = const #static_id,
+            checksum = const #runtime::veneer_checksum(#static_id, #static_type_hash),
+            section_key = const #section_key,
+            entry = sym #entry_ident,
+        );
+
+        #item_static
+
+        #[allow(non_snake_case, unused_unsafe)]
+        #[cfg_attr(target_arch = "arm", cmse_nonsecure_entry)]
+        extern "C" fn #entry_ident() -> *const () {
+            unsafe { core::ptr::addr_of!(#static_ident).cast() }
+        }
+
+        #simulated_veneer
+
+        #type_assertion
+
+        #metadata
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn test_expansion(input_text: &str, output_text: &str) {
+        let attr: String = input_text.lines().take(1).collect();
+        let item: String = input_text.lines().skip(1).collect();
+
+        // The first line is the attribute, of which the macro only gets the arguments
+        let attr_stream = match syn::parse::Parser::parse_str(syn::Attribute::parse_outer, &attr)
+            .unwrap()[0]
+            .meta
+        {
+            syn::Meta::List(ref list) => list.tokens.clone(),
+            _ => TokenStream::new(),
+        };
+        let item_stream = TokenStream::from_str(&item).unwrap();
+
+        let output = nonsecure_shared(attr_stream, item_stream);
+
+        let pretty_output = prettyplease::unparse(&parse2(output).unwrap());
+        pretty_assertions::assert_eq!(
+            pretty_output.replace("\r\n", "\n"),
+            output_text.replace("\r\n", "\n")
+        );
+    }
+
+    #[test]
+    fn test_name() {
+        test_expansion(
+            include_str!("../test-sources/nonsecure_shared_simple_test.txt"),
+            include_str!("../test-sources/nonsecure_shared_simple_result.txt"),
+        );
+    }
+
+    #[test]
+    fn test_struct() {
+        test_expansion(
+            include_str!("../test-sources/nonsecure_shared_struct_test.txt"),
+            include_str!("../test-sources/nonsecure_shared_struct_result.txt"),
+        );
+    }
+
+    #[test]
+    fn test_unsupported_types() {
+        test_expansion(
+            include_str!("../test-sources/nonsecure_shared_unsupported_types_test.txt"),
+            include_str!("../test-sources/nonsecure_shared_unsupported_types_result.txt"),
+        );
+    }
+}
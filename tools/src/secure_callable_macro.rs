@@ -1,7 +1,20 @@
 use proc_macro2::TokenStream;
 use syn::parse2;
 
-pub fn secure_callable(_attr: TokenStream, item: TokenStream) -> TokenStream {
+use crate::{
+    abi,
+    export_attributes::{self, ExportAttributes},
+    manifest::ManifestItem,
+    metadata, simulation,
+};
+
+pub fn secure_callable(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let attributes =
+        match ExportAttributes::parse(attr).and_then(ExportAttributes::without_on_reject) {
+            Ok(attributes) => attributes,
+            Err(e) => return e.into_compile_error(),
+        };
+
     let function = parse2::<syn::ItemFn>(item);
 
     let function = match function {
@@ -12,37 +25,112 @@ pub fn secure_callable(_attr: TokenStream, item: TokenStream) -> TokenStream {
     };
     let function = &function;
 
-    if !function.sig.abi.as_ref().map_or(false, |abi| {
-        abi.name
-            .as_ref()
-            .map_or(false, |abi_name| abi_name.value().to_uppercase() == "C")
-    }) {
-        return quote::quote! {
-            compile_error!("Function must be 'extern \"C\"'");
-        };
-    }
+    let runtime = quote::quote!(::trustzone_m_nonsecure_rt);
+    let signature_assertions = match abi::check_signature(&function.sig, &runtime) {
+        Ok(assertions) => assertions,
+        Err(e) => {
+            // Keep the function around so the error doesn't cause errors everywhere it's used
+            let mut output = e.into_compile_error();
+            output.extend(quote::quote!(#function));
+            return output;
+        }
+    };
 
     let function_name = function.sig.ident.to_string();
-    let function_veneer_name = format!("{}_veneer", function_name.to_uppercase());
-    let function_name_hash = crate::hash_vector_name(&function_name);
+    // The id is part of the name, so functions with the same name in different modules don't clash
+    let function_veneer_name = format!(
+        "{}_{{id}}_veneer",
+        attributes.export_name(&function.sig.ident).to_uppercase()
+    );
+    let function_id = attributes.id_expression(&function.sig.ident, runtime.clone());
+    let function_signature_hash = abi::signature_hash(&function.sig);
+
+    // The bindings are named after the exported name
+    let mut exported_signature = function.sig.clone();
+    exported_signature.ident = syn::Ident::new(
+        &attributes.export_name(&function.sig.ident),
+        function.sig.ident.span(),
+    );
+    let metadata = metadata::metadata_record(
+        &ManifestItem::function(&[], &exported_signature, true, 0, function_signature_hash)
+            .with_attributes(&function.attrs),
+        &function_id,
+        &runtime,
+    );
+
+    // Functions that don't use the C ABI get a shim that does, which is what the veneer calls
+    let (entry_ident, entry_functions) = if abi::is_extern_c(&function.sig) {
+        (
+            function.sig.ident.clone(),
+            quote::quote! {
+                #function
+            },
+        )
+    } else {
+        let shim_ident = quote::format_ident!("__trustzone_{}", function_name);
+        let shim = abi::rust_abi_shim(
+            function,
+            &shim_ident,
+            vec![],
+            quote::quote!(::trustzone_m_nonsecure_rt),
+            |ident| {
+                let panic_message =
+                    format!("Secure caller of '{function_name}' passed an invalid '{ident}'");
+                quote::quote!(panic!(#panic_message);)
+            },
+        );
+
+        (
+            shim_ident,
+            quote::quote! {
+                #function
+
+                #shim
+            },
+        )
+    };
+
+    let simulated_veneer = simulation::simulated_veneer(
+        &attributes.export_name(&function.sig.ident),
+        function_signature_hash,
+        &entry_ident,
+        &runtime,
+    );
 
+    let section_key = export_attributes::section_key_expression(&function_id);
     let global = format!(".global {function_veneer_name}");
     let label = format!("{function_veneer_name}:");
-    let branch = format!("B.w {function_name}");
-    let hash = format!(".4byte {function_name_hash}");
+    let signature_hash = format!(".4byte {function_signature_hash}");
 
     quote::quote! {
+        // The veneers only exist on the device, the simulation on the host calls the entry directly
+        #[cfg(target_arch = "arm")]
         core::arch::global_asm!(
-            ".section .ns_veneers, \"ax\"",
+            ".section .ns_veneers.{section_key}, \"ax\"",
+            ".balign 4",
             #global,
             ".thumb_func",
             #label,
-                #branch,
-                #hash,
+                "B.w {entry}",
+                ".4byte {id}",
+                #signature_hash,
+            // The size of this section is the part of the checksum of the table the veneer adds
+            ".pushsection .ns_veneers_checksum, \"\", %nobits",
+            ".space {checksum}",
+            ".popsection",
+            id = const #function_id,
+            checksum = const #runtime::veneer_checksum(#function_id, #function_signature_hash),
+            section_key = const #section_key,
+            entry = sym #entry_ident,
         );
 
-        #[no_mangle]
-        #function
+        #entry_functions
+
+        #simulated_veneer
+
+        #signature_assertions
+
+        #metadata
     }
 }
 
@@ -52,15 +140,18 @@ mod tests {
 
     use super::*;
 
-    #[test]
-    fn test_name() {
-        let input_text = include_str!("../test-sources/secure_callable_simple_test.txt");
-        let output_text = include_str!("../test-sources/secure_callable_simple_result.txt");
-
+    fn test_expansion(input_text: &str, output_text: &str) {
         let attr: String = input_text.lines().take(1).collect();
         let item: String = input_text.lines().skip(1).collect();
 
-        let attr_stream = TokenStream::from_str(&attr).unwrap();
+        // The first line is the attribute, of which the macro only gets the arguments
+        let attr_stream = match syn::parse::Parser::parse_str(syn::Attribute::parse_outer, &attr)
+            .unwrap()[0]
+            .meta
+        {
+            syn::Meta::List(ref list) => list.tokens.clone(),
+            _ => TokenStream::new(),
+        };
         let item_stream = TokenStream::from_str(&item).unwrap();
 
         let output = secure_callable(attr_stream, item_stream);
@@ -71,4 +162,36 @@ mod tests {
             output_text.replace("\r\n", "\n")
         );
     }
+
+    #[test]
+    fn test_name() {
+        test_expansion(
+            include_str!("../test-sources/secure_callable_simple_test.txt"),
+            include_str!("../test-sources/secure_callable_simple_result.txt"),
+        );
+    }
+
+    #[test]
+    fn test_rust_abi() {
+        test_expansion(
+            include_str!("../test-sources/secure_callable_rust_abi_test.txt"),
+            include_str!("../test-sources/secure_callable_rust_abi_result.txt"),
+        );
+    }
+
+    #[test]
+    fn test_unsupported_types() {
+        test_expansion(
+            include_str!("../test-sources/secure_callable_unsupported_types_test.txt"),
+            include_str!("../test-sources/secure_callable_unsupported_types_result.txt"),
+        );
+    }
+
+    #[test]
+    fn test_attributes() {
+        test_expansion(
+            include_str!("../test-sources/secure_callable_attributes_test.txt"),
+            include_str!("../test-sources/secure_callable_attributes_result.txt"),
+        );
+    }
 }
@@ -0,0 +1,157 @@
+//! Reading the veneers out of a linked secure image.
+//!
+//! This is the equivalent of the import library that `--cmse-implib` creates. The nonsecure image
+//! can be linked against the veneer addresses directly instead of searching for them at runtime.
+
+use anyhow::Context;
+use object::{Object, ObjectSection, ObjectSymbol};
+use std::path::Path;
+
+/// The output section the linker script of the secure runtime puts the `.nsc_veneers` table in
+const VENEER_TABLE_SECTION: &str = ".nsc_vectors";
+
+/// The `SG` instruction every veneer starts with, as a little endian word
+const SG_INSTRUCTION: u32 = 0xE97F_E97F;
+
+/// A veneer as found in the `.nsc_veneers` section of the secure image
+pub(crate) struct SecureImageVeneer {
+    /// The symbol name, e.g. `DOUBLE_3254733394_veneer`
+    pub(crate) name: String,
+    /// The address of the `SG` instruction
+    pub(crate) address: u64,
+    /// The id of the veneer
+    pub(crate) id: u32,
+    /// The hash of the signature of the function
+    pub(crate) signature: u32,
+}
+
+/// Reads all function veneers from the secure ELF file
+pub(crate) fn read_veneers<P: AsRef<Path>>(
+    secure_elf_path: P,
+) -> Result<Vec<SecureImageVeneer>, anyhow::Error> {
+    let data = std::fs::read(secure_elf_path.as_ref()).with_context(|| {
+        format!(
+            "Could not read the secure image at {}",
+            secure_elf_path.as_ref().display()
+        )
+    })?;
+    let file = object::File::parse(&*data)?;
+
+    // Other symbols may end with `_veneer` as well, so only the ones in the table are veneers
+    let table = file
+        .section_by_name(VENEER_TABLE_SECTION)
+        .with_context(|| {
+            format!(
+                "The secure image at {} has no `{VENEER_TABLE_SECTION}` section with the veneers",
+                secure_elf_path.as_ref().display()
+            )
+        })?;
+
+    let mut veneers = Vec::new();
+
+    for symbol in file.symbols() {
+        let name = symbol.name()?;
+
+        // The searcher is the only veneer that isn't a function
+        if !symbol.is_global() || !name.ends_with("_veneer") || name == "searcher_veneer" {
+            continue;
+        }
+
+        if symbol.section_index() != Some(table.index()) {
+            continue;
+        }
+
+        // Clear the thumb bit
+        let address = symbol.address() & !1;
+
+        // A veneer is the SG instruction, the branch instruction, the id and the signature hash
+        let words = table
+            .data_range(address, 16)?
+            .with_context(|| format!("The veneer `{name}` is outside of its section"))?;
+        let word =
+            |index: usize| u32::from_le_bytes(words[index * 4..index * 4 + 4].try_into().unwrap());
+
+        if word(0) != SG_INSTRUCTION {
+            anyhow::bail!(
+                "The veneer `{name}` at {address:#010x} doesn't start with an SG instruction"
+            );
+        }
+
+        veneers.push(SecureImageVeneer {
+            name: name.to_string(),
+            address,
+            id: word(2),
+            signature: word(3),
+        });
+    }
+
+    Ok(veneers)
+}
+
+/// Creates a linker script that provides the address of every veneer as a symbol
+pub(crate) fn veneer_linker_script(veneers: &[SecureImageVeneer]) -> String {
+    let mut script = String::from("/* Generated by trustzone-m-tools from the secure image */\n");
+
+    for veneer in veneers {
+        script.push_str(&format!(
+            "PROVIDE({} = {:#010x});\n",
+            veneer.name, veneer.address
+        ));
+    }
+
+    script
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The image is linked from `secure_image.rs` with the `secure_image.x` linker script. It has the
+    /// veneers of `bindings_secure_image` in the table and a function called `fake_veneer` outside
+    /// of it.
+    const SECURE_IMAGE: &str =
+        concat!(env!("CARGO_MANIFEST_DIR"), "/test-sources/secure_image.elf");
+
+    #[test]
+    fn test_read_veneers() {
+        let veneers = read_veneers(SECURE_IMAGE).unwrap();
+
+        pretty_assertions::assert_eq!(
+            veneers
+                .iter()
+                .map(|veneer| (
+                    veneer.name.as_str(),
+                    veneer.address,
+                    veneer.id,
+                    veneer.signature
+                ))
+                .collect::<Vec<_>>(),
+            [
+                ("HALVE_934609468_veneer", 0x10008, 0x37b5023c, 0xdc44ddf8),
+                ("DOUBLE_2737647201_veneer", 0x10018, 0xa32d2e61, 0xdc44ddf8),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_veneers_without_sg() {
+        // Overwrite the SG instruction of the last veneer
+        let mut data = std::fs::read(SECURE_IMAGE).unwrap();
+        let sg = SG_INSTRUCTION.to_le_bytes();
+        let position = data.windows(4).rposition(|window| window == sg).unwrap();
+        data[position..position + 4].copy_from_slice(&[0; 4]);
+
+        let path = std::env::temp_dir().join(format!(
+            "trustzone-m-tools-{}-secure_image.elf",
+            std::process::id()
+        ));
+        std::fs::write(&path, data).unwrap();
+        let result = read_veneers(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "The veneer `DOUBLE_2737647201_veneer` at 0x00010018 doesn't start with an SG instruction"
+        );
+    }
+}
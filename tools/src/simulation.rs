@@ -0,0 +1,38 @@
+//! Calls between the two worlds on the host, so both apps can be tested in one test binary.
+//!
+//! On the host there are no veneers. Instead, the macros export the address of the entry function
+//! of every item in a static with a symbol that has the path and the signature hash of the item.
+//! The bindings read that static and call the entry function directly. An item that the other app
+//! doesn't export or that has another signature is a link error.
+
+use proc_macro2::TokenStream;
+
+/// The start of the symbols of the simulated veneers
+const SYMBOL_PREFIX: &str = "__trustzone_simulation::";
+
+/// The symbol of the simulated veneer of the item with the given path in the given crate
+pub(crate) fn simulation_symbol(crate_name: &str, item_path: &str, signature_hash: u32) -> String {
+    format!("{SYMBOL_PREFIX}{crate_name}::{item_path}::{signature_hash}")
+}
+
+/// Creates the static with the address of the entry function, which the bindings of the other app
+/// read on the host
+pub(crate) fn simulated_veneer(
+    export_name: &str,
+    signature_hash: u32,
+    entry_ident: &syn::Ident,
+    runtime: &TokenStream,
+) -> TokenStream {
+    // The module path starts with the crate name, like the symbol the bindings expect
+    let symbol_end = format!("::{export_name}::{signature_hash}");
+
+    quote::quote! {
+        #[cfg(not(target_arch = "arm"))]
+        const _: () = {
+            #[used]
+            #[export_name = concat!(#SYMBOL_PREFIX, module_path!(), #symbol_end)]
+            static SIMULATED_VENEER: #runtime::SimulatedVeneer =
+                #runtime::SimulatedVeneer(#entry_ident as *const ());
+        };
+    }
+}
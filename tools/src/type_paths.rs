@@ -0,0 +1,303 @@
+//! Making the types in the signatures of the exported items nameable in the bindings.
+//!
+//! The bindings are in the other app, where the names the scanned module imports don't exist.
+//! A type that is imported by name is replaced by its full path, so it resolves as long as the
+//! other app depends on the same crate. A type that is defined in the scanned crate itself can't be
+//! named by the other app at all, so it's rejected.
+
+use crate::cfg::CfgSet;
+use std::collections::HashMap;
+use syn::{spanned::Spanned, visit_mut::VisitMut};
+
+/// The types that can be used without importing them
+const PRELUDE_TYPES: &[&str] = &[
+    "bool", "char", "str", "u8", "u16", "u32", "u64", "u128", "usize", "i8", "i16", "i32", "i64",
+    "i128", "isize", "f32", "f64", "Option", "Result",
+];
+
+/// The names of a module of the scanned crate
+pub(crate) struct ModuleScope {
+    /// The names that are imported by name, with the path they're imported from
+    imports: HashMap<String, syn::Path>,
+    /// The names of the items that are defined in the module, including the child modules
+    definitions: Vec<String>,
+}
+
+impl ModuleScope {
+    /// Collects the names of the items of the module that are enabled in the cfg
+    pub(crate) fn new(items: &[syn::Item], cfg: &CfgSet) -> syn::Result<Self> {
+        let mut scope = Self {
+            imports: HashMap::new(),
+            definitions: Vec::new(),
+        };
+
+        for item in items {
+            let (attrs, ident) = match item {
+                syn::Item::Use(item) => {
+                    if cfg.is_enabled(&item.attrs)? {
+                        let prefix = syn::Path {
+                            leading_colon: item.leading_colon,
+                            segments: Default::default(),
+                        };
+                        scope.add_use_tree(&item.tree, prefix);
+                    }
+                    continue;
+                }
+                syn::Item::Struct(item) => (&item.attrs, &item.ident),
+                syn::Item::Enum(item) => (&item.attrs, &item.ident),
+                syn::Item::Union(item) => (&item.attrs, &item.ident),
+                syn::Item::Type(item) => (&item.attrs, &item.ident),
+                syn::Item::Trait(item) => (&item.attrs, &item.ident),
+                syn::Item::Mod(item) => (&item.attrs, &item.ident),
+                _ => continue,
+            };
+
+            if cfg.is_enabled(attrs)? {
+                scope.definitions.push(ident.to_string());
+            }
+        }
+
+        Ok(scope)
+    }
+
+    fn add_use_tree(&mut self, tree: &syn::UseTree, mut prefix: syn::Path) {
+        match tree {
+            syn::UseTree::Path(path) => {
+                prefix.segments.push(path.ident.clone().into());
+                self.add_use_tree(&path.tree, prefix);
+            }
+            syn::UseTree::Name(name) => {
+                // `use module::{self}` imports the module itself
+                let name = if name.ident == "self" {
+                    match prefix.segments.last() {
+                        Some(segment) => segment.ident.clone(),
+                        None => return,
+                    }
+                } else {
+                    prefix.segments.push(name.ident.clone().into());
+                    name.ident.clone()
+                };
+                self.imports.insert(name.to_string(), prefix);
+            }
+            syn::UseTree::Rename(rename) => {
+                if rename.ident != "self" {
+                    prefix.segments.push(rename.ident.clone().into());
+                }
+                self.imports.insert(rename.rename.to_string(), prefix);
+            }
+            syn::UseTree::Group(group) => {
+                for tree in group.items.iter() {
+                    self.add_use_tree(tree, prefix.clone());
+                }
+            }
+            // The names of a glob import aren't known
+            syn::UseTree::Glob(_) => {}
+        }
+    }
+
+    /// Replaces the paths of the types that are imported by name with their full paths
+    pub(crate) fn qualify_signature(&self, signature: &mut syn::Signature) -> syn::Result<()> {
+        let mut qualifier = Qualifier {
+            scope: self,
+            error: None,
+        };
+        for input in signature.inputs.iter_mut() {
+            if let syn::FnArg::Typed(input) = input {
+                qualifier.visit_type_mut(&mut input.ty);
+            }
+        }
+        if let syn::ReturnType::Type(_, ty) = &mut signature.output {
+            qualifier.visit_type_mut(ty);
+        }
+
+        qualifier.error.map_or(Ok(()), Err)
+    }
+
+    /// Replaces the paths of the types that are imported by name with their full paths
+    pub(crate) fn qualify_type(&self, ty: &mut syn::Type) -> syn::Result<()> {
+        let mut qualifier = Qualifier {
+            scope: self,
+            error: None,
+        };
+        qualifier.visit_type_mut(ty);
+
+        qualifier.error.map_or(Ok(()), Err)
+    }
+
+    /// The path the other app can name the path with
+    fn qualify(&self, path: &syn::Path) -> syn::Result<Option<syn::Path>> {
+        let local_error = |name: &dyn std::fmt::Display| {
+            syn::Error::new(
+                path.span(),
+                format!(
+                    "`{name}` is defined in this crate, so the bindings in the other app can't name it. \
+                    Move it to a crate both apps depend on."
+                ),
+            )
+        };
+
+        if path.leading_colon.is_some() {
+            return Ok(None);
+        }
+        let first = &path.segments[0].ident;
+
+        if first == "crate" || first == "self" || first == "super" {
+            let path = quote::ToTokens::to_token_stream(path).to_string();
+            return Err(local_error(&path.replace(' ', "")));
+        }
+        if self
+            .definitions
+            .iter()
+            .any(|definition| first == definition)
+        {
+            return Err(local_error(first));
+        }
+
+        match self.imports.get(&first.to_string()) {
+            Some(import) => {
+                let import_first = &import.segments[0].ident;
+                if import.leading_colon.is_none()
+                    && (import_first == "crate"
+                        || import_first == "self"
+                        || import_first == "super"
+                        || self
+                            .definitions
+                            .iter()
+                            .any(|definition| import_first == definition))
+                {
+                    return Err(local_error(first));
+                }
+
+                // The first segment of the path is the last one of the import, but it keeps its
+                // generic arguments
+                let mut segments = path.segments.iter().cloned();
+                let mut first_segment = segments.next().unwrap();
+                first_segment.ident = import.segments.last().unwrap().ident.clone();
+
+                let mut qualified = import.clone();
+                qualified.leading_colon = Some(Default::default());
+                qualified.segments.pop();
+                qualified.segments.push(first_segment);
+                qualified.segments.extend(segments);
+                Ok(Some(qualified))
+            }
+            // A path like `core::ffi::c_int` starts with the name of a crate
+            None if path.segments.len() > 1 => Ok(None),
+            None if PRELUDE_TYPES.iter().any(|name| first == name) => Ok(None),
+            None => Err(syn::Error::new(
+                path.span(),
+                format!(
+                    "The bindings generator can't find where `{first}` comes from. \
+                    Import it by name or use its full path."
+                ),
+            )),
+        }
+    }
+}
+
+/// Qualifies the paths of a type, and keeps the first error
+struct Qualifier<'a> {
+    scope: &'a ModuleScope,
+    error: Option<syn::Error>,
+}
+
+impl VisitMut for Qualifier<'_> {
+    fn visit_type_path_mut(&mut self, type_path: &mut syn::TypePath) {
+        // The generic arguments are qualified first, because they are moved into the new path
+        syn::visit_mut::visit_type_path_mut(self, type_path);
+
+        // `<T as Trait>::Assoc` isn't rewritten
+        if type_path.qself.is_some() || self.error.is_some() {
+            return;
+        }
+
+        match self.scope.qualify(&type_path.path) {
+            Ok(Some(path)) => type_path.path = path,
+            Ok(None) => {}
+            Err(e) => self.error = Some(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quote::ToTokens;
+
+    fn qualify(module: &str, signature: &str) -> Result<String, String> {
+        let file = syn::parse_file(module).unwrap();
+        let scope = ModuleScope::new(&file.items, &CfgSet::new()).unwrap();
+        let mut signature = syn::parse_str::<syn::Signature>(signature).unwrap();
+
+        match scope.qualify_signature(&mut signature) {
+            Ok(()) => Ok(signature.to_token_stream().to_string()),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_imported_types() {
+        let module = "
+            use shared_types::{RadioStatus, config::Mode as RadioMode};
+            use core::num;
+        ";
+
+        assert_eq!(
+            qualify(
+                module,
+                "fn set(status: RadioStatus, mode: Option<RadioMode>, count: num::NonZeroU32) -> u32"
+            )
+            .unwrap(),
+            "fn set (status : :: shared_types :: RadioStatus , mode : Option < :: shared_types :: config :: Mode > , count : :: core :: num :: NonZeroU32) -> u32"
+        );
+    }
+
+    #[test]
+    fn test_full_paths() {
+        assert_eq!(
+            qualify(
+                "",
+                "fn set(level: core::ffi::c_int, status: &shared_types::RadioStatus)"
+            )
+            .unwrap(),
+            "fn set (level : core :: ffi :: c_int , status : & shared_types :: RadioStatus)"
+        );
+    }
+
+    #[test]
+    fn test_local_types() {
+        let module = "
+            mod types;
+            use crate::config::Mode;
+            use types::Level;
+
+            #[repr(C)]
+            pub struct RadioStatus {
+                pub rssi: i32,
+            }
+        ";
+
+        for signature in [
+            "fn set(status: RadioStatus)",
+            "fn set(mode: Mode)",
+            "fn set(level: Level)",
+            "fn set(level: types::Level)",
+            "fn set(level: crate::types::Level)",
+        ] {
+            assert!(
+                qualify(module, signature)
+                    .unwrap_err()
+                    .contains("Move it to a crate both apps depend on"),
+                "{signature}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_unknown_types() {
+        assert_eq!(
+            qualify("use shared_types::*;", "fn set(status: RadioStatus)").unwrap_err(),
+            "The bindings generator can't find where `RadioStatus` comes from. Import it by name or use its full path."
+        );
+    }
+}
@@ -0,0 +1,9 @@
+use trustzone_m_macros::nonsecure_callable;
+
+// The file of a module with a path is treated like a mod.rs file
+mod pages;
+
+#[nonsecure_callable]
+pub fn erase(page: u32) -> bool {
+    pages::erase(page)
+}
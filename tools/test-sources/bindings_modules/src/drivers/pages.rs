@@ -0,0 +1,6 @@
+use trustzone_m_macros::nonsecure_callable;
+
+#[nonsecure_callable]
+pub fn erase(page: u32) -> bool {
+    page < 256
+}
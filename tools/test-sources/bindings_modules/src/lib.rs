@@ -0,0 +1,25 @@
+#![no_std]
+
+use trustzone_m_macros::{nonsecure_callable, nonsecure_shared};
+
+mod storage;
+
+#[path = "drivers/flash_driver.rs"]
+pub mod flash;
+
+#[cfg(feature = "radio")]
+pub mod radio {
+    use trustzone_m_macros::nonsecure_callable;
+
+    #[nonsecure_callable]
+    pub fn send(packet: u32) {}
+}
+
+/// Starts the secure services
+#[nonsecure_callable]
+pub extern "C" fn init() -> bool {
+    true
+}
+
+#[nonsecure_shared]
+pub static mut COUNTER: u32 = 0;
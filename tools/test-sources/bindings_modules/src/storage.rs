@@ -0,0 +1,15 @@
+use core::num::NonZeroU32;
+use trustzone_m_macros::nonsecure_callable;
+
+#[nonsecure_callable]
+pub fn read(key: u32) -> Option<NonZeroU32> {
+    NonZeroU32::new(key)
+}
+
+pub mod cache {
+    // An inline module finds its file modules in the directory with its name
+    mod backend;
+
+    #[nonsecure_callable]
+    pub fn clear() {}
+}
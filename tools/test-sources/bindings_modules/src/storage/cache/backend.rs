@@ -0,0 +1,4 @@
+use trustzone_m_macros::nonsecure_callable;
+
+#[nonsecure_callable(name = "flush_backend")]
+pub fn flush() {}
@@ -0,0 +1,13 @@
+#![no_std]
+
+use trustzone_m_macros::nonsecure_callable;
+
+#[nonsecure_callable]
+pub extern "C" fn double(x: u32) -> u32 {
+    x * 2
+}
+
+#[nonsecure_callable]
+pub extern "C" fn halve(x: u32) -> u32 {
+    x / 2
+}
@@ -0,0 +1,186 @@
+/* Generated by trustzone-m-tools. Do not edit. */
+#include "trustzone_bindings.h"
+
+/* The searcher veneer is the first veneer of the `.nsc_veneers` section of the secure image */
+extern const uint32_t _NSC_VENEERS;
+
+typedef const uint32_t *(*trustzone_searcher_t)(uint32_t id, uint32_t signature);
+
+/* Finds the veneer with the given id and signature. A signature of 0 matches any signature. */
+static const uint32_t *find_nsc_veneer(uint32_t id, uint32_t signature)
+{
+    /* Don't forget to set the thumb bit */
+    trustzone_searcher_t searcher = (trustzone_searcher_t)((uintptr_t)&_NSC_VENEERS | 1);
+    return searcher(id, signature);
+}
+
+/* Looks up the veneer and caches its address with the thumb bit set */
+static trustzone_veneer_error_t find_veneer(uintptr_t *cache, uint32_t id, uint32_t signature)
+{
+    if (*cache != 0) {
+        return TRUSTZONE_VENEER_OK;
+    }
+
+    const uint32_t *veneer = find_nsc_veneer(id, signature);
+    if (veneer == NULL) {
+        /* Find out why we couldn't find it */
+        if (find_nsc_veneer(id, 0) != NULL) {
+            return TRUSTZONE_VENEER_SIGNATURE_MISMATCH;
+        }
+        return TRUSTZONE_VENEER_NOT_FOUND;
+    }
+
+    *cache = (uintptr_t)veneer | 1;
+    return TRUSTZONE_VENEER_OK;
+}
+
+__attribute__((weak)) void trustzone_veneer_error(const char *path, trustzone_veneer_error_t error)
+{
+    (void)path;
+    (void)error;
+    for (;;) {
+    }
+}
+
+__attribute__((weak)) void trustzone_initialize_ns_data(void)
+{
+}
+
+/* The secure image calls the first veneer of the `.ns_veneers` section to initialize this image */
+__asm__(
+    ".pushsection .ns_veneers.initializer, \"ax\"\n"
+    ".global initializer_veneer\n"
+    ".thumb_func\n"
+    "initializer_veneer:\n"
+    "    b.w trustzone_initialize_ns_data\n"
+    "    .4byte 0\n"
+    "    .4byte 0\n"
+    ".popsection\n"
+);
+
+/* init */
+static uintptr_t init_veneer;
+
+bool init_is_available(void)
+{
+    return find_veneer(&init_veneer, INIT_ID, INIT_SIGNATURE) == TRUSTZONE_VENEER_OK;
+}
+
+/* Checked by the secure image before it starts this app, as long as the linker keeps it */
+static const uint32_t init_import[2] __attribute__((section(".trustzone_imports.1234567890"))) = {
+    INIT_ID, INIT_SIGNATURE
+};
+
+void init(void)
+{
+    trustzone_veneer_error_t error = find_veneer(&init_veneer, INIT_ID, INIT_SIGNATURE);
+    if (error != TRUSTZONE_VENEER_OK) {
+        trustzone_veneer_error("init", error);
+    }
+    (void)*(const volatile uint32_t *)init_import;
+
+    ((void (*)(void))init_veneer)();
+}
+
+/* storage::read */
+static uintptr_t storage_read_veneer;
+
+bool storage_read_is_available(void)
+{
+    return find_veneer(&storage_read_veneer, STORAGE_READ_ID, STORAGE_READ_SIGNATURE) == TRUSTZONE_VENEER_OK;
+}
+
+/* Checked by the secure image before it starts this app, as long as the linker keeps it */
+static const uint32_t storage_read_import[2] __attribute__((section(".trustzone_imports.305419896"))) = {
+    STORAGE_READ_ID, STORAGE_READ_SIGNATURE
+};
+
+uint32_t storage_read(uint32_t key, uint8_t *buffer, bool erase)
+{
+    trustzone_veneer_error_t error = find_veneer(&storage_read_veneer, STORAGE_READ_ID, STORAGE_READ_SIGNATURE);
+    if (error != TRUSTZONE_VENEER_OK) {
+        trustzone_veneer_error("storage::read", error);
+    }
+    (void)*(const volatile uint32_t *)storage_read_import;
+
+    return ((uint32_t (*)(uint32_t, uint8_t *, bool))storage_read_veneer)(key, buffer, erase);
+}
+
+/* storage::lookup */
+static uintptr_t storage_lookup_veneer;
+
+bool storage_lookup_is_available(void)
+{
+    return find_veneer(&storage_lookup_veneer, STORAGE_LOOKUP_ID, STORAGE_LOOKUP_SIGNATURE) == TRUSTZONE_VENEER_OK;
+}
+
+/* Checked by the secure image before it starts this app, as long as the linker keeps it */
+static const uint32_t storage_lookup_import[2] __attribute__((section(".trustzone_imports.4"))) = {
+    STORAGE_LOOKUP_ID, STORAGE_LOOKUP_SIGNATURE
+};
+
+void *storage_lookup(const char *const *names, const Config *config)
+{
+    trustzone_veneer_error_t error = find_veneer(&storage_lookup_veneer, STORAGE_LOOKUP_ID, STORAGE_LOOKUP_SIGNATURE);
+    if (error != TRUSTZONE_VENEER_OK) {
+        trustzone_veneer_error("storage::lookup", error);
+    }
+    (void)*(const volatile uint32_t *)storage_lookup_import;
+
+    return ((void *(*)(const char *const *, const Config *))storage_lookup_veneer)(names, config);
+}
+
+/* COUNTERS */
+static uintptr_t counters_veneer;
+
+bool counters_is_available(void)
+{
+    return find_veneer(&counters_veneer, COUNTERS_ID, COUNTERS_SIGNATURE) == TRUSTZONE_VENEER_OK;
+}
+
+/* Checked by the secure image before it starts this app, as long as the linker keeps it */
+static const uint32_t counters_import[2] __attribute__((section(".trustzone_imports.5"))) = {
+    COUNTERS_ID, COUNTERS_SIGNATURE
+};
+
+const volatile uint32_t *counters(void)
+{
+    static const volatile uint32_t *address;
+
+    if (address == NULL) {
+        trustzone_veneer_error_t error = find_veneer(&counters_veneer, COUNTERS_ID, COUNTERS_SIGNATURE);
+        if (error != TRUSTZONE_VENEER_OK) {
+            trustzone_veneer_error("COUNTERS", error);
+        }
+        (void)*(const volatile uint32_t *)counters_import;
+
+        /* The veneer of a static returns its address */
+        address = ((const volatile uint32_t *(*)(void))counters_veneer)();
+    }
+
+    return address;
+}
+
+/* events::on_event */
+__asm__(
+    ".pushsection .ns_veneers.13735928559, \"ax\"\n"
+    ".balign 4\n"
+    ".global ON_EVENT_3735928559_veneer\n"
+    ".thumb_func\n"
+    "ON_EVENT_3735928559_veneer:\n"
+    "    b.w events_on_event\n"
+    "    .4byte 0xdeadbeef\n"
+    "    .4byte 0x00000037\n"
+    "    .pushsection .ns_veneers_checksum, \"\", %nobits\n"
+    "    .space 17147\n"
+    "    .popsection\n"
+    ".popsection\n"
+);
+
+void trustzone_bindings_resolve_all(void)
+{
+    (void)find_veneer(&init_veneer, INIT_ID, INIT_SIGNATURE);
+    (void)find_veneer(&storage_read_veneer, STORAGE_READ_ID, STORAGE_READ_SIGNATURE);
+    (void)find_veneer(&storage_lookup_veneer, STORAGE_LOOKUP_ID, STORAGE_LOOKUP_SIGNATURE);
+    (void)find_veneer(&counters_veneer, COUNTERS_ID, COUNTERS_SIGNATURE);
+}
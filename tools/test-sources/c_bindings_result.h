@@ -0,0 +1,78 @@
+/* Generated by trustzone-m-tools. Do not edit. */
+#ifndef TRUSTZONE_BINDINGS_H
+#define TRUSTZONE_BINDINGS_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+/* Types that aren't C primitives keep their Rust name and have to be declared before this header is included. */
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#ifndef TRUSTZONE_VENEER_ERROR_DEFINED
+#define TRUSTZONE_VENEER_ERROR_DEFINED
+/* The reason a veneer couldn't be used */
+typedef enum {
+    TRUSTZONE_VENEER_OK = 0,
+    /* The secure image doesn't export the function */
+    TRUSTZONE_VENEER_NOT_FOUND,
+    /* The secure image exports the function, but with a different signature */
+    TRUSTZONE_VENEER_SIGNATURE_MISMATCH,
+} trustzone_veneer_error_t;
+#endif
+
+/* Called when a function of the secure image is called, but its veneer can't be used.
+ * The default implementation loops forever. */
+__attribute__((noreturn)) void trustzone_veneer_error(const char *path, trustzone_veneer_error_t error);
+
+/* Called by the secure image before it starts this image, through the first veneer of the `.ns_veneers` section.
+ * The default implementation does nothing, because the startup code of a C app initializes the RAM itself. */
+void trustzone_initialize_ns_data(void);
+
+/* Looks up all veneers now instead of at the first call of every function.
+ * Veneers that can't be found are skipped. Their functions report the error when they're called. */
+void trustzone_bindings_resolve_all(void);
+
+/* init */
+#define INIT_ID 0x499602d2u
+#define INIT_SIGNATURE 0x0000000bu
+void init(void);
+/* Tells if the secure image exports `init` with the expected signature */
+bool init_is_available(void);
+
+/* storage::read */
+#define STORAGE_READ_ID 0x12345678u
+#define STORAGE_READ_SIGNATURE 0x00000016u
+uint32_t storage_read(uint32_t key, uint8_t *buffer, bool erase);
+/* Tells if the secure image exports `storage::read` with the expected signature */
+bool storage_read_is_available(void);
+
+/* storage::lookup */
+#define STORAGE_LOOKUP_ID 0x00000004u
+#define STORAGE_LOOKUP_SIGNATURE 0x00000021u
+void *storage_lookup(const char *const *names, const Config *config);
+/* Tells if the secure image exports `storage::lookup` with the expected signature */
+bool storage_lookup_is_available(void);
+
+/* COUNTERS */
+#define COUNTERS_ID 0x00000005u
+#define COUNTERS_SIGNATURE 0x0000002cu
+/* Returns the address of the shared static `COUNTERS` */
+const volatile uint32_t *counters(void);
+/* Tells if the secure image exports `COUNTERS` with the expected type */
+bool counters_is_available(void);
+
+/* events::on_event */
+#define EVENTS_ON_EVENT_ID 0xdeadbeefu
+#define EVENTS_ON_EVENT_SIGNATURE 0x00000037u
+/* Implemented by this app and called by the secure image */
+int32_t events_on_event(uint32_t event, uint8_t *data);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* TRUSTZONE_BINDINGS_H */
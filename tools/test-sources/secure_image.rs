@@ -0,0 +1,47 @@
+#![no_std]
+#![no_main]
+
+core::arch::global_asm!(
+    ".section .nsc_veneers.searcher, \"ax\"",
+    ".balign 4",
+    ".global searcher_veneer",
+    ".thumb_func",
+    "searcher_veneer:",
+    "SG",
+    "B.w entry",
+    // The veneers of `double` and `halve` of `bindings_secure_image`, sorted by id
+    ".section .nsc_veneers.0934609468, \"ax\"",
+    ".balign 4",
+    ".global HALVE_934609468_veneer",
+    ".thumb_func",
+    "HALVE_934609468_veneer:",
+    "SG",
+    "B.w entry",
+    ".4byte 0x37b5023c",
+    ".4byte 0xdc44ddf8",
+    ".section .nsc_veneers.2737647201, \"ax\"",
+    ".balign 4",
+    ".global DOUBLE_2737647201_veneer",
+    ".thumb_func",
+    "DOUBLE_2737647201_veneer:",
+    "SG",
+    "B.w entry",
+    ".4byte 0xa32d2e61",
+    ".4byte 0xdc44ddf8",
+    // A function outside of the veneer table that happens to have a name like a veneer
+    ".section .text.fake_veneer, \"ax\"",
+    ".balign 4",
+    ".global fake_veneer",
+    ".thumb_func",
+    "fake_veneer:",
+    "entry:",
+    "BX lr",
+    ".4byte 3",
+    ".4byte 0",
+    ".4byte 0",
+);
+
+#[panic_handler]
+fn panic(_: &core::panic::PanicInfo) -> ! {
+    loop {}
+}
@@ -0,0 +1,16 @@
+MEMORY
+{
+  FLASH : ORIGIN = 0x00000000, LENGTH = 64K
+  NSC_FLASH : ORIGIN = 0x00010000, LENGTH = 4K
+}
+ENTRY(searcher_veneer)
+SECTIONS
+{
+  .text : { *(.text .text.*) } > FLASH
+  .nsc_vectors ORIGIN(NSC_FLASH) :
+  {
+    KEEP(*(.nsc_veneers.searcher));
+    KEEP(*(SORT_BY_NAME(.nsc_veneers.*)));
+  } > NSC_FLASH = 0
+  /DISCARD/ : { *(.ARM.exidx .ARM.exidx.*) }
+}